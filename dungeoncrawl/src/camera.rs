@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
 use crate::prelude::*;
 
 // 用于能够在玩家角色移动时更新摄像机的参数，以玩家角色为中心。
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Camera {
     pub left_x: i32,
     pub right_x: i32,
@@ -9,19 +11,48 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn new(player_position: Point) -> Self {
-        Self {
-            left_x: player_position.x - DISPLAY_WIDTH / 2,
-            right_x: player_position.x + DISPLAY_WIDTH / 2,
-            top_y: player_position.y - DISPLAY_HEIGHT / 2,
-            bottom_y: player_position.y + DISPLAY_HEIGHT / 2,
-        }
+    pub fn new(player_position: Point, map: &Map) -> Self {
+        let mut camera = Self {
+            left_x: 0,
+            right_x: 0,
+            top_y: 0,
+            bottom_y: 0,
+        };
+        camera.on_player_move(player_position, map);
+        camera
     }
 
-    pub fn on_player_move(&mut self, player_position: Point) {
-        self.left_x = player_position.x - DISPLAY_WIDTH / 2;
-        self.right_x = player_position.x + DISPLAY_WIDTH / 2;
-        self.top_y = player_position.y - DISPLAY_HEIGHT / 2;
-        self.bottom_y = player_position.y + DISPLAY_HEIGHT / 2;
+    // 到达地图边缘时摄像机停止移动，不显示地图之外的区域
+    pub fn on_player_move(&mut self, player_position: Point, map: &Map) {
+        self.left_x = view_start(player_position.x, DISPLAY_WIDTH, map.width);
+        self.right_x = self.left_x + DISPLAY_WIDTH;
+        self.top_y = view_start(player_position.y, DISPLAY_HEIGHT, map.height);
+        self.bottom_y = self.top_y + DISPLAY_HEIGHT;
+    }
+}
+
+// 计算一个方向上视界的起点：尽量让玩家角色居中，但不超出地图；地图比视界小时让地图居中
+fn view_start(player: i32, view: i32, map_size: i32) -> i32 {
+    if map_size <= view {
+        (map_size - view) / 2
+    } else {
+        (player - view / 2).clamp(0, map_size - view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_stops_at_map_edges() {
+        let map = Map::new(100, 60);
+        let camera = Camera::new(Point::new(2, 58), &map);
+        assert_eq!((camera.left_x, camera.top_y), (0, 60 - DISPLAY_HEIGHT));
+        let camera = Camera::new(Point::new(50, 30), &map);
+        assert_eq!((camera.left_x, camera.right_x), (50 - DISPLAY_WIDTH / 2, 50 + DISPLAY_WIDTH / 2));
+        // 比视界小的地图显示在屏幕中央
+        let camera = Camera::new(Point::new(5, 5), &Map::new(DISPLAY_WIDTH - 10, DISPLAY_HEIGHT));
+        assert_eq!((camera.left_x, camera.top_y), (-5, 0));
     }
 }
\ No newline at end of file
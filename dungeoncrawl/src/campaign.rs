@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs::File;
+use ron::de::from_reader;
+use serde::Deserialize;
+use crate::prelude::*;
+
+// 战役中的一层
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename = "Level")]
+pub struct CampaignLevel {
+    // 可以使用的地图生成流水线（resources/mapgen.ron中的名称）以及对应的权重
+    pub chains: HashMap<String, i32>,
+    // 可以使用的地图主题（resources/themes.ron中的名称）以及对应的权重
+    pub themes: HashMap<String, i32>,
+    // 怪物和物品的数量，出生点不够时按比例减少
+    pub monsters: usize,
+    pub items: usize,
+    // 一定会出现在这一层的怪物（resources/template.ron中的名称），放在离玩家角色起点最远的出生点上
+    #[serde(default)]
+    pub bosses: Vec<String>,
+}
+
+impl CampaignLevel {
+    // 这一层需要的出生点数量：怪物、物品和首领各占一个
+    pub fn spawn_points(&self) -> usize {
+        self.monsters + self.items + self.bosses.len()
+    }
+}
+
+/*
+ * 战役：地下城的层数、每一层的地图和怪物设置以及胜利条件，在resources/campaign.ron中定义。
+ * 最后一层没有向下的楼梯，尽头放着胜利物品，捡起之后游戏胜利。
+ */
+#[derive(Clone, Debug, Deserialize)]
+pub struct Campaign {
+    pub name: String,
+    // 开始游戏时的欢迎消息
+    pub intro: String,
+    // 胜利物品（resources/template.ron中的名称）
+    pub victory_item: String,
+    // 守护胜利物品的首领（最后一层的首领之一），消灭它之前无法拿起胜利物品
+    #[serde(default)]
+    pub guardian: Option<String>,
+    // 胜利画面和游戏结束画面上的文字
+    pub victory_text: Vec<String>,
+    pub defeat_text: Vec<String>,
+    // 每一层的设置，从第一层开始
+    pub levels: Vec<CampaignLevel>,
+}
+
+impl Campaign {
+    // 加载战役配置，启动时只加载和检查一次，用到的模板、流水线和主题由调用者传入
+    pub fn load(templates: &Templates, mapgen: &MapGen, themes: &Themes) -> Self {
+        let file = File::open("resources/campaign.ron").expect("Failed opening file");
+        let campaign: Self = from_reader(file).expect("Unable to load campaign");
+        campaign.validate(templates, mapgen, themes).expect("Invalid campaign");
+        campaign
+    }
+
+    // 地下城的某一层，超出层数时使用最后一层的设置
+    pub fn level(&self, depth: u32) -> &CampaignLevel {
+        &self.levels[usize::min(depth as usize, self.levels.len() - 1)]
+    }
+
+    // 是否是最后一层，最后一层放置胜利物品而不是向下的楼梯
+    pub fn is_last_level(&self, depth: u32) -> bool {
+        depth as usize + 1 >= self.levels.len()
+    }
+
+    // 检查战役中用到的流水线、主题和模板都存在
+    fn validate(&self, templates: &Templates, mapgen: &MapGen, themes: &Themes) -> Result<(), String> {
+        if self.levels.is_empty() {
+            return Err(format!("campaign {} has no levels", self.name));
+        }
+        let chains: Vec<&String> = mapgen.chains.iter().map(|c| &c.name).collect();
+        let themes: Vec<&String> = themes.themes.iter().map(|t| &t.name).collect();
+        let template_type = |name: &String| templates.entities.iter()
+            .find(|t| &t.name == name)
+            .map(|t| t.entity_type.clone());
+        if template_type(&self.victory_item) != Some(EntityType::Item) {
+            return Err(format!("victory item {} is not an item template", self.victory_item));
+        }
+        for (depth, level) in self.levels.iter().enumerate() {
+            if let Some(name) = level.chains.keys().find(|name| !chains.contains(name)) {
+                return Err(format!("level {} uses unknown map generation chain {}", depth, name));
+            }
+            if let Some(name) = level.themes.keys().find(|name| !themes.contains(name)) {
+                return Err(format!("level {} uses unknown theme {}", depth, name));
+            }
+            if let Some(name) = level.bosses.iter().find(|name| template_type(name) != Some(EntityType::Enemy)) {
+                return Err(format!("level {} has boss {} which is not a monster template", depth, name));
+            }
+        }
+        if let Some(guardian) = &self.guardian {
+            if !self.levels.last().unwrap().bosses.contains(guardian) {
+                return Err(format!("guardian {} is not a boss of the last level", guardian));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn the_last_level_holds_the_victory_item_instead_of_stairs() {
+        let mut game = Game::new(GameSeed(8));
+        let campaign = game.campaign.clone();
+        let last = campaign.levels.len() as u32 - 1;
+        for _ in 0..last {
+            game.set_turn_state(TurnState::NextLevel);
+            game.step(None);
+        }
+        assert_eq!(<&Player>::query().iter(&game.ecs).next().unwrap().map_level, last);
+        let map = game.resources.get::<Map>().unwrap();
+        assert!(!map.tiles.contains(&TileType::Exit));
+        assert!(map.tiles.contains(&TileType::UpStairs));
+        let victory_items: Vec<&Name> = <&Name>::query()
+            .filter(component::<AmuletOfYala>())
+            .iter(&game.ecs)
+            .collect();
+        assert_eq!(victory_items, vec![&Name(campaign.victory_item.clone())]);
+    }
+
+    #[test]
+    fn the_victory_item_cannot_be_taken_while_its_guardian_lives() {
+        let mut game = Game::new(GameSeed(8));
+        let campaign = game.campaign.clone();
+        let guardian_name = campaign.guardian.clone().unwrap();
+        for _ in 1..campaign.levels.len() {
+            game.set_turn_state(TurnState::NextLevel);
+            game.step(None);
+        }
+        let guardians: Vec<Entity> = <(Entity, &Name)>::query()
+            .filter(component::<Guardian>() & component::<Unique>())
+            .iter(&game.ecs)
+            .filter(|(_, name)| name.0 == guardian_name)
+            .map(|(entity, _)| *entity)
+            .collect();
+        assert_eq!(guardians.len(), 1);
+        assert!(game.resources.get::<Uniques>().unwrap().0.contains(&guardian_name));
+
+        // 把玩家角色放到胜利物品旁边，其他怪物都移走
+        let others: Vec<Entity> = <Entity>::query()
+            .filter(component::<Enemy>() & !component::<Guardian>())
+            .iter(&game.ecs)
+            .copied()
+            .collect();
+        others.iter().for_each(|e| { game.ecs.remove(*e); });
+        let amulet = <&Point>::query().filter(component::<AmuletOfYala>()).iter(&game.ecs).copied().next().unwrap();
+        let (next_to, key) = {
+            let map = game.resources.get::<Map>().unwrap();
+            [(Point::new(-1, 0), VirtualKeyCode::Right), (Point::new(1, 0), VirtualKeyCode::Left),
+             (Point::new(0, -1), VirtualKeyCode::Down), (Point::new(0, 1), VirtualKeyCode::Up)]
+                .into_iter()
+                .map(|(delta, key)| (amulet + delta, key))
+                .find(|(pt, _)| map.can_enter_tile(*pt))
+                .unwrap()
+        };
+        <&mut Point>::query().filter(component::<Player>()).iter_mut(&mut game.ecs).for_each(|p| *p = next_to);
+        // 守护者离得很远，不会打扰玩家角色
+        game.ecs.entry(guardians[0]).unwrap().add_component(Point::new(-100, -100));
+        let take_turn = |game: &mut Game, key| {
+            game.step(Some(key));
+            while matches!(game.turn_state(), TurnState::PlayerTurn | TurnState::MonsterTurn) {
+                game.step(None);
+            }
+        };
+        take_turn(&mut game, key);
+        assert_eq!(game.turn_state(), TurnState::AwaitingInput);
+        assert!(game.resources.get::<MessageLog>().unwrap().entries.iter().any(|e| e.text.contains("is bound by")));
+
+        // 消灭守护者之后，站在胜利物品上就可以拿起它
+        game.ecs.remove(guardians[0]);
+        take_turn(&mut game, VirtualKeyCode::Space);
+        assert_eq!(game.turn_state(), TurnState::Victory);
+    }
+}
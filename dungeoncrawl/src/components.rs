@@ -1,8 +1,9 @@
 use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
 pub use crate::prelude::*;
 
 // 渲染实体
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Render {
     // 同时存储前景色和背景色
     pub color: ColorPair,
@@ -11,42 +12,145 @@ pub struct Render {
 }
 
 // 玩家角色实体
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Player {
     // 地下城的层级
     pub map_level: u32
 }
 
 // 怪物实体
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Enemy;
 
 // 随机移动实体
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MovingRandomly;
 
 // 标记怪物正在追逐玩家角色
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ChasingPlayer;
 
+// 怪物的生命值低于最大生命值的percent%时逃离玩家角色
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FleesWhenHurt {
+    pub percent: i32,
+}
+
+// 标记怪物正在逃跑
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fleeing;
+
+// 远程攻击的怪物与玩家角色保持距离，在自身Ranged组件的射程之内并且弹道畅通时射击
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeepsDistance;
+
+// 在房间之间巡逻的怪物，waypoints是这一层可以选择的路点（房间的中心，没有房间时是出生点），
+// waypoint是当前要去的路点，到达或者无法到达之后再随机选择下一个
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Patrolling {
+    pub waypoints: Vec<Point>,
+    pub waypoint: Option<Point>,
+}
+
+impl Patrolling {
+    // 随机选择下一个路点，不选择当前所在的位置和当前的路点
+    pub fn next_waypoint(&self, rng: &mut RandomNumberGenerator, pos: Point) -> Option<Point> {
+        let candidates: Vec<Point> = self.waypoints.iter()
+            .filter(|pt| **pt != pos && Some(**pt) != self.waypoint)
+            .copied()
+            .collect();
+        rng.random_slice_entry(&candidates).copied()
+    }
+}
+
+// 守卫金库的怪物，只追击离岗位radius格之内的玩家角色，否则回到岗位
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Guarding {
+    pub post: Point,
+    pub radius: i32,
+}
+
+// 看到玩家角色时呼叫radius格之内的同伴
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AlertsAllies {
+    pub radius: i32,
+}
+
+// 被同伴呼叫的怪物前往同伴看到玩家角色的位置
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Alerted {
+    pub target: Point,
+}
+
+// 首领的特殊能力：生命值低于最大生命值的percent%时狂暴
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Enrages {
+    pub percent: i32,
+}
+
+// 标记怪物已经狂暴，攻击力提高，狂暴之后不再恢复
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Enraged;
+
+// 首领的特殊能力：看到玩家角色时在身边召唤count个随从（模板名称），cooldown是再次召唤之前还要等待的行动次数
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Summons {
+    pub minion: String,
+    pub count: i32,
+    pub cooldown: i32,
+}
+
+// 首领的特殊能力：向射程之内弹道畅通的玩家角色喷吐，造成damage点伤害，不需要掷骰命中，也不受防御值影响
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BreathAttack {
+    pub damage: i32,
+    pub cooldown: i32,
+}
+
+// 独特怪物，每局游戏最多出现一次，消息中直接使用它的名字，不加冠词
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Unique;
+
+// 独特怪物被消灭时写入消息记录的消息
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeathMessage(pub String);
+
+// 守护胜利物品的首领，它还活着的时候无法拿起胜利物品
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Guardian;
+
 // 移动意图实体
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WantsToMove {
     pub entity: Entity,
     pub destination: Point,
 }
 
+// 搜索意图实体：搜索附近隐藏的陷阱和暗门
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WantsToSearch {
+    pub entity: Entity,
+}
+
+// 走楼梯意图实体：玩家角色走上楼梯之后，在回合结束时前往下一层或者回到上一层
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WantsToTakeStairs {
+    pub down: bool,
+}
+
 // 攻击意图实体
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WantsToAttack {
     // 攻击者
     pub attacker: Entity,
     // 受害者
     pub victim: Entity,
+    // 远程攻击时使用的武器或投掷物品，近战攻击时为None
+    pub weapon: Option<Entity>,
 }
 
 // 生命值实体
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Health {
     // 当前生命值
     pub current: i32,
@@ -55,18 +159,18 @@ pub struct Health {
 }
 
 // 悬浮提示
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Name(pub String);
 
 // 物品
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Item;
 
-// 用来赢得游戏的物品：亚拉的护身符
-#[derive(Clone, Copy, Debug, PartialEq)]
+// 用来赢得游戏的胜利物品，由战役设置，默认是亚拉的护身符
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct AmuletOfYala;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct FieldOfView {
     // 实体在地图上可见的图块
     pub visible_tiles: HashSet<Point>,
@@ -95,31 +199,415 @@ impl FieldOfView {
 }
 
 // 治疗药水
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ProvidesHealing {
     // 药水可以恢复的生命值
     pub amount: i32,
 }
 
 // 地下城地图
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ProvidesDungeonMap;
 
 // 捡起物品
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Carried(pub Entity);
 
 // 使用物品
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ActivateItem {
     pub used_by: Entity,
     pub item: Entity,
+    // 范围伤害物品瞄准的图块，其它物品为None
+    pub target: Option<Point>,
+}
+
+// 范围伤害：对目标图块radius范围之内的所有生物造成物品的伤害值
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProvidesAreaDamage {
+    pub radius: i32,
 }
 
+// 连锁伤害：对视野中距离使用者最近的targets个怪物造成物品的伤害值
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProvidesChainDamage {
+    pub targets: i32,
+}
+
+// 随机传送到地图上的一块空地
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProvidesTeleport;
+
 // 伤害值
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Damage(pub i32);
 
-// 武器
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct Weapon;
\ No newline at end of file
+// 伤害骰子，每次攻击时掷骰子，结果加在固定伤害值上，在模板中写作1d6+1这样的形式
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DamageDice(pub DiceType);
+
+impl DamageDice {
+    pub fn parse(dice: &str) -> Option<Self> {
+        parse_dice_string(dice).ok().map(Self)
+    }
+
+    // 平均伤害，用于比较装备的好坏
+    pub fn average(&self) -> i32 {
+        self.0.n_dice * (self.0.die_type + 1) / 2 + self.0.bonus
+    }
+
+    pub fn describe(&self) -> String {
+        match self.0.bonus {
+            0 => format!("{}d{}", self.0.n_dice, self.0.die_type),
+            bonus => format!("{}d{}{:+}", self.0.n_dice, self.0.die_type, bonus),
+        }
+    }
+}
+
+// 防御值，受到攻击时减少相应的伤害
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Defence(pub i32);
+
+// 命中值，增加攻击的命中率（百分点）
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Accuracy(pub i32);
+
+// 闪避值，降低受到攻击时被命中的概率（百分点）
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Evasion(pub i32);
+
+// 一次攻击的结果
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttackOutcome {
+    Miss,
+    Hit,
+    // 暴击，伤害加倍
+    Critical,
+}
+
+// 战斗事件：每次攻击结算之后产生的消息实体，其他系统可以据此对命中、未命中和消灭怪物做出反应
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CombatEvent {
+    pub attacker: Entity,
+    pub victim: Entity,
+    // 远程攻击时使用的武器或投掷物品
+    pub weapon: Option<Entity>,
+    pub outcome: AttackOutcome,
+    // 扣除防御值之后实际造成的伤害
+    pub damage: i32,
+    // 被攻击者的生命值是否因此耗尽
+    pub killed: bool,
+}
+
+// 远程武器、投掷物品和卷轴的射程，使用时先进入瞄准模式选择目标
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ranged {
+    pub range: i32,
+}
+
+// 中毒：每回合受到dmg点伤害，持续turns个回合
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Poisoned {
+    pub dmg: i32,
+    pub turns: i32,
+}
+
+// 混乱：移动时随机选择方向
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Confused {
+    pub turns: i32,
+}
+
+// 再生：每回合恢复amount点生命值
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Regenerating {
+    pub amount: i32,
+    pub turns: i32,
+}
+
+// 加速：速度加倍，玩家角色每行动两次，普通速度的怪物才行动一次
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hasted {
+    pub turns: i32,
+}
+
+// 减速：速度减半
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Slowed {
+    pub turns: i32,
+}
+
+// 速度：每个时刻获得的能量，没有速度组件的实体使用普通速度
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Speed(pub i32);
+
+impl Speed {
+    pub const NORMAL: i32 = 100;
+}
+
+// 能量：攒够一次行动消耗的能量时才可以行动
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Energy(pub i32);
+
+impl Energy {
+    // 行动一次消耗的能量
+    pub const ACTION_COST: i32 = 100;
+
+    // 开始时攒够一次行动的能量
+    pub fn new() -> Self {
+        Self(Self::ACTION_COST)
+    }
+
+    pub fn ready(&self) -> bool {
+        self.0 >= Self::ACTION_COST
+    }
+}
+
+// 实体是否可以在当前时刻行动，没有能量组件的实体每次都可以行动
+pub fn can_act(energy: Option<&Energy>) -> bool {
+    energy.map(Energy::ready).unwrap_or(true)
+}
+
+// 饥饿：玩家角色的食物值，每回合减少1，吃东西可以恢复
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hunger {
+    pub food: i32,
+}
+
+// 饥饿程度，越饿攻击力越低，饿死之前每回合失去生命值
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HungerState {
+    Fed,
+    Hungry,
+    Weak,
+    Starving,
+}
+
+impl Hunger {
+    // 食物值的上限，也是开始游戏时的食物值
+    pub const MAX_FOOD: i32 = 1200;
+
+    pub fn new() -> Self {
+        Self { food: Self::MAX_FOOD }
+    }
+
+    pub fn state(&self) -> HungerState {
+        match self.food {
+            f if f <= 0 => HungerState::Starving,
+            f if f <= 100 => HungerState::Weak,
+            f if f <= 300 => HungerState::Hungry,
+            _ => HungerState::Fed,
+        }
+    }
+}
+
+impl HungerState {
+    // 饥饿时减少的攻击力
+    pub fn damage_penalty(&self) -> i32 {
+        match self {
+            HungerState::Fed | HungerState::Hungry => 0,
+            HungerState::Weak => 1,
+            HungerState::Starving => 2,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HungerState::Fed => "Fed",
+            HungerState::Hungry => "Hungry",
+            HungerState::Weak => "Weak",
+            HungerState::Starving => "Starving",
+        }
+    }
+}
+
+// 食物：恢复amount点食物值
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProvidesFood {
+    pub amount: i32,
+}
+
+// 心灵感应：可以看到地图上所有的怪物
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Telepathic {
+    pub turns: i32,
+}
+
+// 物品可以施加的状态效果
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StatusEffect {
+    Poison { dmg: i32, turns: i32 },
+    Confusion { turns: i32 },
+    Regeneration { amount: i32, turns: i32 },
+    Haste { turns: i32 },
+    Slow { turns: i32 },
+    RevealMonsters { turns: i32 },
+}
+
+impl StatusEffect {
+    // 给目标添加对应的状态组件，已有的同类状态会被替换
+    pub fn apply(&self, target: Entity, commands: &mut CommandBuffer) {
+        match *self {
+            StatusEffect::Poison { dmg, turns } => commands.add_component(target, Poisoned { dmg, turns }),
+            StatusEffect::Confusion { turns } => commands.add_component(target, Confused { turns }),
+            StatusEffect::Regeneration { amount, turns } => commands.add_component(target, Regenerating { amount, turns }),
+            StatusEffect::Haste { turns } => commands.add_component(target, Hasted { turns }),
+            StatusEffect::Slow { turns } => commands.add_component(target, Slowed { turns }),
+            StatusEffect::RevealMonsters { turns } => commands.add_component(target, Telepathic { turns }),
+        }
+    }
+
+    // 状态效果的说明，用于查看物品的详细信息
+    pub fn describe(&self) -> String {
+        match *self {
+            StatusEffect::Poison { dmg, turns } => format!("Poisons for {} damage a turn, {} turns", dmg, turns),
+            StatusEffect::Confusion { turns } => format!("Confuses for {} turns", turns),
+            StatusEffect::Regeneration { amount, turns } => format!("Regenerates {} hit points a turn, {} turns", amount, turns),
+            StatusEffect::Haste { turns } => format!("Hastes for {} turns", turns),
+            StatusEffect::Slow { turns } => format!("Slows for {} turns", turns),
+            StatusEffect::RevealMonsters { turns } => format!("Reveals all monsters for {} turns", turns),
+        }
+    }
+}
+
+// 物品提供的状态效果：直接使用时施加给使用者，投掷时施加给被击中的目标
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProvidesStatus(pub Vec<StatusEffect>);
+
+// 装备栏位，每个栏位同时只能装备一件物品
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EquipmentSlot {
+    // 主手：武器
+    MainHand,
+    // 副手：盾牌
+    OffHand,
+    // 身体：盔甲
+    Body,
+    // 头部：头盔
+    Head,
+    // 戒指
+    Ring,
+}
+
+impl EquipmentSlot {
+    // 平视显示区中依次显示的栏位，F1~F5键卸下对应栏位的装备
+    pub const ALL: [EquipmentSlot; 5] = [
+        EquipmentSlot::MainHand,
+        EquipmentSlot::OffHand,
+        EquipmentSlot::Body,
+        EquipmentSlot::Head,
+        EquipmentSlot::Ring,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EquipmentSlot::MainHand => "Main hand",
+            EquipmentSlot::OffHand => "Off hand",
+            EquipmentSlot::Body => "Body",
+            EquipmentSlot::Head => "Head",
+            EquipmentSlot::Ring => "Ring",
+        }
+    }
+}
+
+// 可以装备的物品，以及装备时占用的栏位
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equippable {
+    pub slot: EquipmentSlot,
+}
+
+// 已经装备的物品，不再出现在物品列表中，它的伤害值和防御值计入装备者的属性
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equipped {
+    pub owner: Entity,
+    pub slot: EquipmentSlot,
+}
+
+// 装备物品意图，同一栏位中原来的装备会放回物品列表
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WantsToEquip {
+    pub entity: Entity,
+    pub item: Entity,
+}
+
+// 卸下装备意图，卸下的装备放回物品列表
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WantsToUnequip {
+    pub entity: Entity,
+    pub slot: EquipmentSlot,
+}
+
+// 丢弃物品意图，物品从物品列表中取出，放在玩家角色脚下
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WantsToDrop {
+    pub entity: Entity,
+    pub item: Entity,
+}
+
+// 玩家角色的等级和经验值，经验值达到升级所需的数值时升级，每次升级可以选择一项天赋
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Experience {
+    pub level: i32,
+    // 当前等级已经获得的经验值
+    pub xp: i32,
+    // 还没有选择的天赋数量
+    pub perks: i32,
+}
+
+impl Experience {
+    pub fn new() -> Self {
+        Self { level: 1, xp: 0, perks: 0 }
+    }
+
+    // 从当前等级升到下一级所需的经验值
+    pub fn next_level(&self) -> i32 {
+        self.level * 10
+    }
+}
+
+// 消灭怪物之后玩家角色获得的经验值
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExperienceValue(pub i32);
+
+// 获得经验值的消息实体
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GainExperience {
+    pub entity: Entity,
+    pub amount: i32,
+}
+
+// 升级时可以选择的天赋
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Perk {
+    // 增加最大生命值
+    Vitality,
+    // 增加基础伤害
+    Strength,
+    // 增加基础防御
+    Toughness,
+    // 增加视野半径
+    Perception,
+}
+
+impl Perk {
+    // 升级界面中依次列出的天赋，数字键1~4选择对应的天赋
+    pub const ALL: [Perk; 4] = [Perk::Vitality, Perk::Strength, Perk::Toughness, Perk::Perception];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Perk::Vitality => "Vitality",
+            Perk::Strength => "Strength",
+            Perk::Toughness => "Toughness",
+            Perk::Perception => "Perception",
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Perk::Vitality => "+5 maximum health",
+            Perk::Strength => "+1 damage",
+            Perk::Toughness => "+1 defence",
+            Perk::Perception => "+2 field of view",
+        }
+    }
+}
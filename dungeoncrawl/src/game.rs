@@ -0,0 +1,220 @@
+use crate::prelude::*;
+
+/*
+ * 游戏逻辑：包含ECS世界、资源以及输入、物品栏、瞄准、升级、消息记录、玩家、怪物七个执行计划。
+ * 这里不依赖bracket-lib的窗口和渲染，既可以由窗口中的键盘输入驱动，也可以在无界面模式下由脚本或AI驱动。
+ */
+pub struct Game {
+    // 存储所有的实体和组件，Entity Component System实体组件系统
+    pub ecs: World,
+    pub resources: Resources,
+    // 战役设置、实体模板、地图生成流水线、主题和金库，启动时加载一次，重新开始、进入新的一层和读取存档时不再读取文件
+    pub campaign: Campaign,
+    templates: Templates,
+    mapgen: MapGen,
+    themes: Themes,
+    prefabs: Prefabs,
+    input_systems: Schedule,
+    inventory_systems: Schedule,
+    targeting_systems: Schedule,
+    level_up_systems: Schedule,
+    message_log_systems: Schedule,
+    player_systems: Schedule,
+    monster_systems: Schedule,
+}
+
+impl Game {
+    pub fn new(seed: GameSeed) -> Self {
+        let templates = Templates::load();
+        let mapgen = MapGen::load();
+        let themes = Themes::load();
+        let mut game = Self {
+            ecs: World::default(),
+            resources: Resources::default(),
+            campaign: Campaign::load(&templates, &mapgen, &themes),
+            templates,
+            mapgen,
+            themes,
+            prefabs: Prefabs::load(),
+            input_systems: build_input_scheduler(),
+            inventory_systems: build_inventory_scheduler(),
+            targeting_systems: build_targeting_scheduler(),
+            level_up_systems: build_level_up_scheduler(),
+            message_log_systems: build_message_log_scheduler(),
+            player_systems: build_player_scheduler(),
+            monster_systems: build_monster_scheduler(),
+        };
+        game.reset(seed);
+        game
+    }
+
+    // 使用指定的种子开始一局新游戏
+    pub fn reset(&mut self, seed: GameSeed) {
+        self.ecs = World::default();
+        self.resources = Resources::default();
+        // 整局游戏共享同一个随机数生成器，保证相同的种子得到相同的游戏过程
+        self.resources.insert(seed.rng());
+        self.resources.insert(Uniques::default());
+        // 首领在游戏过程中召唤随从时使用的模板
+        self.resources.insert(self.templates.clone());
+        // 生成第一层，将楼梯、怪物、物品放置在地图上
+        let map_builder = self.build_level(0);
+        // 设置玩家角色
+        spawn_player(&mut self.ecs, map_builder.player_start);
+        self.resources.insert(Camera::new(map_builder.player_start, &map_builder.map));
+        self.resources.insert(map_builder.map);
+        self.resources.insert(TurnState::AwaitingInput);
+        self.resources.insert(map_builder.theme);
+        self.resources.insert(seed);
+        self.resources.insert(GameStats::default());
+        self.resources.insert(InventoryMenu::default());
+        self.resources.insert(Targeting::default());
+        self.resources.insert(Levels::default());
+        let mut log = MessageLog::default();
+        log.add(&self.campaign.intro, YELLOW);
+        self.resources.insert(log);
+    }
+
+    pub fn turn_state(&self) -> TurnState {
+        *self.resources.get::<TurnState>().unwrap()
+    }
+
+    pub fn set_turn_state(&mut self, turn_state: TurnState) {
+        self.resources.insert(turn_state);
+    }
+
+    // 推进一步游戏逻辑，根据当前的游戏模态执行对应的执行计划，key是这一帧的键盘输入
+    pub fn step(&mut self, key: Option<VirtualKeyCode>) {
+        // 将键盘的输入状态作为一个资源加入到资源列表中
+        self.resources.insert(key);
+        match self.turn_state() {
+            TurnState::AwaitingInput => self.input_systems.execute(&mut self.ecs, &mut self.resources),
+            TurnState::Inventory => self.inventory_systems.execute(&mut self.ecs, &mut self.resources),
+            TurnState::Targeting => self.targeting_systems.execute(&mut self.ecs, &mut self.resources),
+            TurnState::LevelUp => self.level_up_systems.execute(&mut self.ecs, &mut self.resources),
+            TurnState::MessageLog => self.message_log_systems.execute(&mut self.ecs, &mut self.resources),
+            TurnState::PlayerTurn => self.player_systems.execute(&mut self.ecs, &mut self.resources),
+            TurnState::MonsterTurn => self.monster_systems.execute(&mut self.ecs, &mut self.resources),
+            TurnState::NextLevel => self.change_level(true),
+            TurnState::PreviousLevel => self.change_level(false),
+            // 主菜单、游戏结束、游戏胜利和地图生成调试画面由界面处理
+            TurnState::MainMenu | TurnState::GameOver | TurnState::Victory | TurnState::MapGenDebug => {}
+        }
+    }
+
+    pub fn save(&self) -> ron::Result<()> {
+        save_game(&self.ecs, &self.resources)
+    }
+
+    // 读取存档，替换当前的游戏世界和资源
+    pub fn load(&mut self) -> ron::Result<()> {
+        let (ecs, resources) = load_game(&self.themes)?;
+        self.ecs = ecs;
+        self.resources = resources;
+        // 界面状态和模板不写入存档
+        self.resources.insert(InventoryMenu::default());
+        self.resources.insert(Targeting::default());
+        self.resources.insert(self.templates.clone());
+        Ok(())
+    }
+
+    // 瞄准模式中把瞄准的图块移到指定的位置，用于鼠标选择目标和回放录像
+    pub fn aim_at(&mut self, target: Point) {
+        if let Some(mut targeting) = self.resources.get_mut::<Targeting>() {
+            targeting.cursor = target;
+        }
+    }
+
+    // 离开当前这一层，前往下一层或者回到上一层；去过的楼层从快照中还原，没有去过的楼层重新生成
+    fn change_level(&mut self, down: bool) {
+        let current_level = <&Player>::query().iter(&self.ecs).map(|p| p.map_level).next().unwrap();
+        let map_level = if down { current_level + 1 } else { current_level.saturating_sub(1) };
+
+        // 1. 把留在当前这一层的实体连同地图和主题保存为快照，游戏世界中只剩下玩家角色、物品列表以及已装备物品
+        let map = self.resources.remove::<Map>().unwrap();
+        let theme = self.resources.remove::<Box<dyn MapTheme>>().unwrap();
+        let mut levels = self.resources.remove::<Levels>().unwrap();
+        levels.store(current_level, &mut self.ecs, map, theme);
+        // 进入另一层时玩家角色可以立即行动
+        <&mut Energy>::query().iter_mut(&mut self.ecs)
+            .for_each(|energy| *energy = Energy::new());
+
+        // 2. 玩家角色站到对应的楼梯上：下楼时站在向上的楼梯上，上楼时站在向下的楼梯上
+        let (map, theme, arrival) = match levels.restore(map_level, &mut self.ecs) {
+            Some((map, theme)) => {
+                let stairs = if down { TileType::UpStairs } else { TileType::Exit };
+                let idx = map.tiles.iter().position(|t| *t == stairs).expect("Level has no matching stairs");
+                let arrival = map.index_to_point2d(idx);
+                // 回到去过的楼层时没有地图生成记录
+                self.resources.remove::<MapGenHistory>();
+                (map, theme, arrival)
+            }
+            None => {
+                let map_builder = self.build_level(map_level);
+                let arrival = if down { map_builder.player_start } else { map_builder.amulet_start };
+                (map_builder.map, map_builder.theme, arrival)
+            }
+        };
+        self.resources.insert(levels);
+
+        // 3. 设置玩家角色，并更新地图层级数
+        <(&mut Player, &mut Point)>::query()
+            .iter_mut(&mut self.ecs)
+            .for_each(|(player, pos)| {
+                player.map_level = map_level;
+                *pos = arrival;
+            });
+        // 所有的视野都设置为脏数据，保证在下一个回合中，地图可以正确被渲染
+        <&mut FieldOfView>::query().iter_mut(&mut self.ecs)
+            .for_each(|fov| fov.is_dirty = true);
+        self.resources.insert(Camera::new(arrival, &map));
+        self.resources.insert(map);
+        self.resources.insert(theme);
+        self.resources.insert(TurnState::AwaitingInput);
+        if let Some(mut log) = self.resources.get_mut::<MessageLog>() {
+            if down {
+                log.add(format!("You descend to dungeon level {}.", map_level + 1), CYAN);
+            } else {
+                log.add(format!("You climb back to dungeon level {}.", map_level + 1), CYAN);
+            }
+        }
+    }
+
+    // 按照战役的设置生成没有去过的一层，放置楼梯、怪物和物品，使用整局游戏共享的随机数生成器
+    fn build_level(&mut self, map_level: u32) -> MapBuilder {
+        let campaign = &self.campaign;
+        let level = campaign.level(map_level);
+        let mut rng = self.resources.remove::<RandomNumberGenerator>().unwrap();
+        let mut uniques = self.resources.remove::<Uniques>().unwrap();
+        let mut map_builder = MapBuilder::new(&mut rng, level, map_level as usize, &self.mapgen, &self.themes, &self.prefabs);
+        if campaign.is_last_level(map_level) {
+            // 最后一层的尽头放置胜利物品
+            self.templates.spawn_named(&mut self.ecs, &campaign.victory_item, map_builder.amulet_start, &mut uniques);
+        } else {
+            // 否则创建向下的楼梯
+            let exit_idx = map_builder.map.point2d_to_index(map_builder.amulet_start);
+            map_builder.map.tiles[exit_idx] = TileType::Exit;
+        }
+        // 第一层之外，玩家角色的起始位置是回到上一层的楼梯
+        if map_level > 0 {
+            let up_idx = map_builder.map.point2d_to_index(map_builder.player_start);
+            map_builder.map.tiles[up_idx] = TileType::UpStairs;
+        }
+        // 设置怪物和物品
+        spawn_level(&mut self.ecs, &mut rng, map_level as usize, level, &map_builder, &self.templates, &mut uniques);
+        // 最后一层的守护者还活着的时候无法拿起胜利物品
+        if let (true, Some(guardian)) = (campaign.is_last_level(map_level), &campaign.guardian) {
+            let guardians: Vec<Entity> = <(Entity, &Name)>::query()
+                .filter(component::<Enemy>())
+                .iter(&self.ecs)
+                .filter(|(_, name)| &name.0 == guardian)
+                .map(|(entity, _)| *entity)
+                .collect();
+            guardians.iter().for_each(|e| self.ecs.entry(*e).unwrap().add_component(Guardian));
+        }
+        self.resources.insert(rng);
+        self.resources.insert(uniques);
+        self.resources.insert(MapGenHistory::take(&mut map_builder));
+        map_builder
+    }
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+use crate::prelude::*;
+
+// 本局游戏的随机数种子，相同的种子会生成相同的地下城，方便玩家分享和复现问题
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameSeed(pub u64);
+
+impl GameSeed {
+    // 随机生成一个新的种子
+    pub fn random() -> Self {
+        Self(RandomNumberGenerator::new().next_u64())
+    }
+
+    // 使用种子创建整局游戏共享的随机数生成器
+    pub fn rng(&self) -> RandomNumberGenerator {
+        RandomNumberGenerator::seeded(self.0)
+    }
+}
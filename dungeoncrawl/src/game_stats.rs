@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+// 本局游戏的统计数据，用于无界面模拟时评估游戏平衡性
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GameStats {
+    // 玩家角色存活的回合数
+    pub turns: u32,
+    // 玩家角色消灭的怪物数量
+    pub kills: u32,
+    // 玩家角色使用的物品数量
+    pub items_used: u32,
+}
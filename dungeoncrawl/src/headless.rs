@@ -0,0 +1,345 @@
+use crate::prelude::*;
+use crate::systems::ITEM_KEYS;
+
+// 无界面模式下的输入来源，每次轮到玩家行动时提供一个按键，返回None表示没有更多输入
+pub trait InputSource {
+    // 瞄准模式中可以修改游戏里瞄准的图块，代替鼠标选择目标
+    fn next_key(&mut self, game: &mut Game) -> Option<VirtualKeyCode>;
+}
+
+// 按顺序回放一组预先写好的按键
+pub struct ScriptedInput {
+    keys: Vec<VirtualKeyCode>,
+    next: usize,
+}
+
+impl ScriptedInput {
+    pub fn new(keys: Vec<VirtualKeyCode>) -> Self {
+        Self { keys, next: 0 }
+    }
+
+    // 解析用空白分隔的按键名称，例如："Right Right G Key1 Space"
+    pub fn parse(script: &str) -> Result<Self, String> {
+        script
+            .split_whitespace()
+            .map(|name| key_from_name(name).ok_or_else(|| format!("unknown key {}", name)))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self::new)
+    }
+}
+
+// 脚本中可以使用的按键名称
+fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    let key = match name {
+        "Left" => VirtualKeyCode::Left,
+        "Right" => VirtualKeyCode::Right,
+        "Up" => VirtualKeyCode::Up,
+        "Down" => VirtualKeyCode::Down,
+        "G" => VirtualKeyCode::G,
+        "S" => VirtualKeyCode::S,
+        "Space" => VirtualKeyCode::Space,
+        // 物品栏界面使用的按键
+        "I" => VirtualKeyCode::I,
+        "U" => VirtualKeyCode::U,
+        "E" => VirtualKeyCode::E,
+        "D" => VirtualKeyCode::D,
+        "X" => VirtualKeyCode::X,
+        "Return" => VirtualKeyCode::Return,
+        "Escape" => VirtualKeyCode::Escape,
+        // 瞄准模式使用的按键
+        "F" => VirtualKeyCode::F,
+        "Tab" => VirtualKeyCode::Tab,
+        // 消息记录使用的按键
+        "L" => VirtualKeyCode::L,
+        "PageUp" => VirtualKeyCode::PageUp,
+        "PageDown" => VirtualKeyCode::PageDown,
+        _ => {
+            // Key1~Key9对应数字键
+            let n: usize = name.strip_prefix("Key")?.parse().ok()?;
+            *ITEM_KEYS.get(n.checked_sub(1)?)?
+        }
+    };
+    Some(key)
+}
+
+impl InputSource for ScriptedInput {
+    fn next_key(&mut self, _game: &mut Game) -> Option<VirtualKeyCode> {
+        let key = self.keys.get(self.next).copied();
+        self.next += 1;
+        key
+    }
+}
+
+/*
+ * 简单的自动玩家：生命值不足时喝治疗药水，换上更好的装备，站在物品上时捡起物品，看到怪物时上前攻击，
+ * 否则选择一个目标并一直走到目标为止，依次考虑视野中的物品、最近的未揭示图块，最后是楼梯或护身符。
+ */
+#[derive(Default)]
+pub struct AutoPlayer {
+    // 当前的目标位置，以及目标是否只是一个需要探索的图块
+    goal: Option<(Point, bool)>,
+    // 设定目标时所在的地图层级，进入新的一层后原来的目标不再有效
+    map_level: u32,
+}
+
+impl AutoPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 沿着迪杰斯特拉图走向目标，返回对应的方向键
+    fn step_towards(map: &Map, from: Point, target: Point) -> Option<VirtualKeyCode> {
+        let dijkstra_map = DijkstraMap::new(
+            map.width, map.height, &[map.point2d_to_index(target)], map, 1024.0);
+        let from_idx = map.point2d_to_index(from);
+        // 已经站在目标上，或者目标不可到达
+        if from == target || dijkstra_map.map[from_idx] == f32::MAX {
+            return None;
+        }
+        // 目标就在旁边时直接走过去，迪杰斯特拉图中起点的值并不是0，不能用它来判断
+        let destination = if DistanceAlg::Pythagoras.distance2d(from, target) > 1.2 {
+            map.index_to_point2d(DijkstraMap::find_lowest_exit(&dijkstra_map, from_idx, map)?)
+        } else {
+            target
+        };
+        let delta = destination - from;
+        match (delta.x, delta.y) {
+            (-1, 0) => Some(VirtualKeyCode::Left),
+            (1, 0) => Some(VirtualKeyCode::Right),
+            (0, -1) => Some(VirtualKeyCode::Up),
+            (0, 1) => Some(VirtualKeyCode::Down),
+            _ => None,
+        }
+    }
+
+    // 物品列表中第一个带有组件T的物品的序号，只考虑可以用数字键使用的前9个物品
+    fn first_carried<T: storage::Component>(ecs: &World, player: Entity) -> Option<usize> {
+        <(&Item, &Carried, Entity)>::query()
+            .iter(ecs)
+            .filter(|(_, carried, _)| carried.0 == player)
+            .enumerate()
+            .find(|(_, (_, _, entity))| ecs.entry_ref(**entity).unwrap().get_component::<T>().is_ok())
+            .map(|(n, _)| n)
+            .filter(|n| *n < ITEM_KEYS.len())
+    }
+
+    // 装备的伤害值（伤害骰子按平均值计算）和防御值之和
+    fn gear_score(ecs: &World, item: Entity) -> i32 {
+        let entry = ecs.entry_ref(item).unwrap();
+        entry.get_component::<Damage>().map_or(0, |d| d.0)
+            + entry.get_component::<DamageDice>().map_or(0, |d| d.average())
+            + entry.get_component::<Defence>().map_or(0, |d| d.0)
+    }
+
+    // 找出物品列表中比对应栏位上的装备更好的一件装备，返回它在物品列表中的序号
+    fn better_equipment(ecs: &World, player: Entity) -> Option<usize> {
+        <(&Item, &Carried, Entity)>::query()
+            .iter(ecs)
+            .filter(|(_, carried, _)| carried.0 == player)
+            .enumerate()
+            .find(|(_, (_, _, item))| {
+                let Ok(equippable) = ecs.entry_ref(**item).unwrap().get_component::<Equippable>().copied() else {
+                    return false;
+                };
+                let current = <(&Equipped, Entity)>::query()
+                    .iter(ecs)
+                    .find(|(equipped, _)| equipped.owner == player && equipped.slot == equippable.slot)
+                    .map(|(_, entity)| Self::gear_score(ecs, *entity));
+                match current {
+                    Some(score) => Self::gear_score(ecs, **item) > score,
+                    None => true,
+                }
+            })
+            .map(|(n, _)| n)
+    }
+
+    // 在候选位置中找出从玩家角色出发路径最短且可到达的一个
+    fn nearest(distances: &DijkstraMap, map: &Map, candidates: &[Point]) -> Option<Point> {
+        candidates
+            .iter()
+            .map(|pt| (*pt, distances.map[map.point2d_to_index(*pt)]))
+            .filter(|(_, distance)| *distance < f32::MAX)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|(pt, _)| pt)
+    }
+}
+
+impl InputSource for AutoPlayer {
+    fn next_key(&mut self, game: &mut Game) -> Option<VirtualKeyCode> {
+        // 升级时总是选择第一项天赋：增加最大生命值
+        if game.turn_state() == TurnState::LevelUp {
+            return Some(VirtualKeyCode::Key1);
+        }
+        let ecs = &game.ecs;
+        let map = game.resources.get::<Map>().unwrap();
+        let (player, player_pos, health, fov, map_level) = <(Entity, &Point, &Health, &FieldOfView, &Player)>::query()
+            .iter(ecs)
+            .map(|(entity, pos, health, fov, p)| (*entity, *pos, *health, fov.clone(), p.map_level))
+            .next()?;
+        if map_level != self.map_level {
+            self.map_level = map_level;
+            self.goal = None;
+        }
+
+        // 生命值低于一半时，使用物品列表中的第一瓶治疗药水
+        if health.current * 2 < health.max {
+            if let Some(n) = Self::first_carried::<ProvidesHealing>(ecs, player) {
+                return Some(ITEM_KEYS[n]);
+            }
+        }
+
+        // 饥饿时吃掉物品列表中的第一份食物
+        let hungry = ecs.entry_ref(player).unwrap().get_component::<Hunger>()
+            .map(|h| h.state() != HungerState::Fed)
+            .unwrap_or(false);
+        if hungry {
+            if let Some(n) = Self::first_carried::<ProvidesFood>(ecs, player) {
+                return Some(ITEM_KEYS[n]);
+            }
+        }
+
+        // 物品列表中有比当前更好的装备时换上
+        if let Some(n) = Self::better_equipment(ecs, player).filter(|n| *n < ITEM_KEYS.len()) {
+            return Some(ITEM_KEYS[n]);
+        }
+
+        // 站在物品上时捡起物品
+        let mut items = <(&Item, &Point)>::query();
+        if items.iter(ecs).any(|(_, pos)| *pos == player_pos) {
+            return Some(VirtualKeyCode::G);
+        }
+
+        let distances = DijkstraMap::new(
+            map.width, map.height, &[map.point2d_to_index(player_pos)], &*map, 1024.0);
+
+        // 视野中出现怪物时，优先攻击最近的怪物
+        let enemies: Vec<Point> = <&Point>::query()
+            .filter(component::<Enemy>())
+            .iter(ecs)
+            .filter(|pos| fov.visible_tiles.contains(pos))
+            .copied()
+            .collect();
+        if let Some(enemy) = Self::nearest(&distances, &map, &enemies) {
+            self.goal = Some((enemy, false));
+        }
+
+        // 到达目标，或者需要探索的图块已经被揭示时，放弃当前目标
+        if let Some((goal, exploring)) = self.goal {
+            if goal == player_pos || (exploring && map.revealed_tiles[map.point2d_to_index(goal)]) {
+                self.goal = None;
+            }
+        }
+
+        if self.goal.is_none() {
+            let visible_items: Vec<Point> = items
+                .iter(ecs)
+                .map(|(_, pos)| *pos)
+                .filter(|pos| fov.visible_tiles.contains(pos))
+                .collect();
+            let unexplored: Vec<Point> = map.tiles
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| !map.revealed_tiles[*idx])
+                .map(|(idx, _)| map.index_to_point2d(idx))
+                .filter(|pt| map.can_enter_tile(*pt))
+                .collect();
+            // 守护胜利物品的首领还活着时，先去找它而不是胜利物品
+            let mut guardians: Vec<Point> = <&Point>::query().filter(component::<Guardian>()).iter(ecs).copied().collect();
+            if guardians.is_empty() {
+                guardians = <&Point>::query().filter(component::<AmuletOfYala>()).iter(ecs).copied().collect();
+            }
+            let exits: Vec<Point> = map.tiles
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| **t == TileType::Exit)
+                .map(|(idx, _)| map.index_to_point2d(idx))
+                .chain(guardians)
+                .collect();
+            self.goal = Self::nearest(&distances, &map, &visible_items).map(|pt| (pt, false))
+                .or_else(|| Self::nearest(&distances, &map, &unexplored).map(|pt| (pt, true)))
+                .or_else(|| Self::nearest(&distances, &map, &exits).map(|pt| (pt, false)));
+        }
+
+        // 找不到路径时放弃目标，原地等待一回合
+        match self.goal.and_then(|(goal, _)| Self::step_towards(&map, player_pos, goal)) {
+            Some(key) => Some(key),
+            None => {
+                self.goal = None;
+                Some(VirtualKeyCode::Space)
+            }
+        }
+    }
+}
+
+// 一局无界面模拟的结果
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimulationReport {
+    pub seed: GameSeed,
+    // 模拟结束时的游戏模态
+    pub outcome: TurnState,
+    // 存活的回合数
+    pub turns: u32,
+    // 到达的地下城层数，从1开始计数
+    pub depth: u32,
+    // 消灭的怪物数量
+    pub kills: u32,
+    // 使用的物品数量
+    pub items_used: u32,
+}
+
+/*
+ * 在没有窗口的情况下运行游戏：轮到玩家行动时从输入来源读取按键，
+ * 直到完成max_turns个回合、游戏结束、游戏胜利或者输入来源没有更多按键为止。
+ */
+pub fn run_headless(seed: GameSeed, max_turns: u32, input: &mut dyn InputSource) -> (Game, SimulationReport) {
+    let mut game = Game::new(seed);
+    loop {
+        let stats = *game.resources.get::<GameStats>().unwrap();
+        match game.turn_state() {
+            TurnState::GameOver | TurnState::Victory => break,
+            _ if stats.turns >= max_turns => break,
+            state if state.awaits_input() => match input.next_key(&mut game) {
+                Some(key) => game.step(Some(key)),
+                None => break,
+            },
+            _ => game.step(None),
+        }
+    }
+    let report = simulation_report(&game);
+    (game, report)
+}
+
+// 根据游戏当前的状态生成模拟报告
+pub fn simulation_report(game: &Game) -> SimulationReport {
+    let stats = *game.resources.get::<GameStats>().unwrap();
+    let depth = <&Player>::query().iter(&game.ecs).map(|p| p.map_level + 1).next().unwrap_or(0);
+    SimulationReport {
+        seed: *game.resources.get::<GameSeed>().unwrap(),
+        outcome: game.turn_state(),
+        turns: stats.turns,
+        depth,
+        kills: stats.kills,
+        items_used: stats.items_used,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn the_same_seed_simulates_the_same_run() {
+        let (_, first) = run_headless(GameSeed(7), 200, &mut AutoPlayer::new());
+        let (_, second) = run_headless(GameSeed(7), 200, &mut AutoPlayer::new());
+        assert_eq!(first, second);
+        assert!(first.turns > 0);
+    }
+
+    #[test]
+    fn a_script_stops_when_it_runs_out_of_keys() {
+        let mut script = ScriptedInput::parse("Space Space Space").unwrap();
+        let (_, report) = run_headless(GameSeed(7), 100, &mut script);
+        assert_eq!(report.turns, 3);
+        assert!(ScriptedInput::parse("Jump").is_err());
+    }
+}
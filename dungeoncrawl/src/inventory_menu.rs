@@ -0,0 +1,88 @@
+use legion::world::EntryRef;
+use crate::prelude::*;
+
+// 物品栏中一页显示的物品数量，物品更多时上下滚动
+pub const INVENTORY_PAGE_SIZE: usize = 15;
+
+// 物品栏界面的状态，只在打开物品栏时使用，不写入存档
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InventoryMenu {
+    // 选中的物品在物品列表中的序号
+    pub selected: usize,
+    // 当前页第一行对应的物品序号
+    pub scroll: usize,
+    // 是否正在查看选中物品的详细信息
+    pub inspecting: bool,
+}
+
+impl InventoryMenu {
+    // 把选中的物品限制在列表范围内，并滚动列表使选中的物品可见
+    pub fn clamp(&mut self, items: usize) {
+        self.selected = usize::min(self.selected, items.saturating_sub(1));
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + INVENTORY_PAGE_SIZE {
+            self.scroll = self.selected + 1 - INVENTORY_PAGE_SIZE;
+        }
+    }
+}
+
+// 玩家角色携带的物品，顺序与平视显示区中的物品列表相同
+pub fn carried_items(ecs: &SubWorld, player: Entity) -> Vec<Entity> {
+    <(Entity, &Item, &Carried)>::query()
+        .iter(ecs)
+        .filter(|(_, _, carried)| carried.0 == player)
+        .map(|(entity, _, _)| *entity)
+        .collect()
+}
+
+// 物品的效果说明，用于查看物品的详细信息
+pub fn describe_item(item: &EntryRef) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Ok(healing) = item.get_component::<ProvidesHealing>() {
+        lines.push(format!("Restores {} hit points", healing.amount));
+    }
+    if let Ok(food) = item.get_component::<ProvidesFood>() {
+        lines.push(format!("Satisfies {} turns of hunger", food.amount));
+    }
+    if item.get_component::<ProvidesDungeonMap>().is_ok() {
+        lines.push("Reveals the map of this level".to_string());
+    }
+    if let Ok(area) = item.get_component::<ProvidesAreaDamage>() {
+        lines.push(format!("Damages everything within {} tiles of the target", area.radius));
+    }
+    if let Ok(chain) = item.get_component::<ProvidesChainDamage>() {
+        lines.push(format!("Strikes the {} nearest visible monsters", chain.targets));
+    }
+    if item.get_component::<ProvidesTeleport>().is_ok() {
+        lines.push("Teleports you to a random place on this level".to_string());
+    }
+    if let Ok(status) = item.get_component::<ProvidesStatus>() {
+        status.0.iter().for_each(|effect| lines.push(effect.describe()));
+    }
+    if let Ok(equippable) = item.get_component::<Equippable>() {
+        lines.push(format!("Equipped in slot: {}", equippable.slot.name()));
+    }
+    if let Ok(damage) = item.get_component::<Damage>() {
+        lines.push(format!("Damage: +{}", damage.0));
+    }
+    if let Ok(dice) = item.get_component::<DamageDice>() {
+        lines.push(format!("Damage: {}", dice.describe()));
+    }
+    if let Ok(accuracy) = item.get_component::<Accuracy>() {
+        lines.push(format!("Accuracy: {:+}%", accuracy.0));
+    }
+    if let Ok(ranged) = item.get_component::<Ranged>() {
+        lines.push(format!("Range: {}", ranged.range));
+    }
+    if let Ok(defence) = item.get_component::<Defence>() {
+        lines.push(format!("Defence: +{}", defence.0));
+    }
+    if let Ok(evasion) = item.get_component::<Evasion>() {
+        lines.push(format!("Evasion: {:+}%", evasion.0));
+    }
+    if lines.is_empty() {
+        lines.push("It has no special effects".to_string());
+    }
+    lines
+}
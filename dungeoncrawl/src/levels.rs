@@ -0,0 +1,133 @@
+use std::collections::{BTreeMap, HashSet};
+use crate::prelude::*;
+
+// 离开一层时保存的快照：地图（包括已经揭示的图块）、地图主题以及留在这一层的怪物和物品
+pub struct LevelSnapshot {
+    pub map: Map,
+    pub theme: Box<dyn MapTheme>,
+    pub world: World,
+}
+
+// 去过的楼层，按照地图层级保存快照，玩家角色所在的这一层不在其中
+#[derive(Default)]
+pub struct Levels {
+    pub snapshots: BTreeMap<u32, LevelSnapshot>,
+}
+
+// 临时标记留在当前这一层的实体，用来把它们从游戏世界中整批移出
+struct LeftBehind;
+
+impl Levels {
+    // 把玩家角色、物品列表和已装备物品之外的所有实体移出游戏世界，和地图一起保存为这一层的快照
+    pub fn store(&mut self, map_level: u32, ecs: &mut World, map: Map, theme: Box<dyn MapTheme>) {
+        let player = *<Entity>::query()
+            .filter(component::<Player>())
+            .iter(ecs).next().unwrap();
+        // 用于存储需要带走的实体
+        let mut entities_to_keep = HashSet::new();
+        entities_to_keep.insert(player);
+        <(Entity, &Carried)>::query()
+            .iter(ecs)
+            .filter(|(_, carry)| carry.0 == player)
+            .for_each(|(e, _)| { entities_to_keep.insert(*e); });
+        <(Entity, &Equipped)>::query()
+            .iter(ecs)
+            .filter(|(_, equipped)| equipped.owner == player)
+            .for_each(|(e, _)| { entities_to_keep.insert(*e); });
+        let left_behind: Vec<Entity> = <Entity>::query()
+            .iter(ecs)
+            .filter(|e| !entities_to_keep.contains(*e))
+            .copied()
+            .collect();
+        left_behind.iter().for_each(|e| ecs.entry(*e).unwrap().add_component(LeftBehind));
+
+        // 移动之后实体保持原来的Entity，互相之间的引用仍然有效
+        let mut world = World::default();
+        world.move_from(ecs, &component::<LeftBehind>());
+        left_behind.iter().for_each(|e| world.entry(*e).unwrap().remove_component::<LeftBehind>());
+        self.snapshots.insert(map_level, LevelSnapshot { map, theme, world });
+    }
+
+    // 取出去过的楼层的快照，把保存的实体放回游戏世界，返回这一层的地图和主题
+    pub fn restore(&mut self, map_level: u32, ecs: &mut World) -> Option<(Map, Box<dyn MapTheme>)> {
+        let mut snapshot = self.snapshots.remove(&map_level)?;
+        ecs.move_from(&mut snapshot.world, &any());
+        Some((snapshot.map, snapshot.theme))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // 执行一个回合，直到重新等待玩家输入
+    fn take_turn(game: &mut Game, key: VirtualKeyCode) {
+        game.step(Some(key));
+        while game.turn_state() != TurnState::AwaitingInput {
+            game.step(None);
+        }
+    }
+
+    // 把玩家角色放到楼梯旁边，返回走上楼梯的方向键
+    fn stand_next_to(game: &mut Game, stairs: TileType) -> (Point, VirtualKeyCode) {
+        let (stairs_pos, next_to) = {
+            let map = game.resources.get::<Map>().unwrap();
+            let idx = map.tiles.iter().position(|t| *t == stairs).unwrap();
+            let stairs_pos = map.index_to_point2d(idx);
+            let next_to = [(Point::new(-1, 0), VirtualKeyCode::Right), (Point::new(1, 0), VirtualKeyCode::Left),
+                           (Point::new(0, -1), VirtualKeyCode::Down), (Point::new(0, 1), VirtualKeyCode::Up)]
+                .into_iter()
+                .map(|(delta, key)| (stairs_pos + delta, key))
+                .find(|(pt, _)| map.can_enter_tile(*pt))
+                .unwrap();
+            (stairs_pos, next_to)
+        };
+        let (pos, key) = next_to;
+        <&mut Point>::query().filter(component::<Player>()).iter_mut(&mut game.ecs).for_each(|p| *p = pos);
+        (stairs_pos, key)
+    }
+
+    fn player_pos(game: &Game) -> (Point, u32) {
+        <(&Point, &Player)>::query().iter(&game.ecs).map(|(pos, p)| (*pos, p.map_level)).next().unwrap()
+    }
+
+    // 这一层上的物品，按照位置排序
+    fn items_on_level(game: &Game) -> Vec<(String, Point)> {
+        let mut items: Vec<(String, Point)> = <(&Name, &Point)>::query()
+            .filter(component::<Item>())
+            .iter(&game.ecs)
+            .map(|(name, pos)| (name.0.clone(), *pos))
+            .collect();
+        items.sort_by_key(|(name, pos)| (pos.x, pos.y, name.clone()));
+        items
+    }
+
+    #[test]
+    fn a_visited_level_is_kept_when_the_player_goes_back_up() {
+        let mut game = Game::new(GameSeed(4));
+        let enemies: Vec<Entity> = <Entity>::query().filter(component::<Enemy>()).iter(&game.ecs).copied().collect();
+        enemies.iter().for_each(|e| { game.ecs.remove(*e); });
+        let items = items_on_level(&game);
+        let (exit, key) = stand_next_to(&mut game, TileType::Exit);
+        take_turn(&mut game, key);
+        let tiles = game.resources.get::<Levels>().unwrap().snapshots[&0].map.tiles.clone();
+
+        // 到达第二层时站在向上的楼梯上，原地等待不会换层
+        let (pos, map_level) = player_pos(&game);
+        assert_eq!(map_level, 1);
+        {
+            let map = game.resources.get::<Map>().unwrap();
+            assert_eq!(map.tiles[map.point2d_to_index(pos)], TileType::UpStairs);
+        }
+        take_turn(&mut game, VirtualKeyCode::Space);
+        assert_eq!(player_pos(&game), (pos, 1));
+
+        // 回到第一层时站在向下的楼梯上，地图和物品都和离开时一样
+        let (_, key) = stand_next_to(&mut game, TileType::UpStairs);
+        take_turn(&mut game, key);
+        assert_eq!(player_pos(&game), (exit, 0));
+        assert_eq!(game.resources.get::<Map>().unwrap().tiles, tiles);
+        assert_eq!(items_on_level(&game), items);
+        assert!(game.resources.get::<Levels>().unwrap().snapshots.contains_key(&1));
+    }
+}
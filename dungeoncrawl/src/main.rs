@@ -5,6 +5,19 @@ mod components;
 mod spawner;
 mod systems;
 mod turn_state;
+mod save_load;
+mod game_seed;
+mod options;
+mod game;
+mod game_stats;
+mod headless;
+mod replay;
+mod mapgen_viewer;
+mod inventory_menu;
+mod targeting;
+mod message_log;
+mod levels;
+mod campaign;
 
 mod prelude {
     pub use bracket_lib::prelude::*;
@@ -31,149 +44,211 @@ mod prelude {
     pub use crate::systems::*;
 
     pub use crate::turn_state::*;
+
+    pub use crate::save_load::*;
+
+    pub use crate::game_seed::*;
+
+    pub use crate::options::*;
+
+    pub use crate::game::*;
+
+    pub use crate::game_stats::*;
+
+    pub use crate::headless::*;
+
+    pub use crate::replay::*;
+
+    pub use crate::mapgen_viewer::*;
+
+    pub use crate::inventory_menu::*;
+
+    pub use crate::targeting::*;
+
+    pub use crate::message_log::*;
+
+    pub use crate::levels::*;
+
+    pub use crate::campaign::*;
 }
 
 use prelude::*;
 
+// 自动存档的间隔（回合数），存档包含去过的所有楼层，不在每个回合都重写
+const AUTOSAVE_TURNS: u32 = 20;
+
 struct State {
-    // 存储所有的实体和组件，Entity Component System实体组件系统
-    ecs: World,
-    resources: Resources,
-    input_systems: Schedule,
-    player_systems: Schedule,
-    monster_systems: Schedule,
+    // 游戏逻辑
+    game: Game,
+    // 渲染地图、实体和平视显示区的执行计划
+    render_systems: Schedule,
+    // 录制按键时的录像文件和录像内容
+    recording: Option<(String, Recording)>,
+    // 正在回放的录像，回放结束后交还给键盘控制
+    playback: Option<Playback>,
+    // 是否在每一层开始之前回放地图生成的过程
+    mapgen_debug: bool,
+    mapgen_viewer: MapGenViewer,
+    // 上一次自动存档时的回合数
+    autosaved_turns: u32,
+    // 鼠标在地图图层上的位置，瞄准模式中鼠标移动时才改变瞄准的图块
+    mouse_pos: Point,
 }
 
 impl State {
-    fn new() -> Self {
-        let mut ecs = World::default();
-        let mut resources = Resources::default();
-        let mut rng = RandomNumberGenerator::new();
-        let mut map_builder = MapBuilder::new(&mut rng);
-        // 设置玩家角色
-        spawn_player(&mut ecs, map_builder.player_start);
-        // 设置楼梯
-        let exit_idx = map_builder.map.point2d_to_index(map_builder.amulet_start);
-        map_builder.map.tiles[exit_idx] = TileType::Exit;
-        // 将怪物、物品放置在地图上
-        spawn_level(&mut ecs, &mut rng, 0, &map_builder.monster_spawns);
-        resources.insert(map_builder.map);
-        resources.insert(Camera::new(map_builder.player_start));
-        resources.insert(TurnState::AwaitingInput);
-        resources.insert(map_builder.theme);
-        Self {
-            ecs,
-            resources,
-            input_systems: build_input_scheduler(),
-            player_systems: build_player_scheduler(),
-            monster_systems: build_monster_scheduler(),
+    fn new(options: &Options) -> Self {
+        let replay = options.replay.as_ref()
+            .map(|path| Recording::load(path).expect("Unable to read recording"));
+        let seed = replay.as_ref().map_or(options.seed, |r| r.seed);
+        let game = Game::new(seed);
+        let recording = options.record.clone().map(|path| (path, Recording::new(seed)));
+        let mut state = Self {
+            game,
+            render_systems: build_render_scheduler(),
+            recording,
+            playback: replay.map(|r| r.playback()),
+            mapgen_debug: options.mapgen_debug,
+            mapgen_viewer: MapGenViewer::default(),
+            autosaved_turns: 0,
+            mouse_pos: Point::zero(),
+        };
+        // 存在存档时先进入主菜单，让玩家选择继续游戏还是开始新游戏；录制和回放总是从新游戏开始
+        if has_save_game() && state.recording.is_none() && state.playback.is_none() {
+            state.game.set_turn_state(TurnState::MainMenu);
+        } else {
+            state.start_level();
+        }
+        state
+    }
+
+    // 瞄准模式中鼠标指向的图块成为瞄准的图块，单击鼠标左键相当于按下回车键确认
+    fn aim_with_mouse(&mut self, ctx: &BTerm, mouse_moved: bool) -> Option<VirtualKeyCode> {
+        if !mouse_moved && !ctx.left_click {
+            return None;
+        }
+        let camera = self.game.resources.get::<Camera>().unwrap();
+        let map_pos = self.mouse_pos + Point::new(camera.left_x, camera.top_y);
+        drop(camera);
+        self.game.aim_at(map_pos);
+        ctx.left_click.then_some(VirtualKeyCode::Return)
+    }
+
+    // 开始在新生成的地图上游戏，调试模式下先回放地图生成的过程
+    fn start_level(&mut self) {
+        if self.mapgen_debug {
+            self.mapgen_viewer.restart();
+            self.game.set_turn_state(TurnState::MapGenDebug);
+        } else {
+            self.game.set_turn_state(TurnState::AwaitingInput);
+        }
+    }
+
+    fn mapgen_debug(&mut self, ctx: &mut BTerm) {
+        // 没有生成记录时（例如读取存档之后）直接开始游戏
+        let finished = match self.game.resources.get::<MapGenHistory>() {
+            Some(history) => self.mapgen_viewer.tick(ctx, &history),
+            None => true,
+        };
+        if finished {
+            self.game.set_turn_state(TurnState::AwaitingInput);
+        }
+    }
+
+    fn main_menu(&mut self, ctx: &mut BTerm) {
+        ctx.set_active_console(2);
+        ctx.print_color_centered(2, YELLOW, BLACK, "Dungeon Crawler");
+        ctx.print_color_centered(5, WHITE, BLACK,
+                                 "A saved game was found.");
+        ctx.print_color_centered(7, GREEN, BLACK, "Press C to continue.");
+        ctx.print_color_centered(8, GREEN, BLACK, "Press N to start a new game.");
+        match ctx.key {
+            Some(VirtualKeyCode::C) => self.continue_game(),
+            Some(VirtualKeyCode::N) => {
+                delete_save_game();
+                self.start_level();
+            }
+            _ => {}
+        }
+    }
+
+    // 读取存档，读取失败时开始一局新游戏
+    fn continue_game(&mut self) {
+        if let Err(e) = self.game.load() {
+            eprintln!("Warning: unable to load saved game: {}", e);
+            delete_save_game();
+            self.start_level();
         }
+        self.autosaved_turns = self.turns();
     }
 
-    fn reset_game_state(&mut self) {
-        self.ecs = World::default();
-        self.resources = Resources::default();
-        let mut rng = RandomNumberGenerator::new();
-        let mut map_builder = MapBuilder::new(&mut rng);
-        spawn_player(&mut self.ecs, map_builder.player_start);
-        let exit_idx = map_builder.map.point2d_to_index(map_builder.amulet_start);
-        map_builder.map.tiles[exit_idx] = TileType::Exit;
-        spawn_level(&mut self.ecs, &mut rng, 0, &map_builder.monster_spawns);
-        self.resources.insert(map_builder.map);
-        self.resources.insert(Camera::new(map_builder.player_start));
-        self.resources.insert(TurnState::AwaitingInput);
-        self.resources.insert(map_builder.theme);
+    fn turns(&self) -> u32 {
+        self.game.resources.get::<GameStats>().unwrap().turns
+    }
+
+    fn save_game(&mut self) {
+        self.autosaved_turns = self.turns();
+        if let Err(e) = self.game.save() {
+            eprintln!("Warning: unable to save game: {}", e);
+        }
+    }
+
+    fn save_recording(&self) {
+        if let Some((path, recording)) = &self.recording {
+            if let Err(e) = recording.save(path) {
+                eprintln!("Warning: unable to save recording: {}", e);
+            }
+        }
+    }
+
+    // 用指定的种子重新开始游戏，正在录制时录像也从头开始
+    fn reset(&mut self, seed: GameSeed) {
+        self.game.reset(seed);
+        self.start_level();
+        self.autosaved_turns = 0;
+        self.playback = None;
+        if let Some((_, recording)) = &mut self.recording {
+            *recording = Recording::new(seed);
+        }
     }
 
     fn game_over(&mut self, ctx: &mut BTerm) {
         // 展示在平视显示区
         ctx.set_active_console(2);
         ctx.print_color_centered(2, RED, BLACK, "Your quest has ended.");
-        ctx.print_color_centered(4, WHITE, BLACK,
-                                 "Slain by a monster, your hero's journey has come to a premature end.");
-        ctx.print_color_centered(5, WHITE, BLACK,
-                                 "The Amulet of Yala remains unclaimed, and your home town is not saved.");
+        // 战役设置的游戏结束文字
+        self.game.campaign.defeat_text.iter().take(3).enumerate().for_each(|(i, line)| {
+            ctx.print_color_centered(4 + i as i32, WHITE, BLACK, line);
+        });
         ctx.print_color_centered(8, YELLOW, BLACK,
                                  "Don't worry, you can always try again with a new hero.");
         ctx.print_color_centered(9, GREEN, BLACK,
                                  "Press 1 to play again.");
-        // 使用1号键，避免不小心跳过游戏结束画面
-        if let Some(VirtualKeyCode::Key1) = ctx.key {
-            self.reset_game_state();
-        }
+        self.seed_options(ctx, 11);
     }
 
     fn victory(&mut self, ctx: &mut BTerm) {
         ctx.set_active_console(2);
         ctx.print_color_centered(2, GREEN, BLACK, "You have won!");
-        ctx.print_color_centered(4, WHITE, BLACK,
-                                 "You put on the Amulet of Yala and feel its power course through \
-            your veins.");
-        ctx.print_color_centered(5, WHITE, BLACK,
-                                 "Your town is saved, and you can return to your normal life.");
+        // 战役设置的胜利文字
+        self.game.campaign.victory_text.iter().take(2).enumerate().for_each(|(i, line)| {
+            ctx.print_color_centered(4 + i as i32, WHITE, BLACK, line);
+        });
         ctx.print_color_centered(7, GREEN, BLACK, "Press 1 to \
             play again.");
-        if let Some(VirtualKeyCode::Key1) = ctx.key {
-            self.reset_game_state();
-        }
+        self.seed_options(ctx, 9);
     }
 
-    fn advance_level(&mut self) {
-        // 1. 从esc删除除玩家角色以及物品列表之外的所有实体
-        let player_entity = *<Entity>::query()
-            .filter(component::<Player>())
-            .iter(&mut self.ecs).nth(0).unwrap();
-        use std::collections::HashSet;
-        // 用于存储需要留下的实体
-        let mut entities_to_keep = HashSet::new();
-        entities_to_keep.insert(player_entity);
-        // 获取物品列表中的实体
-        <(Entity, &Carried)>::query()
-            .iter(&self.ecs)
-            .filter(|(_e, carry)| carry.0 == player_entity)
-            .map(|(e, _carry)| *e)
-            .for_each(|e| { entities_to_keep.insert(e); });
-        // 删除其余的实体
-        let mut cb = CommandBuffer::new(&mut self.ecs);
-        for e in Entity::query().iter(&self.ecs) {
-            if !entities_to_keep.contains(e) {
-                cb.remove(*e);
-            }
+    // 显示本局游戏的种子，1号键用新种子重新开始，2号键用同一个种子再玩一次
+    fn seed_options(&mut self, ctx: &mut BTerm, y: i32) {
+        let seed = *self.game.resources.get::<GameSeed>().unwrap();
+        ctx.print_color_centered(y, WHITE, BLACK, format!("Seed: {}", seed.0));
+        ctx.print_color_centered(y + 1, GREEN, BLACK, "Press 2 to replay this seed.");
+        // 使用数字键，避免不小心跳过游戏结束画面
+        match ctx.key {
+            Some(VirtualKeyCode::Key1) => self.reset(GameSeed::random()),
+            Some(VirtualKeyCode::Key2) => self.reset(seed),
+            _ => {}
         }
-        // 将变更应用到ECS世界中
-        cb.flush(&mut self.ecs);
-
-        // 2. 设置玩家角色的视野设置为脏数据，保证在下一个回合中，地图可以正确被渲染
-        <&mut FieldOfView>::query().iter_mut(&mut self.ecs)
-            .for_each(|fov| fov.is_dirty = true);
-
-        // 3. 创建新地图
-        let mut rng = RandomNumberGenerator::new();
-        let mut map_builder = MapBuilder::new(&mut rng);
-        // 设置玩家角色，并更新地图层级数
-        let mut map_level = 0;
-        <(&mut Player, &mut Point)>::query()
-            .iter_mut(&mut self.ecs)
-            .for_each(|(player, pos)| {
-                player.map_level += 1;
-                map_level = player.map_level;
-                pos.x = map_builder.player_start.x;
-                pos.y = map_builder.player_start.y;
-            });
-        if map_level == 2 {
-            // 如果地图层级到了第3层，创建亚拉的护身符
-            spawn_amulet_of_yala(&mut self.ecs, map_builder.amulet_start);
-        } else {
-            // 否则创建楼梯
-            let exit_idx = map_builder.map.point2d_to_index(map_builder.amulet_start);
-            map_builder.map.tiles[exit_idx] = TileType::Exit;
-        }
-        // 设置怪物和物品
-        spawn_level(&mut self.ecs, &mut rng, map_level as usize, &map_builder.monster_spawns);
-        self.resources.insert(map_builder.map);
-        self.resources.insert(Camera::new(map_builder.player_start));
-        self.resources.insert(TurnState::AwaitingInput);
-        self.resources.insert(map_builder.theme);
     }
 }
 
@@ -189,28 +264,120 @@ impl GameState for State {
         // 2：平视显示区图层
         ctx.set_active_console(2);
         ctx.cls();
-        // 将键盘的输入状态作为一个资源加入到资源列表中
-        self.resources.insert(ctx.key);
         // 从地图图层中获得鼠标位置
         ctx.set_active_console(0);
-        self.resources.insert(Point::from_tuple(ctx.mouse_pos()));
+        let mouse_pos = Point::from_tuple(ctx.mouse_pos());
+        let mouse_moved = mouse_pos != self.mouse_pos;
+        self.mouse_pos = mouse_pos;
+        self.game.resources.insert(mouse_pos);
 
-        // 执行各个系统的执行计划
-        let current_state = self.resources.get::<TurnState>().unwrap().clone();
+        let current_state = self.game.turn_state();
         match current_state {
-            TurnState::AwaitingInput => self.input_systems.execute(&mut self.ecs, &mut self.resources),
-            TurnState::PlayerTurn => self.player_systems.execute(&mut self.ecs, &mut self.resources),
-            TurnState::MonsterTurn => self.monster_systems.execute(&mut self.ecs, &mut self.resources),
+            TurnState::MainMenu => self.main_menu(ctx),
             TurnState::GameOver => self.game_over(ctx),
             TurnState::Victory => self.victory(ctx),
-            TurnState::NextLevel => self.advance_level()
+            TurnState::MapGenDebug => self.mapgen_debug(ctx),
+            // 等待输入时按Esc键保存游戏并退出
+            TurnState::AwaitingInput if ctx.key == Some(VirtualKeyCode::Escape) => {
+                self.save_game();
+                ctx.quit();
+                return;
+            }
+            _ => {
+                // 回放录像时用录像中的按键代替键盘输入，录像放完后交还给键盘
+                let mut key = ctx.key;
+                if let Some(playback) = self.playback.as_mut().filter(|_| current_state.awaits_input()) {
+                    key = playback.next_key(&mut self.game);
+                    if key.is_none() {
+                        self.playback = None;
+                    }
+                } else if current_state == TurnState::Targeting {
+                    key = self.aim_with_mouse(ctx, mouse_moved).or(key);
+                }
+                if let Some((_, recording)) = &mut self.recording {
+                    recording.record(&self.game, key);
+                }
+                // 执行游戏逻辑，然后渲染地图、实体和平视显示区
+                self.game.step(key);
+                // 进入另一层时先回放新地图的生成过程，回到去过的楼层时没有生成记录
+                if matches!(current_state, TurnState::NextLevel | TurnState::PreviousLevel) {
+                    self.start_level();
+                }
+                self.render_systems.execute(&mut self.game.ecs, &mut self.game.resources);
+            }
+        }
+        /* 重新等待玩家输入时，每隔一段回合以及进入另一层之后自动存档，关闭窗口最多丢失几十个回合的进度。
+         * 游戏结束或胜利时删除存档，避免继续一局已经结束的游戏。
+         */
+        let new_state = self.game.turn_state();
+        let changed_level = matches!(current_state, TurnState::NextLevel | TurnState::PreviousLevel | TurnState::MapGenDebug);
+        if new_state == TurnState::AwaitingInput && current_state != TurnState::AwaitingInput
+            && (changed_level || self.turns() >= self.autosaved_turns + AUTOSAVE_TURNS) {
+            self.save_game();
+        }
+        if new_state != current_state && matches!(new_state, TurnState::GameOver | TurnState::Victory) {
+            delete_save_game();
+        }
+        // 游戏模态变化时（回合结束、游戏结束或胜利）写入录像
+        if new_state != current_state {
+            self.save_recording();
         }
         // 批量渲染
         render_draw_buffer(ctx).expect("Render error");
     }
 }
 
+// 在没有窗口的情况下运行一局游戏，可以同时录制按键
+fn simulate(options: &Options) {
+    let max_turns = options.headless.unwrap_or(u32::MAX);
+    let replay = options.replay.as_ref()
+        .map(|path| Recording::load(path).expect("Unable to read recording"));
+    let seed = replay.as_ref().map_or(options.seed, |r| r.seed);
+    let mut input: Box<dyn InputSource> = match (&replay, &options.script) {
+        (Some(recording), _) => Box::new(recording.playback()),
+        (None, Some(path)) => {
+            let script = std::fs::read_to_string(path).expect("Unable to read script");
+            Box::new(ScriptedInput::parse(&script).expect("Unable to parse script"))
+        }
+        (None, None) => Box::new(AutoPlayer::new()),
+    };
+    let mut recording = Recording::new(seed);
+    let (_, report) = run_headless(seed, max_turns, &mut RecordingInput {
+        source: input.as_mut(),
+        recording: &mut recording,
+    });
+    if let Some(path) = &options.record {
+        recording.save(path).expect("Unable to save recording");
+    }
+    println!("Seed: {}", report.seed.0);
+    println!("Outcome: {:?}", report.outcome);
+    println!("Turns survived: {}", report.turns);
+    println!("Depth reached: {}", report.depth);
+    println!("Kills: {}", report.kills);
+    println!("Items used: {}", report.items_used);
+}
+
+// 检查每一个地图生成器生成的地图，有地图没有通过检查时以错误码退出
+fn validate_maps(maps: u64) {
+    let stats = validate_architects(maps);
+    stats.iter().for_each(|s| println!("{}", s));
+    if stats.iter().any(|s| s.failed > 0) {
+        std::process::exit(1);
+    }
+}
+
 fn main() -> BError {
+    let options = Options::from_args();
+    if let Some(maps) = options.validate_maps {
+        validate_maps(maps);
+        return Ok(());
+    }
+    // 无界面模式：由按键脚本、录像或自动玩家进行游戏，输出统计数据后退出
+    if options.headless.is_some() || options.script.is_some() {
+        simulate(&options);
+        return Ok(());
+    }
+
     /* with_dimensions：添加控制台尺寸
      * with_tile_dimensions：设置图块的尺寸
      * with_resource_path：设置资源存放目录
@@ -230,5 +397,5 @@ fn main() -> BError {
         .with_simple_console_no_bg(DISPLAY_WIDTH, DISPLAY_HEIGHT, "dungeonfont.png") // 实体
         .with_simple_console_no_bg(SCREEN_WIDTH * 2, SCREEN_HEIGHT * 2, "terminal8x8.png") // 平视显示区
         .build()?;
-    main_loop(context, State::new())
+    main_loop(context, State::new(&options))
 }
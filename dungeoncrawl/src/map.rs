@@ -1,46 +1,102 @@
+use serde::{Deserialize, Serialize};
 use crate::prelude::*;
 
-const NUM_TILES: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
+// 默认的地图尺寸，流水线没有指定尺寸时使用
+pub const DEFAULT_MAP_WIDTH: i32 = 80;
+pub const DEFAULT_MAP_HEIGHT: i32 = 50;
 
 /*
  * Clone类型：添加一个clone()函数
  * Copy类型：不再转移变量的所有权，做一个拷贝
  * PartialEq类型：可以使用==比较两个TileType类型的变量
  */
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TileType {
     // 墙壁
     Wall,
     // 地板
     Floor,
-    // 楼梯
-    Exit
+    // 向下的楼梯
+    Exit,
+    // 向上的楼梯，回到上一层
+    UpStairs,
+    // 关着的门，阻挡移动和视线，走进去时把门打开
+    Door,
+    // 打开的门
+    OpenDoor,
+    // 浅水，可以通过，但是走进去要多花半次行动的时间
+    Water,
+    // 尖刺陷阱，踩上去受到伤害
+    SpikeTrap,
+    // 传送陷阱，踩上去被随机传送到一块空地
+    TeleportTrap,
+    // 隐藏的陷阱，看起来和空地一样，踩上去或者被搜索到之后显露出来
+    HiddenSpikeTrap,
+    HiddenTeleportTrap,
+    // 暗门，看起来和石墙一样，被搜索到之后变成关着的门
+    SecretWall,
 }
 
+impl TileType {
+    // 隐藏的图块在玩家眼中的样子
+    pub fn appearance(self) -> TileType {
+        match self {
+            TileType::HiddenSpikeTrap | TileType::HiddenTeleportTrap => TileType::Floor,
+            TileType::SecretWall => TileType::Wall,
+            tile => tile,
+        }
+    }
+
+    // 隐藏的图块显露出来之后的样子
+    pub fn revealed(self) -> TileType {
+        match self {
+            TileType::HiddenSpikeTrap => TileType::SpikeTrap,
+            TileType::HiddenTeleportTrap => TileType::TeleportTrap,
+            TileType::SecretWall => TileType::Door,
+            tile => tile,
+        }
+    }
+
+    pub fn is_hidden(self) -> bool {
+        self.revealed() != self
+    }
+
+    pub fn is_trap(self) -> bool {
+        matches!(self.revealed(), TileType::SpikeTrap | TileType::TeleportTrap)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Map {
+    // 地图的宽度和高度，每一层可以不同
+    pub width: i32,
+    pub height: i32,
     // 图块
     pub tiles: Vec<TileType>,
     // 已经揭示的图块
     pub revealed_tiles: Vec<bool>
 }
 
-// 计算地图索引，行优先的编码方式
-pub fn map_idx(x: i32, y: i32) -> usize {
-    ((y * SCREEN_WIDTH) + x) as usize
-}
-
 impl Map {
-    pub fn new() -> Self {
+    pub fn new(width: i32, height: i32) -> Self {
+        let num_tiles = (width * height) as usize;
         Self {
-            tiles: vec![TileType::Floor; NUM_TILES],
-            revealed_tiles: vec![false; NUM_TILES]
+            width,
+            height,
+            tiles: vec![TileType::Floor; num_tiles],
+            revealed_tiles: vec![false; num_tiles]
         }
     }
 
+    // 计算地图索引，行优先的编码方式
+    pub fn idx(&self, x: i32, y: i32) -> usize {
+        ((y * self.width) + x) as usize
+    }
+
     // 判断玩家是否越过边界
     pub fn in_bounds(&self, point: Point) -> bool {
-        point.x >= 0 && point.x < SCREEN_WIDTH
-            && point.y >= 0 && point.y < SCREEN_HEIGHT
+        point.x >= 0 && point.x < self.width
+            && point.y >= 0 && point.y < self.height
     }
 
     // 获取一个图块坐标的索引值，当坐标落在地图之外时返回一个错误提示
@@ -48,41 +104,44 @@ impl Map {
         if !self.in_bounds(point) {
             None
         } else {
-            Some(map_idx(point.x, point.y))
+            Some(self.idx(point.x, point.y))
         }
     }
 
-    // 判断玩家是否可以进入一个图块
+    // 判断玩家是否可以进入一个图块，关着的门需要先打开
     pub fn can_enter_tile(&self, point: Point) -> bool {
-        self.in_bounds(point) && (
-            self.tiles[map_idx(point.x, point.y)] == TileType::Floor ||
-            self.tiles[map_idx(point.x, point.y)] == TileType::Exit
+        self.in_bounds(point) && !matches!(
+            self.tiles[self.idx(point.x, point.y)],
+            TileType::Wall | TileType::Door | TileType::SecretWall
         )
     }
 
+    // 寻路时走进一个图块的代价：开门和涉水要多花时间，已知的陷阱尽量绕开；无法通过时返回None
+    fn path_cost(&self, point: Point) -> Option<f32> {
+        if !self.in_bounds(point) {
+            return None;
+        }
+        match self.tiles[self.idx(point.x, point.y)] {
+            TileType::Door => Some(2.0),
+            TileType::Water => Some(1.5),
+            TileType::SpikeTrap | TileType::TeleportTrap => Some(10.0),
+            _ if self.can_enter_tile(point) => Some(1.0),
+            _ => None,
+        }
+    }
 
-
-    // 如果返回的是None，表示这个方向的走动行不通，如果返回的是Some，则包含目标图块的索引编号
-    fn valid_exit(&self, loc: Point, delta: Point) -> Option<usize> {
+    // 如果返回的是None，表示这个方向的走动行不通，如果返回的是Some，则包含目标图块的索引编号和走过去的代价
+    fn valid_exit(&self, loc: Point, delta: Point) -> Option<(usize, f32)> {
         let destination = loc + delta;
-        if self.in_bounds(destination) {
-            if self.can_enter_tile(destination) {
-                // 获取对应数组索引编号
-                let idx = self.point2d_to_index(destination);
-                Some(idx)
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+        // 获取对应数组索引编号
+        self.path_cost(destination).map(|cost| (self.point2d_to_index(destination), cost))
     }
 }
 
 // 地图映射
 impl Algorithm2D for Map {
     fn dimensions(&self) -> Point {
-        Point::new(SCREEN_WIDTH, SCREEN_HEIGHT)
+        Point::new(self.width, self.height)
     }
 
     fn in_bounds(&self, point: Point) -> bool {
@@ -98,18 +157,18 @@ impl BaseMap for Map {
         // 把地图中需要检测的图块的索引编号转化为x/y坐标对
         let location = self.index_to_point2d(idx);
 
-        if let Some(idx) = self.valid_exit(location, Point::new(-1, 0)) {
-            // 将作为出口的图块添加到出口列表中，代价为1.0，值越小，这条路径被选中的概率越大。
-            exits.push((idx, 1.0))
+        if let Some(exit) = self.valid_exit(location, Point::new(-1, 0)) {
+            // 将作为出口的图块添加到出口列表中，普通图块的代价为1.0，值越小，这条路径被选中的概率越大。
+            exits.push(exit)
         }
-        if let Some(idx) = self.valid_exit(location, Point::new(1, 0)) {
-            exits.push((idx, 1.0))
+        if let Some(exit) = self.valid_exit(location, Point::new(1, 0)) {
+            exits.push(exit)
         }
-        if let Some(idx) = self.valid_exit(location, Point::new(0, -1)) {
-            exits.push((idx, 1.0))
+        if let Some(exit) = self.valid_exit(location, Point::new(0, -1)) {
+            exits.push(exit)
         }
-        if let Some(idx) = self.valid_exit(location, Point::new(0, 1)) {
-            exits.push((idx, 1.0))
+        if let Some(exit) = self.valid_exit(location, Point::new(0, 1)) {
+            exits.push(exit)
         }
 
         exits
@@ -125,8 +184,8 @@ impl BaseMap for Map {
     }
 
     fn is_opaque(&self, idx: usize) -> bool {
-        // 定义墙是不透明的，地板是透明的
-        self.tiles[idx] != TileType::Floor
+        // 定义墙、关着的门和暗门是不透明的，其余的图块是透明的
+        matches!(self.tiles[idx], TileType::Wall | TileType::Door | TileType::SecretWall)
     }
 }
 
@@ -1,29 +1,41 @@
 use crate::prelude::*;
-use super::MapArchitect;
+use super::{MapArchitect, MapModifier};
 
 pub struct CellularAutomataArchitect {}
 
 // 元胞自动机：地图上每个图块都独立地根据相邻图块的数量来决定是墙壁还是空地，不停地运行迭代，直至得到可用的地图。
 impl MapArchitect for CellularAutomataArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder {
+    fn new(&mut self, rng: &mut RandomNumberGenerator, width: i32, height: i32, spawns: usize) -> MapBuilder {
         let mut mb = MapBuilder {
-            map: Map::new(),
+            map: Map::new(width, height),
             rooms: Vec::new(),
             monster_spawns: Vec::new(),
+            vault_spawns: Vec::new(),
             player_start: Point::zero(),
             amulet_start: Point::zero(),
             theme: super::themes::DungeonTheme::new(),
+            chain: String::new(),
+            history: Vec::new(),
         };
-        // 随机生成石墙和空地
-        self.random_noise_map(rng, &mut mb.map);
-        // 根据规则自动生成石墙和空地
-        for _ in 0..10 {
-            self.iteration(&mut mb.map);
-        }
-        // 找到距离地图中心最近的空地，以便放置玩家角色
-        let start = self.find_start(&mb.map);
-        // 50个怪物的位置坐标点
-        mb.monster_spawns = mb.spawn_monsters(&start, rng);
+        // 玩家所在的洞穴太小时（例如被石墙围住的几个图块）重新生成地图
+        let start = loop {
+            // 随机生成石墙和空地
+            self.random_noise_map(rng, &mut mb.map);
+            mb.take_snapshot();
+            // 根据规则自动生成石墙和空地
+            for _ in 0..10 {
+                self.iteration(&mut mb.map);
+                mb.take_snapshot();
+            }
+            // 找到距离地图中心最近的空地，以便放置玩家角色
+            let start = self.find_start(&mb.map);
+            // 玩家所在的洞穴至少要占全图的25%
+            if self.reachable_tiles(&start, &mb.map) >= mb.map.tiles.len() / 4 {
+                break start;
+            }
+        };
+        // 怪物和物品的出生点
+        mb.monster_spawns = mb.spawn_monsters(&start, rng, spawns);
         mb.player_start = start;
         // 放置护身符
         mb.amulet_start = mb.find_most_distant();
@@ -31,6 +43,21 @@ impl MapArchitect for CellularAutomataArchitect {
     }
 }
 
+// 元胞自动机平滑：对已经生成的地图再运行若干次元胞自动机的迭代，让洞穴和走廊的边缘更加圆滑
+pub struct CellularSmoothing {
+    pub iterations: usize,
+}
+
+impl MapModifier for CellularSmoothing {
+    fn modify(&mut self, mb: &mut MapBuilder, _rng: &mut RandomNumberGenerator) {
+        let mut automata = CellularAutomataArchitect{};
+        for _ in 0..self.iterations {
+            automata.iteration(&mut mb.map);
+            mb.take_snapshot();
+        }
+    }
+}
+
 impl CellularAutomataArchitect {
     fn random_noise_map(&mut self, rng: &mut RandomNumberGenerator, map: &mut Map) {
         // 随机生成墙壁和地板
@@ -49,7 +76,7 @@ impl CellularAutomataArchitect {
         for iy in -1 ..= 1 {
             for ix in -1 ..= 1 {
                 // 检查每一个相邻的图块，如果邻居是墙壁，则计数累加1。
-                if !(ix==0 && iy == 0) && map.tiles[map_idx(x+ix, y+iy)] == TileType::Wall {
+                if !(ix==0 && iy == 0) && map.tiles[map.idx(x+ix, y+iy)] == TileType::Wall {
                     neighbors += 1;
                 }
             }
@@ -60,11 +87,11 @@ impl CellularAutomataArchitect {
     // 根据规则自动生成石墙和空地
     fn iteration(&mut self, map: &mut Map) {
         let mut new_tiles = map.tiles.clone();
-        for y in 1 .. SCREEN_HEIGHT -1 {
-            for x in 1 .. SCREEN_WIDTH -1 {
+        for y in 1 .. map.height -1 {
+            for x in 1 .. map.width -1 {
                 // 获得邻居为墙壁的图块个数
                 let neighbors = self.count_neighbors(x, y, map);
-                let idx = map_idx(x, y);
+                let idx = map.idx(x, y);
 
                 if neighbors > 4 || neighbors == 0 {
                     // 如果有0个或多于4个墙壁，则当前图块是墙壁
@@ -78,9 +105,21 @@ impl CellularAutomataArchitect {
         map.tiles = new_tiles;
     }
 
+    // 从起始位置可以到达的图块数量
+    fn reachable_tiles(&self, start: &Point, map: &Map) -> usize {
+        let dijkstra_map = DijkstraMap::new(
+            map.width,
+            map.height,
+            &[map.point2d_to_index(*start)],
+            map,
+            1024.0,
+        );
+        dijkstra_map.map.iter().filter(|distance| **distance < 2000.0).count()
+    }
+
     // 找到距离地图中心最近的空地
     fn find_start(&self, map: &Map) -> Point {
-        let center = Point::new(SCREEN_WIDTH/2, SCREEN_HEIGHT/2);
+        let center = Point::new(map.width/2, map.height/2);
         let closest_point = map.tiles
             .iter()
             .enumerate() // 将迭代结果变成(index, tiletype)元组
@@ -90,7 +129,7 @@ impl CellularAutomataArchitect {
                                                                      map.index_to_point2d(idx)
             ))) // 计算每一个图块的距离
             .min_by(|(_, distance), (_, distance2)|
-                        distance.partial_cmp(&distance2).unwrap()
+                        distance.partial_cmp(distance2).unwrap()
             ) // 对图块进行排序，找到距离最近的空地
             .map(|(idx, _)| idx)
             .unwrap();
@@ -0,0 +1,108 @@
+use crate::prelude::*;
+use super::MapArchitect;
+use super::doors::add_doors;
+
+/* 二叉空间分割（Binary Space Partition）算法：
+ * 把整张地图沿水平或垂直方向一分为二，再对分出的两个区域继续分割，直到区域小到无法再分割。
+ * 在每个最小的区域中开凿一个房间，然后沿着分割的层次逐层用走廊把兄弟区域中的房间连接起来，
+ * 最后在走廊进入房间的地方放置门。
+ */
+pub struct BspArchitect {}
+
+// 分割后区域的最小边长
+const MIN_LEAF_SIZE: i32 = 8;
+// 房间的最小边长
+const MIN_ROOM_SIZE: i32 = 3;
+
+impl MapArchitect for BspArchitect {
+    fn new(&mut self, rng: &mut RandomNumberGenerator, width: i32, height: i32, _spawns: usize) -> MapBuilder {
+        let mut mb = MapBuilder {
+            map: Map::new(width, height),
+            rooms: Vec::new(),
+            monster_spawns: Vec::new(),
+            vault_spawns: Vec::new(),
+            player_start: Point::zero(),
+            amulet_start: Point::zero(),
+            theme: super::themes::DungeonTheme::new(),
+            chain: String::new(),
+            history: Vec::new(),
+        };
+        // 先填充石墙
+        mb.fill(TileType::Wall);
+        // 保留地图边缘的石墙
+        self.split(Rect::with_size(1, 1, mb.map.width - 2, mb.map.height - 2), rng, &mut mb);
+        // 在走廊和房间的交界处放置门
+        add_doors(&mut mb);
+        mb.take_snapshot();
+        mb.player_start = mb.rooms[0].center();
+        mb.amulet_start = mb.find_most_distant();
+        // 把怪兽放在房间中央
+        for room in mb.rooms.iter().skip(1) {
+            mb.monster_spawns.push(room.center());
+        }
+        mb
+    }
+}
+
+impl BspArchitect {
+    // 分割区域，返回区域中的一个房间，用于和兄弟区域中的房间相连
+    fn split(&mut self, area: Rect, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) -> Rect {
+        let (width, height) = (area.width(), area.height());
+        let can_split_x = width >= MIN_LEAF_SIZE * 2;
+        let can_split_y = height >= MIN_LEAF_SIZE * 2;
+        // 区域又窄又长时沿着长边分割，否则随机选择分割方向
+        let split_x = match (can_split_x, can_split_y) {
+            (false, false) => return self.carve_room(area, rng, mb),
+            (true, false) => true,
+            (false, true) => false,
+            (true, true) if width * 4 > height * 5 => true,
+            (true, true) if height * 4 > width * 5 => false,
+            (true, true) => rng.range(0, 2) == 0,
+        };
+        let (first, second) = if split_x {
+            let at = rng.range(MIN_LEAF_SIZE, width - MIN_LEAF_SIZE + 1);
+            (Rect::with_size(area.x1, area.y1, at, height),
+             Rect::with_size(area.x1 + at, area.y1, width - at, height))
+        } else {
+            let at = rng.range(MIN_LEAF_SIZE, height - MIN_LEAF_SIZE + 1);
+            (Rect::with_size(area.x1, area.y1, width, at),
+             Rect::with_size(area.x1, area.y1 + at, width, height - at))
+        };
+        let first_room = self.split(first, rng, mb);
+        let second_room = self.split(second, rng, mb);
+        self.connect(first_room, second_room, rng, mb);
+        if rng.range(0, 2) == 0 { first_room } else { second_room }
+    }
+
+    // 在区域中开凿一个随机大小的房间，房间四周至少保留一格石墙
+    fn carve_room(&mut self, area: Rect, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) -> Rect {
+        let width = rng.range(MIN_ROOM_SIZE, area.width() - 1);
+        let height = rng.range(MIN_ROOM_SIZE, area.height() - 1);
+        let room = Rect::with_size(
+            area.x1 + rng.range(1, area.width() - width),
+            area.y1 + rng.range(1, area.height() - height),
+            width,
+            height,
+        );
+        room.for_each(|p| {
+            let idx = mb.map.idx(p.x, p.y);
+            mb.map.tiles[idx] = TileType::Floor;
+        });
+        mb.rooms.push(room);
+        mb.take_snapshot();
+        room
+    }
+
+    // 用一条L形的走廊连接两个房间的中心
+    fn connect(&mut self, a: Rect, b: Rect, rng: &mut RandomNumberGenerator, mb: &mut MapBuilder) {
+        let (prev, new) = (a.center(), b.center());
+        if rng.range(0, 2) == 1 {
+            mb.apply_horizontal_tunnel(prev.x, new.x, prev.y);
+            mb.apply_vertical_tunnel(prev.y, new.y, new.x);
+        } else {
+            mb.apply_vertical_tunnel(prev.y, new.y, prev.x);
+            mb.apply_horizontal_tunnel(prev.x, new.x, new.y);
+        }
+        mb.take_snapshot();
+    }
+}
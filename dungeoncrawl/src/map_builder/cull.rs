@@ -0,0 +1,22 @@
+use crate::prelude::*;
+use super::MapModifier;
+
+// 把玩家起始位置无法到达的空地填充为石墙
+pub struct CullUnreachable {}
+
+impl MapModifier for CullUnreachable {
+    fn modify(&mut self, mb: &mut MapBuilder, _rng: &mut RandomNumberGenerator) {
+        // 之前的修改器可能把起始位置变成了石墙
+        mb.fix_player_start();
+        let dijkstra_map = DijkstraMap::new(
+            mb.map.width,
+            mb.map.height,
+            &[mb.map.point2d_to_index(mb.player_start)],
+            &mb.map,
+            1024.0,
+        );
+        dijkstra_map.map.iter().enumerate()
+            .filter(|(_, distance)| *distance > &2000.0)
+            .for_each(|(idx, _)| mb.map.tiles[idx] = TileType::Wall);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::prelude::*;
+use super::MapModifier;
+
+// 把几扇门换成暗门，只选择换掉之后不会挡住任何区域的门
+pub struct SecretDoors {
+    pub count: usize,
+}
+
+impl MapModifier for SecretDoors {
+    fn modify(&mut self, mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
+        let mut doors: Vec<usize> = mb.map.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| **t == TileType::Door)
+            .map(|(idx, _)| idx)
+            .collect();
+        let mut placed = 0;
+        while placed < self.count && !doors.is_empty() {
+            let idx = doors.remove(rng.random_slice_index(&doors).unwrap());
+            // 暗门和石墙一样无法通过，比较换掉前后可以到达的图块数量
+            let before = reachable_tiles(mb);
+            mb.map.tiles[idx] = TileType::SecretWall;
+            if reachable_tiles(mb) == before - 1 {
+                placed += 1;
+            } else {
+                mb.map.tiles[idx] = TileType::Door;
+            }
+        }
+    }
+}
+
+// 从玩家起始位置出发可以到达的图块数量
+fn reachable_tiles(mb: &MapBuilder) -> usize {
+    let dijkstra_map = DijkstraMap::new(
+        mb.map.width,
+        mb.map.height,
+        &[mb.map.point2d_to_index(mb.player_start)],
+        &mb.map,
+        1024.0,
+    );
+    dijkstra_map.map.iter().filter(|distance| **distance < 2000.0).count()
+}
+
+// 在走廊进入房间的地方放置门，只对有房间的地图起作用
+pub struct AddDoors {}
+
+impl MapModifier for AddDoors {
+    fn modify(&mut self, mb: &mut MapBuilder, _rng: &mut RandomNumberGenerator) {
+        add_doors(mb);
+    }
+}
+
+// 检查每个房间外面的一圈图块，走廊穿过石墙进入房间的地方就是门
+pub fn add_doors(mb: &mut MapBuilder) {
+    let is_wall = |map: &Map, x: i32, y: i32| map.tiles[map.idx(x, y)] == TileType::Wall;
+    let mut doors = Vec::new();
+    for room in mb.rooms.iter() {
+        let mut ring = Vec::new();
+        for x in room.x1..room.x2 {
+            ring.push(Point::new(x, room.y1 - 1));
+            ring.push(Point::new(x, room.y2));
+        }
+        for y in room.y1..room.y2 {
+            ring.push(Point::new(room.x1 - 1, y));
+            ring.push(Point::new(room.x2, y));
+        }
+        for pt in ring {
+            // 跳过地图边缘、石墙以及其它房间内部的图块
+            if pt.x < 1 || pt.x >= mb.map.width - 1 || pt.y < 1 || pt.y >= mb.map.height - 1
+                || mb.map.tiles[mb.map.idx(pt.x, pt.y)] != TileType::Floor
+                || mb.rooms.iter().any(|r| r.point_in_rect(pt)) {
+                continue;
+            }
+            // 门的两侧是石墙，前后是空地
+            let horizontal = is_wall(&mb.map, pt.x - 1, pt.y) && is_wall(&mb.map, pt.x + 1, pt.y)
+                && !is_wall(&mb.map, pt.x, pt.y - 1) && !is_wall(&mb.map, pt.x, pt.y + 1);
+            let vertical = is_wall(&mb.map, pt.x, pt.y - 1) && is_wall(&mb.map, pt.x, pt.y + 1)
+                && !is_wall(&mb.map, pt.x - 1, pt.y) && !is_wall(&mb.map, pt.x + 1, pt.y);
+            if horizontal || vertical {
+                doors.push(pt);
+            }
+        }
+    }
+    for pt in doors {
+        // 避免两扇门紧挨在一起
+        let next_to_door = [Point::new(-1, 0), Point::new(1, 0), Point::new(0, -1), Point::new(0, 1)]
+            .iter()
+            .any(|d| mb.map.tiles[mb.map.point2d_to_index(pt + *d)] == TileType::Door);
+        if !next_to_door {
+            let idx = mb.map.point2d_to_index(pt);
+            mb.map.tiles[idx] = TileType::Door;
+        }
+    }
+}
@@ -11,48 +11,51 @@ pub struct DrunkardsWalkArchitect {}
 // 设置矿工能行走的最大步数
 const STAGGER_DISTANCE: usize = 400;
 
-const NUM_TILES: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
-// 空地图块必须占全图的30%
-const DESIRED_FLOOR: usize = NUM_TILES / 3;
-
 impl MapArchitect for DrunkardsWalkArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder {
+    fn new(&mut self, rng: &mut RandomNumberGenerator, width: i32, height: i32, spawns: usize) -> MapBuilder {
         let mut mb = MapBuilder {
-            map: Map::new(),
+            map: Map::new(width, height),
             rooms: Vec::new(),
             monster_spawns: Vec::new(),
+            vault_spawns: Vec::new(),
             player_start: Point::zero(),
             amulet_start: Point::zero(),
             theme: super::themes::DungeonTheme::new(),
+            chain: String::new(),
+            history: Vec::new(),
         };
         // 先全部填充石墙
         mb.fill(TileType::Wall);
-        let center = Point::new(SCREEN_WIDTH /2, SCREEN_HEIGHT/2);
+        let center = Point::new(mb.map.width /2, mb.map.height/2);
+        // 空地图块必须占全图的30%
+        let desired_floor = mb.map.tiles.len() / 3;
         // 矿工开始挖掘
         self.drunkard(&center, rng, &mut mb.map);
+        mb.take_snapshot();
         // 循环判断是否继续需要矿工挖掘
-        while mb.map.tiles.iter().filter(|t| **t == TileType::Floor).count() < DESIRED_FLOOR {
+        while mb.map.tiles.iter().filter(|t| **t == TileType::Floor).count() < desired_floor {
             // 随机在一个点上放置矿工挖掘
             self.drunkard(
                 &Point::new(
-                    rng.range(0, SCREEN_WIDTH),
-                    rng.range(0, SCREEN_HEIGHT)
+                    rng.range(0, mb.map.width),
+                    rng.range(0, mb.map.height)
                 ),
                 rng,
                 &mut mb.map
             );
             let dijkstra_map = DijkstraMap::new(
-                                                SCREEN_WIDTH,
-                                                SCREEN_HEIGHT,
-                                                &vec![mb.map.point2d_to_index(center)],
+                                                mb.map.width,
+                                                mb.map.height,
+                                                &[mb.map.point2d_to_index(center)],
                                                 &mb.map,
                                                 1024.0);
             // 将不可达的点设置为石墙
             dijkstra_map.map.iter().enumerate()
                 .filter(|(_, distance)| *distance > &2000.0)
                 .for_each(|(idx, _)| mb.map.tiles[idx] = TileType::Wall);
+            mb.take_snapshot();
         }
-        mb.monster_spawns = mb.spawn_monsters(&center, rng);
+        mb.monster_spawns = mb.spawn_monsters(&center, rng, spawns);
         mb.player_start = center;
         mb.amulet_start = mb.find_most_distant();
         mb
@@ -64,7 +67,7 @@ impl MapArchitect for DrunkardsWalkArchitect {
 impl DrunkardsWalkArchitect {
     fn drunkard(&mut self, start: &Point, rng: &mut RandomNumberGenerator, map: &mut Map) {
         // 矿工的位置
-        let mut drunkard_pos = start.clone();
+        let mut drunkard_pos = *start;
         // 记录已经走过的步数
         let mut distance_staggered = 0;
 
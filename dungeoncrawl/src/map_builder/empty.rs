@@ -4,27 +4,31 @@ use super::MapArchitect;
 pub struct EmptyArchitect {}
 
 impl MapArchitect for EmptyArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder {
+    fn new(&mut self, rng: &mut RandomNumberGenerator, width: i32, height: i32, spawns: usize) -> MapBuilder {
         let mut mb = MapBuilder {
-            map: Map::new(),
+            map: Map::new(width, height),
             rooms: Vec::new(),
             monster_spawns: Vec::new(),
+            vault_spawns: Vec::new(),
             player_start: Point::zero(),
             amulet_start: Point::zero(),
             theme: super::themes::DungeonTheme::new(),
+            chain: String::new(),
+            history: Vec::new(),
         };
         // 填充地板
         mb.fill(TileType::Floor);
+        mb.take_snapshot();
         // 玩家位于整个地图中央
-        mb.player_start = Point::new(SCREEN_WIDTH/ 2, SCREEN_HEIGHT / 2);
+        mb.player_start = Point::new(mb.map.width / 2, mb.map.height / 2);
         // 放置亚拉的护身符
         mb.amulet_start = mb.find_most_distant();
-        for _ in 0..50 {
+        for _ in 0..spawns {
             // 添加怪物
             mb.monster_spawns.push(
                 Point::new(
-                    rng.range(1, SCREEN_WIDTH),
-                    rng.range(1, SCREEN_HEIGHT)
+                    rng.range(1, mb.map.width),
+                    rng.range(1, mb.map.height)
                 )
             )
         }
@@ -2,23 +2,38 @@ mod empty;
 mod rooms;
 mod automata;
 mod drunkard;
+mod bsp;
+mod cull;
+mod doors;
+mod traps;
+mod water;
 mod prefab;
 mod themes;
+mod pipeline;
+mod validation;
 
 use crate::prelude::*;
 
-use rooms::RoomsArchitect;
-use crate::map_builder::automata::CellularAutomataArchitect;
-use crate::map_builder::drunkard::DrunkardsWalkArchitect;
-use crate::map_builder::prefab::apply_prefab;
-use crate::map_builder::themes::{DungeonTheme, ForestTheme};
+pub use crate::map_builder::prefab::{Prefabs, VaultSpawn};
+pub use crate::map_builder::pipeline::{BuildChain, MapGen};
+pub use crate::map_builder::validation::*;
+pub use crate::map_builder::themes::Themes;
 
+// 初始生成器：从零开始生成一张指定尺寸的地图，spawns是没有房间的地图上要选出的出生点数量，有房间的地图使用房间的中心
 trait MapArchitect {
-    fn new(&mut self, rng: &mut RandomNumberGenerator) -> MapBuilder;
+    #[allow(clippy::new_ret_no_self, clippy::wrong_self_convention)]
+    fn new(&mut self, rng: &mut RandomNumberGenerator, width: i32, height: i32, spawns: usize) -> MapBuilder;
 }
 
-// 定义20个房间的地下城
+// 修改器：在初始生成器之后对地图做进一步的加工
+trait MapModifier {
+    fn modify(&mut self, mb: &mut MapBuilder, rng: &mut RandomNumberGenerator);
+}
+
+// 默认尺寸的地图中有20个房间，更大的地图按照面积增加房间的数量
 const NUM_ROOMS: usize = 20;
+// 放置房间的最多尝试次数
+const MAX_ROOM_ATTEMPTS: usize = 1000;
 
 pub struct MapBuilder {
     pub map: Map,
@@ -26,33 +41,110 @@ pub struct MapBuilder {
     pub rooms: Vec<Rect>,
     // 怪物列表
     pub monster_spawns: Vec<Point>,
+    // 金库中指定了类型或名字的实体
+    pub vault_spawns: Vec<(Point, VaultSpawn)>,
     // 玩家的初始位置
     pub player_start: Point,
     // 护身符的位置
     pub amulet_start: Point,
     // 主题风格
-    pub theme: Box<dyn MapTheme>
+    pub theme: Box<dyn MapTheme>,
+    // 生成地图使用的流水线名称
+    pub chain: String,
+    // 生成过程中每一步的地图快照，用于调试地图生成算法
+    pub history: Vec<Map>,
 }
 
 impl MapBuilder {
-    pub fn new(rng: &mut RandomNumberGenerator) -> Self {
-        // 装箱操作，dyn表示动态分发
-        let mut architect : Box<dyn MapArchitect> = match rng.range(0,3) {
-            0 => Box::new(DrunkardsWalkArchitect{}),
-            1 => Box::new(RoomsArchitect{}),
-            _ => Box::new(CellularAutomataArchitect{})
-        };
-        let mut mb = architect.new(rng);
-        // 放置金库
-        apply_prefab(&mut mb, rng);
-
-        mb.theme = match rng.range(0, 2) {
-            0 => DungeonTheme::new(),
-            _ => ForestTheme::new()
-        };
+    // level：战役中这一层的设置，用于选择流水线和主题；depth：地下城的层数，从0开始，用于选择金库；
+    // mapgen、themes和prefabs是启动时加载的流水线、主题和金库
+    pub fn new(rng: &mut RandomNumberGenerator,
+               level: &CampaignLevel,
+               depth: usize,
+               mapgen: &MapGen,
+               themes: &Themes,
+               prefabs: &Prefabs) -> Self {
+        let chain = mapgen.random_chain(rng, &level.chains);
+        let mut mb = Self::build(&chain, rng, depth, level.spawn_points(), prefabs);
+        // 从主题文件中选择主题风格
+        mb.theme = themes.random_theme(rng, &level.themes);
+        mb
+    }
+
+    // 按照流水线生成地图：先运行初始生成器，再依次运行每一个修改器；spawns是需要的出生点数量，prefabs是可以放置的金库
+    pub fn build(chain: &BuildChain,
+                 rng: &mut RandomNumberGenerator,
+                 depth: usize,
+                 spawns: usize,
+                 prefabs: &Prefabs) -> Self {
+        let mut mb = chain.architect.architect().new(rng, chain.width, chain.height, spawns);
+        mb.chain = chain.name.clone();
+        for modifier in chain.modifiers.iter() {
+            modifier.modifier(depth, prefabs).modify(&mut mb, rng);
+            mb.take_snapshot();
+        }
+        mb.finalize();
         mb
     }
 
+    // 修改器可能改变了地图，保证玩家角色、护身符和怪物都位于可以到达的图块上
+    fn finalize(&mut self) {
+        self.seal_border();
+        self.fix_player_start();
+        let dijkstra_map = DijkstraMap::new(
+            self.map.width,
+            self.map.height,
+            &[self.map.point2d_to_index(self.player_start)],
+            &self.map,
+            1024.0,
+        );
+        let reachable = |pt: &Point| *pt == self.player_start
+            || dijkstra_map.map[self.map.point2d_to_index(*pt)] < 2000.0;
+        if !reachable(&self.amulet_start) {
+            self.amulet_start = self.find_most_distant();
+        }
+        let player_start = self.player_start;
+        self.monster_spawns.retain(|pt| *pt != player_start && reachable(pt));
+        self.vault_spawns.retain(|(pt, _)| *pt != player_start && reachable(pt));
+    }
+
+    // 有些生成器会把地图边缘挖成空地，重新用石墙围住整张地图
+    fn seal_border(&mut self) {
+        let (width, height) = (self.map.width, self.map.height);
+        for x in 0..width {
+            let (top, bottom) = (self.map.idx(x, 0), self.map.idx(x, height - 1));
+            self.map.tiles[top] = TileType::Wall;
+            self.map.tiles[bottom] = TileType::Wall;
+        }
+        for y in 0..height {
+            let (left, right) = (self.map.idx(0, y), self.map.idx(width - 1, y));
+            self.map.tiles[left] = TileType::Wall;
+            self.map.tiles[right] = TileType::Wall;
+        }
+    }
+
+    // 起始位置不是空地时，移动到离它最近的空地上
+    fn fix_player_start(&mut self) {
+        if self.map.can_enter_tile(self.player_start) {
+            return;
+        }
+        let start = self.player_start;
+        let closest = self.map.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| **t == TileType::Floor)
+            .map(|(idx, _)| (idx, DistanceAlg::Pythagoras.distance2d(start, self.map.index_to_point2d(idx))))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|(idx, _)| idx)
+            .expect("No floor left on the map");
+        self.player_start = self.map.index_to_point2d(closest);
+    }
+
+    // 记录当前地图的快照
+    fn take_snapshot(&mut self) {
+        self.history.push(self.map.clone());
+    }
+
     fn fill(&mut self, tile: TileType) {
         // 填充石墙
         self.map.tiles.iter_mut().for_each(|t| *t = tile);
@@ -60,9 +152,9 @@ impl MapBuilder {
 
     fn find_most_distant(&self) -> Point {
         let dijkstra_map = DijkstraMap::new(
-            SCREEN_WIDTH,
-            SCREEN_HEIGHT,
-            &vec![self.map.point2d_to_index(self.player_start)],
+            self.map.width,
+            self.map.height,
+            &[self.map.point2d_to_index(self.player_start)],
             &self.map,
             1024.0,
         );
@@ -78,11 +170,34 @@ impl MapBuilder {
         )
     }
 
+    // 巡逻怪物的路点：可以到达的房间中心，没有房间的地图使用怪物的出生点
+    pub fn patrol_points(&self) -> Vec<Point> {
+        if self.rooms.is_empty() {
+            return self.monster_spawns.clone();
+        }
+        let dijkstra_map = DijkstraMap::new(
+            self.map.width,
+            self.map.height,
+            &[self.map.point2d_to_index(self.player_start)],
+            &self.map,
+            1024.0,
+        );
+        self.rooms.iter()
+            .map(|room| room.center())
+            .filter(|pt| self.map.in_bounds(*pt) && dijkstra_map.map[self.map.point2d_to_index(*pt)] < 2000.0)
+            .collect()
+    }
+
     fn build_random_rooms(&mut self, rng: &mut RandomNumberGenerator) {
-        while self.rooms.len() < NUM_ROOMS {
+        let default_tiles = (DEFAULT_MAP_WIDTH * DEFAULT_MAP_HEIGHT) as usize;
+        let num_rooms = usize::max(NUM_ROOMS, NUM_ROOMS * self.map.tiles.len() / default_tiles);
+        // 地图较小时可能放不下所有的房间，尝试一定次数之后停止
+        let mut attempts = 0;
+        while self.rooms.len() < num_rooms && attempts < MAX_ROOM_ATTEMPTS {
+            attempts += 1;
             let room = Rect::with_size(
-                rng.range(1, SCREEN_WIDTH - 10),
-                rng.range(1, SCREEN_HEIGHT - 10),
+                rng.range(1, self.map.width - 10),
+                rng.range(1, self.map.height - 10),
                 rng.range(2, 10),
                 rng.range(2, 10),
             );
@@ -96,12 +211,13 @@ impl MapBuilder {
             // 填充地板，开凿房间
             if !overlap {
                 room.for_each(|p| {
-                    if p.x > 0 && p.x < SCREEN_WIDTH && p.y > 0 && p.y < SCREEN_HEIGHT {
-                        let idx = map_idx(p.x, p.y);
+                    if p.x > 0 && p.x < self.map.width && p.y > 0 && p.y < self.map.height {
+                        let idx = self.map.idx(p.x, p.y);
                         self.map.tiles[idx] = TileType::Floor;
                     }
                 });
-                self.rooms.push(room)
+                self.rooms.push(room);
+                self.take_snapshot();
             }
         }
     }
@@ -130,7 +246,7 @@ impl MapBuilder {
     fn build_corridors(&mut self, rng: &mut RandomNumberGenerator) {
         let mut rooms = self.rooms.clone();
         // 按照各个房间的中心点的位置对房间进行排序，避免出现连接两个较远房间的蛇形走廊
-        rooms.sort_by(|a, b| a.center().x.cmp(&b.center().x));
+        rooms.sort_by_key(|a| a.center().x);
 
         for (i, room) in rooms.iter().enumerate().skip(1) {
             let prev = rooms[i - 1].center();
@@ -144,19 +260,30 @@ impl MapBuilder {
                 self.apply_vertical_tunnel(prev.y, new.y, prev.x);
                 self.apply_horizontal_tunnel(prev.x, new.x, new.y);
             }
+            self.take_snapshot();
         }
     }
 
-    // 选出能够放置50个怪物的位置
-    fn spawn_monsters(&self, start: &Point, rng: &mut RandomNumberGenerator) -> Vec<Point> {
-        // 怪物数量为50个
-        const NUM_MONSTERS : usize = 50;
-        // 过滤出空地并且与玩家起始位置距离大于10的图块
+    // 选出最多count个能够放置怪物和物品的位置
+    fn spawn_monsters(&self, start: &Point, rng: &mut RandomNumberGenerator, count: usize) -> Vec<Point> {
+        let dijkstra_map = DijkstraMap::new(
+            self.map.width,
+            self.map.height,
+            &[self.map.point2d_to_index(*start)],
+            &self.map,
+            1024.0,
+        );
+        // 过滤出可以从玩家起始位置到达、并且与玩家起始位置距离大于10的空地；
+        // 无法到达的出生点和地图边缘（最后会被封上石墙）的出生点在生成结束时会被移除，让这一层的怪物和物品变少
+        let (width, height) = (self.map.width, self.map.height);
+        let inside = |pt: Point| pt.x > 0 && pt.y > 0 && pt.x < width - 1 && pt.y < height - 1;
         let mut spawnable_tiles : Vec<Point> = self.map.tiles
             .iter()
             .enumerate()
             .filter(|(idx, t)|
                 **t == TileType::Floor &&
+                    dijkstra_map.map[*idx] < 2000.0 &&
+                    inside(self.map.index_to_point2d(*idx)) &&
                     DistanceAlg::Pythagoras.distance2d(
                         *start,
                         self.map.index_to_point2d(*idx)
@@ -166,10 +293,13 @@ impl MapBuilder {
             .collect();
 
         let mut spawns = Vec::new();
-        for _ in 0 .. NUM_MONSTERS {
+        // 空地不足count个时，每块空地上都放置一个怪物或物品
+        while spawns.len() < count {
             // 生成一个怪物的出生点坐标编号
-            let target_index = rng.random_slice_index(&spawnable_tiles).unwrap();
-            spawns.push(spawnable_tiles[target_index].clone());
+            let Some(target_index) = rng.random_slice_index(&spawnable_tiles) else {
+                break;
+            };
+            spawns.push(spawnable_tiles[target_index]);
             // 已选过的图块则从待选区移除
             spawnable_tiles.remove(target_index);
         }
@@ -182,5 +312,11 @@ impl MapBuilder {
  * Send：可以在不同的线程之间传递、转移变量
  */
 pub trait MapTheme: Sync + Send {
+    // 主题名称，保存游戏时用来记录当前使用的主题
+    fn name(&self) -> &str;
+
     fn tile_to_render(&self, tile_type: TileType) -> FontCharType;
+
+    // 图块的前景色和背景色，visible表示图块是否在玩家视野中，否则是记忆中已揭示的图块
+    fn tile_colors(&self, tile_type: TileType, visible: bool) -> ColorPair;
 }
\ No newline at end of file
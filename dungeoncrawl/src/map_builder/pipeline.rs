@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::fs::File;
+use ron::de::from_reader;
+use serde::Deserialize;
+use crate::prelude::*;
+use super::{MapArchitect, MapModifier};
+use super::empty::EmptyArchitect;
+use super::rooms::RoomsArchitect;
+use super::automata::{CellularAutomataArchitect, CellularSmoothing};
+use super::drunkard::DrunkardsWalkArchitect;
+use super::bsp::BspArchitect;
+use super::cull::CullUnreachable;
+use super::doors::{AddDoors, SecretDoors};
+use super::traps::AddTraps;
+use super::water::AddWater;
+use super::prefab::PrefabStamper;
+
+// 生成初始地图的算法
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum ArchitectKind {
+    Empty,
+    Rooms,
+    CellularAutomata,
+    DrunkardsWalk,
+    Bsp,
+}
+
+impl ArchitectKind {
+    // 所有的初始生成器，用于逐一检查生成的地图
+    pub const ALL: [ArchitectKind; 5] = [
+        ArchitectKind::Empty,
+        ArchitectKind::Rooms,
+        ArchitectKind::CellularAutomata,
+        ArchitectKind::DrunkardsWalk,
+        ArchitectKind::Bsp,
+    ];
+
+    pub(super) fn architect(self) -> Box<dyn MapArchitect> {
+        match self {
+            ArchitectKind::Empty => Box::new(EmptyArchitect{}),
+            ArchitectKind::Rooms => Box::new(RoomsArchitect{}),
+            ArchitectKind::CellularAutomata => Box::new(CellularAutomataArchitect{}),
+            ArchitectKind::DrunkardsWalk => Box::new(DrunkardsWalkArchitect{}),
+            ArchitectKind::Bsp => Box::new(BspArchitect{}),
+        }
+    }
+}
+
+// 在初始地图上依次执行的修改器
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum ModifierKind {
+    // 运行指定次数的元胞自动机迭代
+    CellularSmoothing(usize),
+    // 删除无法到达的区域
+    CullUnreachable,
+    // 在走廊进入房间的地方放置门
+    AddDoors,
+    // 把指定数量的门换成暗门
+    SecretDoors(usize),
+    // 放置指定数量的隐藏陷阱
+    AddTraps(usize),
+    // 挖出指定数量的浅水池
+    AddWater(usize),
+    // 放置金库
+    Prefabs,
+}
+
+impl ModifierKind {
+    pub(super) fn modifier(self, depth: usize, prefabs: &Prefabs) -> Box<dyn MapModifier + '_> {
+        match self {
+            ModifierKind::CellularSmoothing(iterations) => Box::new(CellularSmoothing { iterations }),
+            ModifierKind::CullUnreachable => Box::new(CullUnreachable{}),
+            ModifierKind::AddDoors => Box::new(AddDoors{}),
+            ModifierKind::SecretDoors(count) => Box::new(SecretDoors { count }),
+            ModifierKind::AddTraps(count) => Box::new(AddTraps { count }),
+            ModifierKind::AddWater(pools) => Box::new(AddWater { pools }),
+            ModifierKind::Prefabs => Box::new(PrefabStamper { depth, prefabs }),
+        }
+    }
+}
+
+// 一条地图生成流水线：一个初始生成器，后面跟着若干个修改器
+#[derive(Clone, Debug, Deserialize)]
+pub struct BuildChain {
+    // 流水线的名称，便于在配置文件和地图生成调试画面中区分
+    pub name: String,
+    pub architect: ArchitectKind,
+    pub modifiers: Vec<ModifierKind>,
+    // 生成的地图尺寸，省略时使用默认尺寸
+    #[serde(default = "default_width")]
+    pub width: i32,
+    #[serde(default = "default_height")]
+    pub height: i32,
+}
+
+fn default_width() -> i32 {
+    DEFAULT_MAP_WIDTH
+}
+
+fn default_height() -> i32 {
+    DEFAULT_MAP_HEIGHT
+}
+
+// 地图的最小尺寸，更小的地图放不下房间和金库
+const MIN_MAP_WIDTH: i32 = 30;
+const MIN_MAP_HEIGHT: i32 = 20;
+
+impl BuildChain {
+    // 只包含一个初始生成器、使用默认尺寸的流水线
+    pub fn architect_only(architect: ArchitectKind) -> Self {
+        Self {
+            name: format!("{:?}", architect),
+            architect,
+            modifiers: Vec::new(),
+            width: DEFAULT_MAP_WIDTH,
+            height: DEFAULT_MAP_HEIGHT,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.width < MIN_MAP_WIDTH || self.height < MIN_MAP_HEIGHT {
+            return Err(format!("chain {} is smaller than {}x{}", self.name, MIN_MAP_WIDTH, MIN_MAP_HEIGHT));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MapGen {
+    pub chains: Vec<BuildChain>,
+}
+
+impl MapGen {
+    // 加载地图生成配置
+    pub fn load() -> Self {
+        let file = File::open("resources/mapgen.ron").expect("Failed opening file");
+        let mapgen: Self = from_reader(file).expect("Unable to load map generation chains");
+        mapgen.chains.iter().for_each(|c| c.validate().expect("Invalid map generation chain"));
+        mapgen
+    }
+
+    // 按照战役中这一层设置的权重随机选择一条流水线
+    pub fn random_chain(&self, rng: &mut RandomNumberGenerator, weights: &HashMap<String, i32>) -> BuildChain {
+        let mut available_chains = Vec::new();
+        self.chains
+            .iter()
+            .for_each(|c| {
+                for _ in 0..weights.get(&c.name).copied().unwrap_or(0) {
+                    available_chains.push(c);
+                }
+            });
+        match rng.random_slice_entry(&available_chains) {
+            Some(chain) => (*chain).clone(),
+            None => {
+                eprintln!("Warning: no map generation chain in {:?}", weights.keys().collect::<Vec<_>>());
+                BuildChain {
+                    modifiers: vec![ModifierKind::Prefabs],
+                    ..BuildChain::architect_only(ArchitectKind::Rooms)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_chain_builds_a_map_with_a_reachable_exit() {
+        let prefabs = Prefabs::load();
+        for chain in MapGen::load().chains {
+            for seed in 0..5 {
+                let mut rng = GameSeed(seed).rng();
+                let mb = MapBuilder::build(&chain, &mut rng, 0, CHECK_SPAWN_POINTS, &prefabs);
+                let dijkstra_map = DijkstraMap::new(
+                    mb.map.width, mb.map.height, &[mb.map.point2d_to_index(mb.player_start)], &mb.map, 1024.0);
+                assert!(mb.map.can_enter_tile(mb.player_start), "{} seed {}", chain.name, seed);
+                assert!(dijkstra_map.map[mb.map.point2d_to_index(mb.amulet_start)] < 2000.0,
+                        "{} seed {}: exit is unreachable", chain.name, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn bsp_rooms_have_doors() {
+        let chain = BuildChain::architect_only(ArchitectKind::Bsp);
+        let mb = MapBuilder::build(&chain, &mut GameSeed(3).rng(), 0, CHECK_SPAWN_POINTS, &Prefabs::default());
+        assert!(mb.rooms.len() > 4);
+        assert!(mb.map.tiles.contains(&TileType::Door));
+    }
+}
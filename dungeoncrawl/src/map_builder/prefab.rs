@@ -1,56 +1,212 @@
+use std::collections::HashMap;
+use std::fs::File;
+use ron::de::from_reader;
+use serde::Deserialize;
 use crate::prelude::*;
+use super::MapModifier;
 
-// -表示空地，#表示石墙，M表示怪物
-const FORTRESS: (&str, i32, i32) = ("
-------------
----######---
----#----#---
----#-M--#---
--###----###-
---M------M--
--###----###-
----#----#---
----#----#---
----######---
-------------
-", 12, 11);
-
-pub fn apply_prefab(mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
-    // 表示金库的位置
-    let mut placement = None;
+// 金库图例中的一个字符代表的内容
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum VaultTile {
+    // 石墙
+    Wall,
+    // 空地
+    Floor,
+    // 空地，并放置一个当前层数可以出现的随机怪物
+    Monster,
+    // 空地，并放置一个当前层数可以出现的随机物品
+    Item,
+    // 空地，并放置template.ron中指定名字的实体
+    Template(String),
+    // 楼梯（最后一层是亚拉的护身符）放在这里
+    Exit,
+}
+
+// 金库中需要生成的实体
+#[derive(Clone, Debug, PartialEq)]
+pub enum VaultSpawn {
+    Monster,
+    Item,
+    Template(String),
+}
+
+// 金库的放置规则，距离都是从起点出发的路径长度，使用迪杰斯特拉图计算
+#[derive(Clone, Debug, Deserialize)]
+pub enum Placement {
+    // 金库中至少有一个图块与玩家起始位置的距离在min~max之间
+    AwayFromPlayer { min: f32, max: f32 },
+    // 金库中至少有一个图块与楼梯（护身符）的距离不超过max，用来把守出口
+    NearExit { max: f32 },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Vault {
+    pub name: String,
+    // 金库的布局，每行一个字符串，行首和行尾的空白会被忽略
+    pub layout: String,
+    // 布局中每个字符的含义
+    pub legend: HashMap<char, VaultTile>,
+    // 是否允许随机旋转90°、180°或270°
+    pub rotate: bool,
+    // 是否允许随机左右翻转
+    pub mirror: bool,
+    // 金库可以出现的地下城层数（从0开始）以及对应的出现频率
+    pub frequency: HashMap<usize, i32>,
+    pub placement: Placement,
+}
+
+impl Vault {
+    // 将布局转换为字符网格
+    fn grid(&self) -> Vec<Vec<char>> {
+        self.layout
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| line.chars().collect())
+            .collect()
+    }
+
+    // 检查布局是否为矩形，并且所有字符都在图例中
+    fn validate(&self) -> Result<(), String> {
+        let grid = self.grid();
+        let width = grid.first().map_or(0, |row| row.len());
+        if width == 0 || grid.iter().any(|row| row.len() != width) {
+            return Err(format!("vault {} is not a rectangle", self.name));
+        }
+        match grid.iter().flatten().find(|c| !self.legend.contains_key(c)) {
+            Some(c) => Err(format!("vault {} uses [{}] which is not in its legend", self.name, c)),
+            None => Ok(()),
+        }
+    }
+}
+
+// 金库配置，启动时只加载和检查一次，生成每一层时使用同一份
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Prefabs {
+    // 每一层最多放置的金库数量
+    pub vaults_per_level: usize,
+    pub vaults: Vec<Vault>,
+}
 
+impl Prefabs {
+    // 加载金库文件
+    pub fn load() -> Self {
+        let file = File::open("resources/prefabs.ron").expect("Failed opening file");
+        let prefabs: Self = from_reader(file).expect("Unable to load prefabs");
+        prefabs.vaults.iter().for_each(|v| v.validate().expect("Invalid prefab"));
+        prefabs
+    }
+}
+
+// 旋转（顺时针90°的次数）和翻转字符网格
+fn transform(grid: Vec<Vec<char>>, rotations: i32, mirror: bool) -> Vec<Vec<char>> {
+    let mut grid = grid;
+    for _ in 0..rotations {
+        let height = grid.len();
+        let width = grid[0].len();
+        grid = (0..width)
+            .map(|x| (0..height).rev().map(|y| grid[y][x]).collect())
+            .collect();
+    }
+    if mirror {
+        grid.iter_mut().for_each(|row| row.reverse());
+    }
+    grid
+}
+
+// 放置金库的修改器
+pub struct PrefabStamper<'a> {
+    // 地下城的层数，从0开始
+    pub depth: usize,
+    pub prefabs: &'a Prefabs,
+}
+
+impl MapModifier for PrefabStamper<'_> {
+    fn modify(&mut self, mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
+        apply_prefab(mb, rng, self.depth, self.prefabs);
+    }
+}
+
+// 按照金库在当前层数的出现频率，放置若干个金库
+fn apply_prefab(mb: &mut MapBuilder, rng: &mut RandomNumberGenerator, depth: usize, prefabs: &Prefabs) {
+    // 获得可用金库的列表，按照各个金库的频率数保存在列表中
+    let mut available_vaults = Vec::new();
+    prefabs.vaults
+        .iter()
+        .for_each(|v| {
+            for _ in 0..v.frequency.get(&depth).copied().unwrap_or(0) {
+                available_vaults.push(v);
+            }
+        });
+    // 已经放置的金库所在的区域
+    let mut placed: Vec<Rect> = Vec::new();
+    for _ in 0..prefabs.vaults_per_level {
+        if let Some(vault) = rng.random_slice_entry(&available_vaults) {
+            if let Some(area) = place_vault(mb, rng, vault, &placed) {
+                placed.push(area);
+                mb.take_snapshot();
+            }
+        }
+    }
+}
+
+// 尝试放置一个金库，成功时返回金库所在的区域
+fn place_vault(mb: &mut MapBuilder,
+               rng: &mut RandomNumberGenerator,
+               vault: &Vault,
+               placed: &[Rect]) -> Option<Rect> {
+    let rotations = if vault.rotate { rng.range(0, 4) } else { 0 };
+    let mirror = vault.mirror && rng.range(0, 2) == 1;
+    let grid = transform(vault.grid(), rotations, mirror);
+    let (width, height) = (grid[0].len() as i32, grid.len() as i32);
+    // 金库比地图还大（不算边缘的石墙）时无法放置
+    if width > mb.map.width - 2 || height > mb.map.height - 2 {
+        return None;
+    }
+
+    let (start, min, max) = match vault.placement {
+        Placement::AwayFromPlayer { min, max } => (mb.player_start, min, max),
+        Placement::NearExit { max } => (mb.amulet_start, 0.0, max),
+    };
     let dijkstra_map = DijkstraMap::new(
-        SCREEN_WIDTH,
-        SCREEN_HEIGHT,
-        &vec![mb.map.point2d_to_index(mb.player_start)],
+        mb.map.width,
+        mb.map.height,
+        &[mb.map.point2d_to_index(start)],
         &mb.map,
         1024.0,
     );
 
+    // 表示金库的位置
+    let mut placement = None;
     // 尝试次数
     let mut attempts = 0;
     while placement.is_none() && attempts < 10 {
-        // 创建一个和金库一样大小的矩形
+        // 创建一个和金库一样大小的矩形，不覆盖地图边缘的石墙
         let dimensions =
             Rect::with_size(
-                rng.range(0, SCREEN_WIDTH - FORTRESS.1),
-                rng.range(0, SCREEN_HEIGHT - FORTRESS.2),
-                FORTRESS.1,
-                FORTRESS.2);
-
-        let mut can_place = false;
-        // 确保玩家角色不会从金库中开始游戏，并且所有图块都与玩家角色的距离在20~2000范围内
-        dimensions.for_each(|pt| {
-            let idx = mb.map.point2d_to_index(pt);
-            let distance = dijkstra_map.map[idx];
-            if distance < 2000.0 && distance > 20.0 && mb.amulet_start != pt {
-                can_place = true;
-            }
-        });
+                rng.range(1, mb.map.width - width),
+                rng.range(1, mb.map.height - height),
+                width,
+                height);
+
+        // 金库不能覆盖玩家起始位置、楼梯和其它金库
+        let mut can_place = !dimensions.point_in_rect(mb.player_start)
+            && !dimensions.point_in_rect(mb.amulet_start)
+            && !placed.iter().any(|r| r.intersect(&dimensions));
+        if can_place {
+            // 至少有一个图块满足放置规则，保证金库与地图中可到达的区域相连
+            can_place = false;
+            dimensions.for_each(|pt| {
+                let distance = dijkstra_map.map[mb.map.point2d_to_index(pt)];
+                if distance < 2000.0 && distance >= min && distance <= max {
+                    can_place = true;
+                }
+            });
+        }
 
         if can_place {
             // 金库可以放置在地图的这个位置上
-            placement = Some(Point::new(dimensions.x1, dimensions.y1));
+            placement = Some(dimensions);
             // 删除所有在金库内的怪物
             let points = dimensions.point_set();
             mb.monster_spawns.retain(|pt| !points.contains(pt));
@@ -58,27 +214,48 @@ pub fn apply_prefab(mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
         attempts += 1;
     }
 
-    if let Some(placement) = placement {
-        let string_vec: Vec<char> = FORTRESS.0
-            .chars().filter(|a| *a != '\r' && *a != '\n')
-            .collect();
-        let mut i = 0;
-        for ty in placement.y..placement.y + FORTRESS.2 {
-            for tx in placement.x..placement.x + FORTRESS.1 {
-                let idx = map_idx(tx, ty);
-                let c = string_vec[i];
-                match c {
-                    // M表示怪物，-表示空地，#表示石墙，
-                    'M' => {
-                        mb.map.tiles[idx] = TileType::Floor;
-                        mb.monster_spawns.push(Point::new(tx, ty));
-                    }
-                    '-' => mb.map.tiles[idx] = TileType::Floor,
-                    '#' => mb.map.tiles[idx] = TileType::Wall,
-                    _ => println!("No idea what to do with [{}]", c)
+    let area = placement?;
+    for (y, row) in grid.iter().enumerate() {
+        for (x, c) in row.iter().enumerate() {
+            let pt = Point::new(area.x1 + x as i32, area.y1 + y as i32);
+            let idx = mb.map.point2d_to_index(pt);
+            let tile = &vault.legend[c];
+            // 除了石墙，其余的图块都是空地
+            mb.map.tiles[idx] = if *tile == VaultTile::Wall { TileType::Wall } else { TileType::Floor };
+            match tile {
+                VaultTile::Wall | VaultTile::Floor => {}
+                VaultTile::Monster => mb.vault_spawns.push((pt, VaultSpawn::Monster)),
+                VaultTile::Item => mb.vault_spawns.push((pt, VaultSpawn::Item)),
+                VaultTile::Template(name) => mb.vault_spawns.push((pt, VaultSpawn::Template(name.clone()))),
+                VaultTile::Exit => mb.amulet_start = pt,
+            }
+        }
+    }
+    Some(area)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vaults_rotate_and_mirror() {
+        let grid = vec![vec!['a', 'b', 'c'], vec!['d', 'e', 'f']];
+        assert_eq!(transform(grid.clone(), 1, false), vec![vec!['d', 'a'], vec!['e', 'b'], vec!['f', 'c']]);
+        assert_eq!(transform(grid.clone(), 0, true), vec![vec!['c', 'b', 'a'], vec!['f', 'e', 'd']]);
+        assert_eq!(transform(grid.clone(), 4, false), grid);
+    }
+
+    #[test]
+    fn vault_templates_exist() {
+        let templates = Templates::load();
+        for vault in Prefabs::load().vaults {
+            for tile in vault.legend.values() {
+                if let VaultTile::Template(name) = tile {
+                    assert!(templates.entities.iter().any(|t| &t.name == name),
+                            "vault {} uses unknown template {}", vault.name, name);
                 }
-                i += 1;
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,37 +1,184 @@
+use std::collections::HashMap;
+use std::fs::File;
+use ron::de::from_reader;
+use serde::Deserialize;
 use crate::prelude::*;
 
+// 记忆中的门和浅水的颜色
+const SADDLE_BROWN_DIM: (u8, u8, u8) = (70, 35, 10);
+const ROYAL_BLUE_DIM: (u8, u8, u8) = (30, 50, 110);
+
+// 内置的默认主题，主题文件中找不到可用的主题时使用
 pub struct DungeonTheme {}
 
 impl DungeonTheme {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> Box<dyn MapTheme> {
         Box::new(Self{})
     }
 }
 
 impl MapTheme for DungeonTheme {
+    fn name(&self) -> &str {
+        "Dungeon"
+    }
+
     fn tile_to_render(&self, tile_type: TileType) -> FontCharType {
         match tile_type {
-            TileType::Floor => to_cp437('.'),
-            TileType::Wall => to_cp437('#'),
-            TileType::Exit => to_cp437('>')
+            // 隐藏的陷阱和暗门看起来和空地、石墙一样
+            TileType::Floor | TileType::HiddenSpikeTrap | TileType::HiddenTeleportTrap => to_cp437('.'),
+            TileType::Wall | TileType::SecretWall => to_cp437('#'),
+            TileType::Exit => to_cp437('>'),
+            TileType::UpStairs => to_cp437('<'),
+            // 字体中没有门的图案，使用染成棕色的石墙
+            TileType::Door => to_cp437('#'),
+            TileType::OpenDoor => to_cp437('\''),
+            TileType::Water => to_cp437('~'),
+            TileType::SpikeTrap => to_cp437('*'),
+            TileType::TeleportTrap => to_cp437('&'),
+        }
+    }
+
+    fn tile_colors(&self, tile_type: TileType, visible: bool) -> ColorPair {
+        match (tile_type, visible) {
+            (TileType::Door | TileType::OpenDoor, true) => ColorPair::new(SADDLEBROWN, BLACK),
+            (TileType::Door | TileType::OpenDoor, false) => ColorPair::new(SADDLE_BROWN_DIM, BLACK),
+            (TileType::Water, true) => ColorPair::new(ROYALBLUE, BLACK),
+            (TileType::Water, false) => ColorPair::new(ROYAL_BLUE_DIM, BLACK),
+            (TileType::SpikeTrap, true) => ColorPair::new(RED, BLACK),
+            (TileType::TeleportTrap, true) => ColorPair::new(MAGENTA, BLACK),
+            (_, true) => ColorPair::new(WHITE, BLACK),
+            (_, false) => ColorPair::new(DARK_GRAY, BLACK),
         }
     }
 }
 
-pub struct ForestTheme {}
+// 主题文件中的颜色，使用HTML格式书写，例如："#FF8800"
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct HexColor(pub RGB);
+
+impl TryFrom<String> for HexColor {
+    type Error = String;
+
+    fn try_from(code: String) -> Result<Self, Self::Error> {
+        RGB::from_hex(&code)
+            .map(HexColor)
+            .map_err(|e| format!("invalid colour {}: {:?}", code, e))
+    }
+}
+
+// 玩家视野中的图块使用的字符和颜色
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct TileStyle {
+    pub glyph: char,
+    // 前景色
+    pub fg: HexColor,
+    // 背景色
+    pub bg: HexColor,
+}
+
+// 已揭示但不在视野中的图块使用的颜色，通常比视野中的颜色更暗
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct TileColors {
+    pub fg: HexColor,
+    pub bg: HexColor,
+}
+
+// 从主题文件读取的主题风格
+#[derive(Clone, Debug, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    // 每种图块在视野中的渲染方式
+    pub tiles: HashMap<TileType, TileStyle>,
+    // 每种图块在记忆中的颜色
+    pub remembered: HashMap<TileType, TileColors>,
+}
+
+impl MapTheme for Theme {
+    fn name(&self) -> &str {
+        &self.name
+    }
 
-impl MapTheme for ForestTheme {
     fn tile_to_render(&self, tile_type: TileType) -> FontCharType {
-        match tile_type {
-            TileType::Floor => to_cp437(';'),
-            TileType::Wall => to_cp437('"'),
-            TileType::Exit => to_cp437('>')
+        match self.tiles.get(&tile_type) {
+            Some(style) => to_cp437(style.glyph),
+            // 主题中缺少的图块使用默认主题的字符
+            None => DungeonTheme{}.tile_to_render(tile_type),
+        }
+    }
+
+    fn tile_colors(&self, tile_type: TileType, visible: bool) -> ColorPair {
+        let colors = if visible {
+            self.tiles.get(&tile_type).map(|style| (style.fg, style.bg))
+        } else {
+            self.remembered.get(&tile_type).map(|colors| (colors.fg, colors.bg))
+        };
+        match colors {
+            Some((fg, bg)) => ColorPair::new(fg.0, bg.0),
+            None => DungeonTheme{}.tile_colors(tile_type, visible),
         }
     }
 }
 
-impl ForestTheme {
-    pub fn new() -> Box<dyn MapTheme> {
-        Box::new(Self{})
+// 主题配置，启动时只加载一次，生成每一层和读取存档时使用同一份
+#[derive(Clone, Debug, Deserialize)]
+pub struct Themes {
+    pub themes: Vec<Theme>,
+}
+
+impl Themes {
+    // 加载主题文件
+    pub fn load() -> Self {
+        let file = File::open("resources/themes.ron").expect("Failed opening file");
+        from_reader(file).expect("Unable to load themes")
+    }
+
+    // 按照战役中这一层设置的权重随机选择一个主题
+    pub fn random_theme(&self, rng: &mut RandomNumberGenerator, weights: &HashMap<String, i32>) -> Box<dyn MapTheme> {
+        let mut available_themes = Vec::new();
+        self.themes
+            .iter()
+            .for_each(|t| {
+                for _ in 0..weights.get(&t.name).copied().unwrap_or(0) {
+                    available_themes.push(t);
+                }
+            });
+        match rng.random_slice_entry(&available_themes) {
+            Some(theme) => Box::new((*theme).clone()),
+            None => {
+                eprintln!("Warning: no map theme in {:?}", weights.keys().collect::<Vec<_>>());
+                DungeonTheme::new()
+            }
+        }
     }
-}
\ No newline at end of file
+
+    // 根据主题名称还原主题风格，用于读取存档
+    pub fn theme_by_name(&self, name: &str) -> Box<dyn MapTheme> {
+        match self.themes.iter().find(|t| t.name == name) {
+            Some(theme) => Box::new(theme.clone()),
+            None => {
+                eprintln!("Warning: unknown map theme {}, using the default theme", name);
+                DungeonTheme::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use super::Themes;
+
+    #[test]
+    fn every_theme_styles_every_tile_type() {
+        let themes = Themes::load();
+        for theme in &themes.themes {
+            for tile_type in [TileType::Wall, TileType::Floor, TileType::Exit, TileType::UpStairs, TileType::Door, TileType::OpenDoor,
+                              TileType::Water, TileType::SpikeTrap, TileType::TeleportTrap] {
+                assert!(theme.tiles.contains_key(&tile_type), "{} has no {:?}", theme.name, tile_type);
+                assert!(theme.remembered.contains_key(&tile_type), "{} has no remembered {:?}", theme.name, tile_type);
+            }
+        }
+    }
+}
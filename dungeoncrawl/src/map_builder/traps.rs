@@ -0,0 +1,32 @@
+use crate::prelude::*;
+use super::MapModifier;
+
+// 在随机的空地上放置隐藏的陷阱，其中三分之一是传送陷阱，其余是尖刺陷阱
+pub struct AddTraps {
+    pub count: usize,
+}
+
+impl MapModifier for AddTraps {
+    fn modify(&mut self, mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
+        // 不在玩家起始位置和楼梯上放置陷阱
+        let mut candidates: Vec<usize> = mb.map.tiles
+            .iter()
+            .enumerate()
+            .filter(|(idx, t)| **t == TileType::Floor
+                && *idx != mb.map.point2d_to_index(mb.player_start)
+                && *idx != mb.map.point2d_to_index(mb.amulet_start))
+            .map(|(idx, _)| idx)
+            .collect();
+        for _ in 0..self.count {
+            let Some(n) = rng.random_slice_index(&candidates) else {
+                break;
+            };
+            let idx = candidates.remove(n);
+            mb.map.tiles[idx] = if rng.range(0, 3) == 0 {
+                TileType::HiddenTeleportTrap
+            } else {
+                TileType::HiddenSpikeTrap
+            };
+        }
+    }
+}
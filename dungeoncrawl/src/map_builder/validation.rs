@@ -0,0 +1,267 @@
+use std::fmt;
+use crate::prelude::*;
+use super::pipeline::ArchitectKind;
+
+// 从玩家起始位置到楼梯的最短路径长度，太近的楼梯让这一层失去意义
+pub const MIN_EXIT_DISTANCE: f32 = 10.0;
+// 检查地图时需要的出生点数量，与战役中一层的怪物和物品的总数相当
+pub const CHECK_SPAWN_POINTS: usize = 50;
+// 每个生成器最多保留的失败信息条数
+const MAX_FAILURES: usize = 5;
+
+// 每个初始生成器生成的地图中，可以行走的空地所占比例的合理范围，超出范围说明生成算法出现了退化
+pub fn floor_ratio_bounds(architect: ArchitectKind) -> (f32, f32) {
+    match architect {
+        // 空地图只有边缘是石墙，空地比例只取决于地图尺寸
+        ArchitectKind::Empty => (0.8, 1.0),
+        ArchitectKind::Rooms => (0.1, 0.5),
+        // 玩家所在的洞穴至少要占全图的25%
+        ArchitectKind::CellularAutomata => (0.3, 0.7),
+        // 矿工挖出全图三分之一的空地，封上边缘之后略少一些
+        ArchitectKind::DrunkardsWalk => (0.28, 0.5),
+        ArchitectKind::Bsp => (0.15, 0.5),
+    }
+}
+
+// 可以行走的空地：地板、门和楼梯，暗门、陷阱和浅水不算
+fn is_open_floor(tile: TileType) -> bool {
+    matches!(tile, TileType::Floor | TileType::Door | TileType::OpenDoor | TileType::Exit | TileType::UpStairs)
+}
+
+// 检查一张生成好的地图得到的数据
+#[derive(Clone, Debug)]
+pub struct MapCheck {
+    pub floor_ratio: f32,
+    // 从玩家起始位置到楼梯的路径长度，无法到达时为None
+    pub exit_distance: Option<f32>,
+    pub monsters: usize,
+    // 地图违反的规则
+    pub problems: Vec<String>,
+}
+
+impl MapCheck {
+    // 检查地图是否满足所有规则：楼梯可以到达、边缘的石墙完整、空地比例合理，architect是生成地图的初始生成器
+    pub fn new(mb: &MapBuilder, architect: ArchitectKind) -> Self {
+        let map = &mb.map;
+        let mut problems = Vec::new();
+
+        let floor = map.tiles.iter().filter(|t| is_open_floor(**t)).count();
+        let floor_ratio = floor as f32 / map.tiles.len() as f32;
+        let (min, max) = floor_ratio_bounds(architect);
+        if !(min..=max).contains(&floor_ratio) {
+            problems.push(format!("floor ratio {:.2} is out of bounds", floor_ratio));
+        }
+
+        let broken_border = (0..map.width)
+            .flat_map(|x| [Point::new(x, 0), Point::new(x, map.height - 1)])
+            .chain((0..map.height).flat_map(|y| [Point::new(0, y), Point::new(map.width - 1, y)]))
+            .find(|pt| map.tiles[map.point2d_to_index(*pt)] != TileType::Wall);
+        if let Some(pt) = broken_border {
+            problems.push(format!("border is open at {:?}", pt));
+        }
+
+        if !map.can_enter_tile(mb.player_start) {
+            problems.push(format!("player starts inside a wall at {:?}", mb.player_start));
+        }
+
+        let dijkstra_map = DijkstraMap::new(
+            map.width,
+            map.height,
+            &[map.point2d_to_index(mb.player_start)],
+            map,
+            1024.0,
+        );
+        let distance = dijkstra_map.map[map.point2d_to_index(mb.amulet_start)];
+        // 迪杰斯特拉图不会把起点设置为0，楼梯和起点重合时距离也是0
+        let exit_distance = if mb.amulet_start == mb.player_start {
+            Some(0.0)
+        } else if distance < 2000.0 {
+            Some(distance)
+        } else {
+            None
+        };
+        match exit_distance {
+            None => problems.push(format!("exit at {:?} is unreachable", mb.amulet_start)),
+            Some(d) if d < MIN_EXIT_DISTANCE => problems.push(format!("exit is only {} tiles away", d)),
+            _ => {}
+        }
+
+        Self {
+            floor_ratio,
+            exit_distance,
+            monsters: mb.monster_spawns.len(),
+            problems,
+        }
+    }
+}
+
+// 一个生成器生成的所有地图的统计数据
+#[derive(Clone, Debug)]
+pub struct ArchitectStats {
+    pub architect: ArchitectKind,
+    pub maps: usize,
+    // 没有通过检查的地图数量
+    pub failed: usize,
+    // 前几张失败地图的种子和原因
+    pub failures: Vec<String>,
+    pub min_floor_ratio: f32,
+    pub max_floor_ratio: f32,
+    total_floor_ratio: f32,
+    total_exit_distance: f32,
+    total_monsters: usize,
+}
+
+impl ArchitectStats {
+    pub fn new(architect: ArchitectKind) -> Self {
+        Self {
+            architect,
+            maps: 0,
+            failed: 0,
+            failures: Vec::new(),
+            min_floor_ratio: f32::MAX,
+            max_floor_ratio: 0.0,
+            total_floor_ratio: 0.0,
+            total_exit_distance: 0.0,
+            total_monsters: 0,
+        }
+    }
+
+    pub fn add(&mut self, seed: GameSeed, check: &MapCheck) {
+        self.maps += 1;
+        self.min_floor_ratio = self.min_floor_ratio.min(check.floor_ratio);
+        self.max_floor_ratio = self.max_floor_ratio.max(check.floor_ratio);
+        self.total_floor_ratio += check.floor_ratio;
+        self.total_exit_distance += check.exit_distance.unwrap_or(0.0);
+        self.total_monsters += check.monsters;
+        if !check.problems.is_empty() {
+            self.failed += 1;
+            if self.failures.len() < MAX_FAILURES {
+                self.failures.push(format!("seed {}: {}", seed.0, check.problems.join(", ")));
+            }
+        }
+    }
+
+    pub fn average_floor_ratio(&self) -> f32 {
+        self.total_floor_ratio / self.maps.max(1) as f32
+    }
+
+    pub fn average_exit_distance(&self) -> f32 {
+        self.total_exit_distance / self.maps.max(1) as f32
+    }
+
+    pub fn average_monsters(&self) -> f32 {
+        self.total_monsters as f32 / self.maps.max(1) as f32
+    }
+}
+
+impl fmt::Display for ArchitectStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<18} maps {:>6}  failed {:>4}  floor {:.2}-{:.2} (avg {:.2})  exit distance {:>5.1}  monsters {:>4.1}",
+               format!("{:?}", self.architect), self.maps, self.failed,
+               self.min_floor_ratio, self.max_floor_ratio, self.average_floor_ratio(),
+               self.average_exit_distance(), self.average_monsters())?;
+        for failure in &self.failures {
+            write!(f, "\n    {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+// 用从0开始的maps个种子，分别让每一个生成器生成地图并检查
+pub fn validate_architects(maps: u64) -> Vec<ArchitectStats> {
+    ArchitectKind::ALL
+        .iter()
+        .map(|architect| {
+            // 只有初始生成器的流水线，结果不受mapgen.ron中修改器和金库的影响
+            let chain = BuildChain::architect_only(*architect);
+            let prefabs = Prefabs::default();
+            let mut stats = ArchitectStats::new(*architect);
+            for seed in 0..maps {
+                let seed = GameSeed(seed);
+                let mb = MapBuilder::build(&chain, &mut seed.rng(), 0, CHECK_SPAWN_POINTS, &prefabs);
+                stats.add(seed, &MapCheck::new(&mb, *architect));
+            }
+            stats
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn every_architect_generates_valid_maps() {
+        for stats in validate_architects(200) {
+            assert_eq!(stats.failed, 0, "\n{}", stats);
+        }
+    }
+
+    #[test]
+    fn an_open_map_fails_the_rooms_floor_ratio() {
+        let chain = BuildChain::architect_only(ArchitectKind::Empty);
+        let mb = MapBuilder::build(&chain, &mut GameSeed(1).rng(), 0, CHECK_SPAWN_POINTS, &Prefabs::default());
+        assert!(MapCheck::new(&mb, ArchitectKind::Empty).problems.is_empty());
+        let check = MapCheck::new(&mb, ArchitectKind::Rooms);
+        assert!(check.problems.iter().any(|p| p.contains("floor ratio")), "{:?}", check.problems);
+    }
+
+    #[test]
+    fn architects_build_maps_of_other_sizes() {
+        for architect in ArchitectKind::ALL {
+            for (width, height) in [(30, 20), (120, 80)] {
+                let chain = BuildChain { width, height, ..BuildChain::architect_only(architect) };
+                for seed in 0..10 {
+                    let mb = MapBuilder::build(&chain, &mut GameSeed(seed).rng(), 0, CHECK_SPAWN_POINTS, &Prefabs::default());
+                    assert_eq!((mb.map.width, mb.map.height), (width, height));
+                    let check = MapCheck::new(&mb, architect);
+                    assert!(check.problems.is_empty(), "{:?} {}x{} seed {}: {:?}",
+                            architect, width, height, seed, check.problems);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_level_can_ask_for_more_than_fifty_monsters_and_items() {
+        let level = CampaignLevel {
+            chains: HashMap::new(),
+            themes: HashMap::new(),
+            monsters: 30,
+            items: 50,
+            bosses: Vec::new(),
+        };
+        let mut rng = GameSeed(1).rng();
+        let chain = BuildChain::architect_only(ArchitectKind::CellularAutomata);
+        let mb = MapBuilder::build(&chain, &mut rng, 0, level.spawn_points(), &Prefabs::default());
+        let mut ecs = World::default();
+        spawn_level(&mut ecs, &mut rng, 0, &level, &mb, &Templates::load(), &mut Uniques::default());
+        assert_eq!(<&Enemy>::query().iter(&ecs).count(), 30);
+        assert_eq!(<&Item>::query().iter(&ecs).count(), 50);
+    }
+
+    #[test]
+    fn a_small_cave_gets_fewer_monsters() {
+        let mut mb = MapBuilder {
+            map: Map::new(DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT),
+            rooms: Vec::new(),
+            monster_spawns: Vec::new(),
+            vault_spawns: Vec::new(),
+            player_start: Point::new(10, 32),
+            amulet_start: Point::zero(),
+            theme: super::super::themes::DungeonTheme::new(),
+            chain: String::new(),
+            history: Vec::new(),
+        };
+        mb.fill(TileType::Wall);
+        Rect::with_size(30, 30, 5, 6).for_each(|pt| {
+            let idx = mb.map.idx(pt.x, pt.y);
+            mb.map.tiles[idx] = TileType::Floor;
+        });
+        // 从起点通往洞穴的走廊，离起点超过10格的有9个图块
+        mb.apply_horizontal_tunnel(10, 29, 32);
+        let spawns = mb.spawn_monsters(&mb.player_start, &mut GameSeed(1).rng(), CHECK_SPAWN_POINTS);
+        assert_eq!(spawns.len(), 39);
+    }
+}
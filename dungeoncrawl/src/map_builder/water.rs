@@ -0,0 +1,43 @@
+use crate::prelude::*;
+use super::MapModifier;
+
+// 每个水池由醉汉漫步走过的空地组成
+const POOL_STEPS: usize = 20;
+
+// 在空地上挖出几个浅水池，浅水可以通过，所以不会影响地图的连通性
+pub struct AddWater {
+    pub pools: usize,
+}
+
+impl MapModifier for AddWater {
+    fn modify(&mut self, mb: &mut MapBuilder, rng: &mut RandomNumberGenerator) {
+        let floor: Vec<Point> = mb.map.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| **t == TileType::Floor)
+            .map(|(idx, _)| mb.map.index_to_point2d(idx))
+            .collect();
+        for _ in 0..self.pools {
+            let Some(mut pos) = rng.random_slice_entry(&floor).copied() else {
+                break;
+            };
+            for _ in 0..POOL_STEPS {
+                let idx = mb.map.point2d_to_index(pos);
+                // 玩家起始位置和楼梯保持干燥
+                if mb.map.tiles[idx] == TileType::Floor && pos != mb.player_start && pos != mb.amulet_start {
+                    mb.map.tiles[idx] = TileType::Water;
+                }
+                // 只在空地和水面上漫步，碰到石墙时原地不动
+                let next = pos + match rng.range(0, 4) {
+                    0 => Point::new(-1, 0),
+                    1 => Point::new(1, 0),
+                    2 => Point::new(0, -1),
+                    _ => Point::new(0, 1),
+                };
+                if mb.map.try_idx(next).is_some_and(|idx| matches!(mb.map.tiles[idx], TileType::Floor | TileType::Water)) {
+                    pos = next;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::prelude::*;
+
+// 最近一次生成地图的过程，地图生成调试画面使用它来回放每一步
+pub struct MapGenHistory {
+    // 使用的流水线名称
+    pub chain: String,
+    // 每一步的地图快照，最后一张是最终的地图
+    pub snapshots: Vec<Map>,
+    // 最终的怪物和金库实体的位置
+    pub spawns: Vec<Point>,
+    pub player_start: Point,
+    pub amulet_start: Point,
+}
+
+impl MapGenHistory {
+    // 从地图构建器中取出生成过程，需要在地图移交给游戏之前调用
+    pub fn take(mb: &mut MapBuilder) -> Self {
+        let mut snapshots = std::mem::take(&mut mb.history);
+        snapshots.push(mb.map.clone());
+        Self {
+            chain: mb.chain.clone(),
+            snapshots,
+            spawns: mb.monster_spawns.iter().copied()
+                .chain(mb.vault_spawns.iter().map(|(pt, _)| *pt))
+                .collect(),
+            player_start: mb.player_start,
+            amulet_start: mb.amulet_start,
+        }
+    }
+}
+
+// 回放时最多播放的帧数，快照太多时跳过一部分
+const MAX_FRAMES: usize = 150;
+
+// 地图生成调试画面，在平视显示区图层上用色块表示图块，显示整张地图
+#[derive(Default)]
+pub struct MapGenViewer {
+    // 当前播放到的快照
+    step: usize,
+}
+
+impl MapGenViewer {
+    pub fn restart(&mut self) {
+        self.step = 0;
+    }
+
+    // 绘制当前的快照并前进一步，按空格键跳到最后一步，返回true表示按下回车键开始游戏
+    pub fn tick(&mut self, ctx: &BTerm, history: &MapGenHistory) -> bool {
+        let last = history.snapshots.len() - 1;
+        let stride = history.snapshots.len().div_ceil(MAX_FRAMES);
+        let mut draw_batch = DrawBatch::new();
+        draw_batch.target(2);
+
+        let map = &history.snapshots[self.step];
+        // 尽量放大地图，比平视显示区图层还大的地图只显示左上角
+        let scale = i32::max(1, i32::min(SCREEN_WIDTH * 2 / map.width, SCREEN_HEIGHT * 2 / map.height));
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let color = match map.tiles[map.idx(x, y)] {
+                    TileType::Wall => RGB::named(DIMGRAY),
+                    TileType::Floor => RGB::from_u8(30, 30, 30),
+                    TileType::Exit | TileType::UpStairs => RGB::named(YELLOW),
+                    TileType::Door | TileType::OpenDoor => RGB::named(SADDLEBROWN),
+                    TileType::Water => RGB::named(ROYALBLUE),
+                    // 调试画面同样显示隐藏的陷阱和暗门
+                    TileType::SpikeTrap | TileType::HiddenSpikeTrap => RGB::named(RED),
+                    TileType::TeleportTrap | TileType::HiddenTeleportTrap => RGB::named(MAGENTA),
+                    TileType::SecretWall => RGB::named(PURPLE),
+                };
+                draw_block(&mut draw_batch, Point::new(x, y), scale, color, ' ');
+            }
+        }
+        // 最后一步叠加显示怪物、玩家角色和护身符的位置
+        if self.step == last {
+            history.spawns.iter().for_each(|pt| draw_block(&mut draw_batch, *pt, scale, RGB::named(RED), 'M'));
+            draw_block(&mut draw_batch, history.amulet_start, scale, RGB::named(GOLD), '>');
+            draw_block(&mut draw_batch, history.player_start, scale, RGB::named(GREEN), '@');
+        }
+
+        draw_batch.print_color(
+            Point::new(1, 0),
+            format!(" Map generation: {}, step {}/{} ", history.chain, self.step + 1, last + 1),
+            ColorPair::new(WHITE, BLACK),
+        );
+        draw_batch.print_color(
+            Point::new(1, SCREEN_HEIGHT * 2 - 1),
+            " Space: skip to the end  Enter: start playing ",
+            ColorPair::new(WHITE, BLACK),
+        );
+        draw_batch.submit(20000).expect("Batch error");
+
+        match ctx.key {
+            Some(VirtualKeyCode::Return) => return true,
+            Some(VirtualKeyCode::Space) => self.step = last,
+            _ => self.step = usize::min(self.step + stride, last),
+        }
+        false
+    }
+}
+
+// 用scale x scale个字符的色块表示一个图块，glyph显示在左上角
+fn draw_block(draw_batch: &mut DrawBatch, pt: Point, scale: i32, color: RGB, glyph: char) {
+    let top_left = Point::new(pt.x * scale, pt.y * scale);
+    if top_left.x >= SCREEN_WIDTH * 2 || top_left.y >= SCREEN_HEIGHT * 2 {
+        return;
+    }
+    for dy in 0..scale {
+        for dx in 0..scale {
+            draw_batch.set(top_left + Point::new(dx, dy), ColorPair::new(BLACK, color), to_cp437(' '));
+        }
+    }
+    draw_batch.set(top_left, ColorPair::new(WHITE, color), to_cp437(glyph));
+}
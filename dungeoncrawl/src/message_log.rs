@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use crate::prelude::*;
+
+// 最多保存的消息条数，更早的消息会被丢弃
+const MAX_ENTRIES: usize = 500;
+// 平视显示区下方的消息面板显示的消息条数
+pub const LOG_PANEL_LINES: usize = 5;
+
+// 一条带颜色的消息
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub text: String,
+    pub color: RGB,
+}
+
+// 消息记录：战斗、使用物品、走上楼梯和进入下一层等游戏事件的文字记录，写入存档
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageLog {
+    pub entries: Vec<LogEntry>,
+    // 完整消息记录界面向上滚动的行数，0表示显示最新的消息，不写入存档
+    #[serde(skip)]
+    pub scroll: usize,
+}
+
+impl MessageLog {
+    // 添加一条消息，首字母自动大写
+    pub fn add<S: ToString>(&mut self, text: S, color: (u8, u8, u8)) {
+        let text = text.to_string();
+        let mut chars = text.chars();
+        let text = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => text,
+        };
+        self.entries.push(LogEntry { text, color: RGB::named(color) });
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+    }
+
+    // 把滚动的行数限制在消息记录的范围内，page是一页显示的消息条数
+    pub fn clamp_scroll(&mut self, page: usize) {
+        self.scroll = usize::min(self.scroll, self.entries.len().saturating_sub(page));
+    }
+}
+
+// 消息中对实体的称呼，玩家角色称为"you"，其余的实体使用名字
+pub fn name_of(ecs: &impl EntityStore, entity: Entity) -> String {
+    match ecs.entry_ref(entity) {
+        Ok(entry) if entry.get_component::<Player>().is_ok() => "you".to_string(),
+        Ok(entry) if entry.get_component::<Unique>().is_ok() => entry.get_component::<Name>()
+            .map(|name| name.0.clone())
+            .unwrap_or_else(|_| "something".to_string()),
+        Ok(entry) => entry.get_component::<Name>()
+            .map(|name| format!("the {}", name.0))
+            .unwrap_or_else(|_| "something".to_string()),
+        Err(_) => "something".to_string(),
+    }
+}
+
+// 独特怪物被消灭时的消息，没有设置时返回None
+pub fn death_message(ecs: &impl EntityStore, entity: Entity) -> Option<String> {
+    ecs.entry_ref(entity)
+        .ok()
+        .and_then(|entry| entry.get_component::<DeathMessage>().ok().map(|message| message.0.clone()))
+}
+
+// 根据主语选择动词的形式，主语是"you"时使用原形，例如"you hit"和"the Goblin hits"
+pub fn verb(subject: &str, base: &str, third_person: &str) -> String {
+    if subject == "you" {
+        base.to_string()
+    } else {
+        third_person.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn the_log_keeps_only_the_latest_entries() {
+        let mut log = MessageLog::default();
+        (0..600).for_each(|n| log.add(format!("message {}", n), WHITE));
+        assert_eq!(log.entries.len(), 500);
+        assert_eq!(log.entries[0].text, "Message 100");
+        log.scroll = 1000;
+        log.clamp_scroll(90);
+        assert_eq!(log.scroll, 410);
+    }
+
+    #[test]
+    fn picking_up_an_item_is_logged() {
+        let mut game = Game::new(GameSeed(4));
+        let pos = <&Point>::query().filter(component::<Player>()).iter(&game.ecs).copied().next().unwrap();
+        game.ecs.push((Item, Name("Healing Potion".to_string()), pos));
+        game.step(Some(VirtualKeyCode::G));
+        let log = game.resources.get::<MessageLog>().unwrap();
+        assert_eq!(log.entries.last().unwrap().text, "You pick up the Healing Potion.");
+    }
+}
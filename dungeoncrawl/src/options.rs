@@ -0,0 +1,67 @@
+use std::env;
+use crate::prelude::*;
+
+// 命令行参数
+pub struct Options {
+    // 随机数种子，未指定时随机生成
+    pub seed: GameSeed,
+    // 无界面模式下模拟的最大回合数，未指定时打开游戏窗口
+    pub headless: Option<u32>,
+    // 无界面模式下按顺序输入的按键脚本文件，未指定时由自动玩家进行游戏
+    pub script: Option<String>,
+    // 把本局游戏的按键录制到这个文件
+    pub record: Option<String>,
+    // 回放这个录像文件，使用录像中的种子
+    pub replay: Option<String>,
+    // 每一层开始之前回放地图生成的过程
+    pub mapgen_debug: bool,
+    // 用这么多个种子检查每一个地图生成器，输出统计数据后退出
+    pub validate_maps: Option<u64>,
+}
+
+impl Options {
+    // 解析命令行参数，例如：dungeoncrawl --seed 12345 --headless 500
+    pub fn from_args() -> Self {
+        let mut options = Self {
+            seed: GameSeed::random(),
+            headless: None,
+            script: None,
+            record: None,
+            replay: None,
+            mapgen_debug: false,
+            validate_maps: None,
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => match args.next().and_then(|s| s.parse().ok()) {
+                    Some(seed) => options.seed = GameSeed(seed),
+                    None => eprintln!("Warning: --seed expects a number"),
+                },
+                "--headless" => match args.next().and_then(|s| s.parse().ok()) {
+                    Some(turns) => options.headless = Some(turns),
+                    None => eprintln!("Warning: --headless expects a number of turns"),
+                },
+                "--script" => match args.next() {
+                    Some(path) => options.script = Some(path),
+                    None => eprintln!("Warning: --script expects a file name"),
+                },
+                "--record" => match args.next() {
+                    Some(path) => options.record = Some(path),
+                    None => eprintln!("Warning: --record expects a file name"),
+                },
+                "--replay" => match args.next() {
+                    Some(path) => options.replay = Some(path),
+                    None => eprintln!("Warning: --replay expects a file name"),
+                },
+                "--mapgen-debug" => options.mapgen_debug = true,
+                "--validate-maps" => match args.next().and_then(|s| s.parse().ok()) {
+                    Some(maps) => options.validate_maps = Some(maps),
+                    None => eprintln!("Warning: --validate-maps expects a number of maps"),
+                },
+                _ => eprintln!("Warning: unknown argument {}", arg),
+            }
+        }
+        options
+    }
+}
@@ -0,0 +1,200 @@
+use std::fs;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use crate::prelude::*;
+
+// 录像格式的版本号，录像结构发生变化时需要递增
+pub const RECORDING_VERSION: u32 = 1;
+
+// 一次输入：玩家在第几个回合按下了哪个键，瞄准模式中还记录了按键时瞄准的图块
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedInput {
+    pub turn: u32,
+    pub key: VirtualKeyCode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<Point>,
+}
+
+/*
+ * 一局游戏的输入录像：记录开局使用的种子，以及等待玩家输入时游戏逻辑收到的每一个按键。
+ * 没有按键的帧、以及不在等待输入时按下的键都不会改变游戏状态，因此不记录。
+ * 用同一个种子开始新游戏并依次回放这些按键，就能完整重现这一局游戏。
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub seed: GameSeed,
+    pub inputs: Vec<RecordedInput>,
+}
+
+impl Recording {
+    pub fn new(seed: GameSeed) -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            seed,
+            inputs: Vec::new(),
+        }
+    }
+
+    // 记录这一帧交给游戏逻辑的按键，回合数取自游戏统计
+    pub fn record(&mut self, game: &Game, key: Option<VirtualKeyCode>) {
+        if let Some(key) = key.filter(|_| game.turn_state().awaits_input()) {
+            let turn = game.resources.get::<GameStats>().unwrap().turns;
+            // 用鼠标选择的目标不经过按键，需要单独记录
+            let target = (game.turn_state() == TurnState::Targeting)
+                .then(|| game.resources.get::<Targeting>().unwrap().cursor);
+            self.inputs.push(RecordedInput { turn, key, target });
+        }
+    }
+
+    pub fn to_ron(&self) -> ron::Result<String> {
+        ron::ser::to_string_pretty(self, PrettyConfig::new().compact_arrays(true))
+    }
+
+    pub fn from_ron(text: &str) -> ron::Result<Self> {
+        let recording: Self = ron::from_str(text)?;
+        // 版本号不一致时拒绝回放，避免用错误的结构解析录像
+        if recording.version != RECORDING_VERSION {
+            return Err(ron::Error::Message(format!(
+                "unsupported recording version {}, expected {}", recording.version, RECORDING_VERSION)));
+        }
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: &str) -> ron::Result<()> {
+        fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> ron::Result<Self> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+
+    pub fn playback(&self) -> Playback {
+        Playback {
+            inputs: self.inputs.clone(),
+            next: 0,
+        }
+    }
+}
+
+// 按录像中的顺序重新输入按键
+pub struct Playback {
+    inputs: Vec<RecordedInput>,
+    next: usize,
+}
+
+impl InputSource for Playback {
+    fn next_key(&mut self, game: &mut Game) -> Option<VirtualKeyCode> {
+        let input = *self.inputs.get(self.next)?;
+        self.next += 1;
+        // 回合数对不上说明游戏过程已经和录制时不同，继续回放但给出提示
+        let turn = game.resources.get::<GameStats>().unwrap().turns;
+        if input.turn != turn {
+            eprintln!("Warning: replay out of sync, key {:?} was recorded on turn {} but the game is on turn {}",
+                     input.key, input.turn, turn);
+        }
+        if let Some(target) = input.target {
+            game.aim_at(target);
+        }
+        Some(input.key)
+    }
+}
+
+// 包装另一个输入来源，把它提供的按键同时写入录像
+pub struct RecordingInput<'a> {
+    pub source: &'a mut dyn InputSource,
+    pub recording: &'a mut Recording,
+}
+
+impl InputSource for RecordingInput<'_> {
+    fn next_key(&mut self, game: &mut Game) -> Option<VirtualKeyCode> {
+        let key = self.source.next_key(game);
+        self.recording.record(game, key);
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    use ron::Value;
+
+    /*
+     * 把游戏转换为便于比较的存档内容：存档中的实体编号是随机生成的UUID，按出现顺序替换成序号；
+     * 视野中的图块保存在HashSet中，顺序不固定，排序后再比较。
+     */
+    fn canonical_save(game: &Game) -> Value {
+        let text = serialize_game(&game.ecs, &game.resources).unwrap();
+        let mut ids: Vec<String> = Vec::new();
+        let mut result = String::new();
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find('"') {
+            let end = start + 1 + rest[start + 1..].find('"').unwrap();
+            let quoted = &rest[start + 1..end];
+            result.push_str(&rest[..=start]);
+            if quoted.len() == 36 && quoted.chars().filter(|c| *c == '-').count() == 4 {
+                let n = ids.iter().position(|id| id == quoted).unwrap_or_else(|| {
+                    ids.push(quoted.to_string());
+                    ids.len() - 1
+                });
+                result.push_str(&format!("entity-{}", n));
+            } else {
+                result.push_str(quoted);
+            }
+            result.push('"');
+            rest = &rest[end + 1..];
+        }
+        result.push_str(rest);
+        let mut value: Value = ron::from_str(&result).unwrap();
+        sort_visible_tiles(&mut value);
+        value
+    }
+
+    fn sort_visible_tiles(value: &mut Value) {
+        match value {
+            Value::Map(map) => map.iter_mut().for_each(|(key, value)| {
+                if let (Value::String(key), Value::Seq(tiles)) = (key, &mut *value) {
+                    if key == "visible_tiles" {
+                        tiles.sort();
+                    }
+                }
+                sort_visible_tiles(value);
+            }),
+            Value::Seq(values) => values.iter_mut().for_each(sort_visible_tiles),
+            Value::Option(Some(value)) => sort_visible_tiles(value),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn replaying_a_recording_gives_the_same_world() {
+        let seed = GameSeed(11);
+        let mut recording = Recording::new(seed);
+        let mut player = AutoPlayer::new();
+        let (recorded, recorded_report) = run_headless(seed, 150, &mut RecordingInput {
+            source: &mut player,
+            recording: &mut recording,
+        });
+        assert!(!recording.inputs.is_empty());
+
+        // 录像经过文件格式往返后再回放
+        let recording = Recording::from_ron(&recording.to_ron().unwrap()).unwrap();
+        let (replayed, replayed_report) = run_headless(recording.seed, 150, &mut recording.playback());
+
+        assert_eq!(recorded_report, replayed_report);
+        assert_eq!(recorded.ecs.len(), replayed.ecs.len());
+        assert_eq!(canonical_save(&recorded), canonical_save(&replayed));
+    }
+
+    #[test]
+    fn a_recording_with_another_version_is_rejected() {
+        let text = Recording::new(GameSeed(11)).to_ron().unwrap().replacen(
+            &format!("version: {}", RECORDING_VERSION),
+            &format!("version: {}", RECORDING_VERSION + 1),
+            1,
+        );
+        assert!(Recording::from_ron(&text).is_err());
+    }
+}
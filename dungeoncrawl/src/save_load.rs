@@ -0,0 +1,473 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use legion::serialize::UnknownType;
+use ron::ser::PrettyConfig;
+use serde::Deserialize;
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use crate::prelude::*;
+
+// 存档文件的位置
+const SAVE_FILE: &str = "savegame.ron";
+
+// 存档格式的版本号，存档结构发生变化时需要递增，旧版本的存档将无法读取
+pub const SAVE_VERSION: u32 = 20;
+
+// 存档中各个字段的名称
+const FIELDS: &[&str] = &["version", "seed", "rng", "stats", "map", "camera", "turn_state", "theme", "log", "world", "levels", "uniques"];
+// 去过的楼层快照中的字段
+const LEVEL_FIELDS: &[&str] = &["map", "theme", "world"];
+
+/*
+ * 注册需要保存的组件，legion根据注册的名称来序列化和反序列化组件。
+ * 名称一旦写入存档就不能再修改，否则旧的存档将无法读取。
+ */
+fn registry() -> Registry<String> {
+    let mut registry = Registry::<String>::default();
+    registry.on_unknown(UnknownType::Ignore);
+    registry.register::<Point>("Point".to_string());
+    registry.register::<Render>("Render".to_string());
+    registry.register::<Player>("Player".to_string());
+    registry.register::<Enemy>("Enemy".to_string());
+    registry.register::<MovingRandomly>("MovingRandomly".to_string());
+    registry.register::<ChasingPlayer>("ChasingPlayer".to_string());
+    registry.register::<WantsToMove>("WantsToMove".to_string());
+    registry.register::<WantsToAttack>("WantsToAttack".to_string());
+    registry.register::<Health>("Health".to_string());
+    registry.register::<Name>("Name".to_string());
+    registry.register::<Item>("Item".to_string());
+    registry.register::<AmuletOfYala>("AmuletOfYala".to_string());
+    registry.register::<FieldOfView>("FieldOfView".to_string());
+    registry.register::<ProvidesHealing>("ProvidesHealing".to_string());
+    registry.register::<ProvidesDungeonMap>("ProvidesDungeonMap".to_string());
+    registry.register::<Carried>("Carried".to_string());
+    registry.register::<ActivateItem>("ActivateItem".to_string());
+    registry.register::<Damage>("Damage".to_string());
+    registry.register::<Defence>("Defence".to_string());
+    registry.register::<Equippable>("Equippable".to_string());
+    registry.register::<Equipped>("Equipped".to_string());
+    registry.register::<WantsToEquip>("WantsToEquip".to_string());
+    registry.register::<WantsToUnequip>("WantsToUnequip".to_string());
+    registry.register::<WantsToDrop>("WantsToDrop".to_string());
+    registry.register::<Ranged>("Ranged".to_string());
+    registry.register::<Poisoned>("Poisoned".to_string());
+    registry.register::<Confused>("Confused".to_string());
+    registry.register::<Regenerating>("Regenerating".to_string());
+    registry.register::<Hasted>("Hasted".to_string());
+    registry.register::<ProvidesStatus>("ProvidesStatus".to_string());
+    registry.register::<Telepathic>("Telepathic".to_string());
+    registry.register::<ProvidesAreaDamage>("ProvidesAreaDamage".to_string());
+    registry.register::<ProvidesChainDamage>("ProvidesChainDamage".to_string());
+    registry.register::<ProvidesTeleport>("ProvidesTeleport".to_string());
+    registry.register::<Hunger>("Hunger".to_string());
+    registry.register::<ProvidesFood>("ProvidesFood".to_string());
+    registry.register::<Experience>("Experience".to_string());
+    registry.register::<ExperienceValue>("ExperienceValue".to_string());
+    registry.register::<GainExperience>("GainExperience".to_string());
+    registry.register::<DamageDice>("DamageDice".to_string());
+    registry.register::<Accuracy>("Accuracy".to_string());
+    registry.register::<Evasion>("Evasion".to_string());
+    registry.register::<CombatEvent>("CombatEvent".to_string());
+    registry.register::<FleesWhenHurt>("FleesWhenHurt".to_string());
+    registry.register::<Fleeing>("Fleeing".to_string());
+    registry.register::<KeepsDistance>("KeepsDistance".to_string());
+    registry.register::<Patrolling>("Patrolling".to_string());
+    registry.register::<Guarding>("Guarding".to_string());
+    registry.register::<AlertsAllies>("AlertsAllies".to_string());
+    registry.register::<Alerted>("Alerted".to_string());
+    registry.register::<Slowed>("Slowed".to_string());
+    registry.register::<Speed>("Speed".to_string());
+    registry.register::<Energy>("Energy".to_string());
+    registry.register::<WantsToSearch>("WantsToSearch".to_string());
+    registry.register::<WantsToTakeStairs>("WantsToTakeStairs".to_string());
+    registry.register::<Unique>("Unique".to_string());
+    registry.register::<Enrages>("Enrages".to_string());
+    registry.register::<Enraged>("Enraged".to_string());
+    registry.register::<Summons>("Summons".to_string());
+    registry.register::<BreathAttack>("BreathAttack".to_string());
+    registry.register::<DeathMessage>("DeathMessage".to_string());
+    registry.register::<Guardian>("Guardian".to_string());
+    registry
+}
+
+// 存档中的字段，ron把结构体的字段名当作标识符而不是字符串
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum Field {
+    Version,
+    Seed,
+    Rng,
+    Stats,
+    Map,
+    Camera,
+    TurnState,
+    Theme,
+    Log,
+    World,
+    Levels,
+    Uniques,
+    #[serde(other)]
+    Unknown,
+}
+
+// 楼层快照中的字段
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum LevelField {
+    Map,
+    Theme,
+    World,
+    #[serde(other)]
+    Unknown,
+}
+
+// 写入存档时使用的视图，借用游戏世界和资源，不需要复制数据
+struct SaveGame<'a> {
+    ecs: &'a World,
+    resources: &'a Resources,
+    registry: &'a Registry<String>,
+}
+
+impl Serialize for SaveGame<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let theme = self.resources.get::<Box<dyn MapTheme>>().unwrap();
+        let mut state = serializer.serialize_struct("SaveGame", FIELDS.len())?;
+        state.serialize_field("version", &SAVE_VERSION)?;
+        state.serialize_field("seed", &*self.resources.get::<GameSeed>().unwrap())?;
+        // 保存随机数生成器的内部状态，继续游戏后的随机结果与不中断时完全一致
+        state.serialize_field("rng", &*self.resources.get::<RandomNumberGenerator>().unwrap())?;
+        state.serialize_field("stats", &*self.resources.get::<GameStats>().unwrap())?;
+        state.serialize_field("map", &*self.resources.get::<Map>().unwrap())?;
+        state.serialize_field("camera", &*self.resources.get::<Camera>().unwrap())?;
+        state.serialize_field("turn_state", &*self.resources.get::<TurnState>().unwrap())?;
+        state.serialize_field("theme", theme.name())?;
+        state.serialize_field("log", &*self.resources.get::<MessageLog>().unwrap())?;
+        state.serialize_field("world", &self.ecs.as_serializable(any(), self.registry))?;
+        let levels = self.resources.get::<Levels>().unwrap();
+        state.serialize_field("levels", &SavedLevels { levels: &levels, registry: self.registry })?;
+        state.serialize_field("uniques", &*self.resources.get::<Uniques>().unwrap())?;
+        state.end()
+    }
+}
+
+// 写入存档时使用的楼层快照视图，每一层的实体同样借助组件注册表序列化
+struct SavedLevels<'a> {
+    levels: &'a Levels,
+    registry: &'a Registry<String>,
+}
+
+struct SavedLevel<'a> {
+    snapshot: &'a LevelSnapshot,
+    registry: &'a Registry<String>,
+}
+
+impl Serialize for SavedLevels<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.levels.snapshots.iter()
+            .map(|(map_level, snapshot)| (map_level, SavedLevel { snapshot, registry: self.registry })))
+    }
+}
+
+impl Serialize for SavedLevel<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Level", LEVEL_FIELDS.len())?;
+        state.serialize_field("map", &self.snapshot.map)?;
+        state.serialize_field("theme", self.snapshot.theme.name())?;
+        state.serialize_field("world", &self.snapshot.world.as_serializable(any(), self.registry))?;
+        state.end()
+    }
+}
+
+// 读取存档时使用的反序列化种子，游戏世界需要借助组件注册表才能还原
+struct LoadGame<'a> {
+    registry: &'a Registry<String>,
+    // 启动时加载的主题，用来按名称还原地图的主题风格
+    themes: &'a Themes,
+}
+
+impl<'de> DeserializeSeed<'de> for LoadGame<'_> {
+    type Value = (World, Resources);
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("SaveGame", FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for LoadGame<'_> {
+    type Value = (World, Resources);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a dungeoncrawl save game")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut world = None;
+        let mut resources = Resources::default();
+        while let Some(key) = access.next_key::<Field>()? {
+            match key {
+                Field::Version => {
+                    // 版本号不一致时拒绝读取，避免用错误的结构解析存档
+                    let version: u32 = access.next_value()?;
+                    if version != SAVE_VERSION {
+                        return Err(de::Error::custom(format!(
+                            "unsupported save version {}, expected {}", version, SAVE_VERSION)));
+                    }
+                }
+                Field::Seed => resources.insert(access.next_value::<GameSeed>()?),
+                Field::Rng => resources.insert(access.next_value::<RandomNumberGenerator>()?),
+                Field::Stats => resources.insert(access.next_value::<GameStats>()?),
+                Field::Map => resources.insert(access.next_value::<Map>()?),
+                Field::Camera => resources.insert(access.next_value::<Camera>()?),
+                Field::TurnState => resources.insert(access.next_value::<TurnState>()?),
+                Field::Theme => resources.insert(self.themes.theme_by_name(&access.next_value::<String>()?)),
+                Field::Log => resources.insert(access.next_value::<MessageLog>()?),
+                Field::World => world = Some(access.next_value_seed(self.registry.as_deserialize())?),
+                Field::Levels => resources.insert(access.next_value_seed(LoadLevels { registry: self.registry, themes: self.themes })?),
+                Field::Uniques => resources.insert(access.next_value::<Uniques>()?),
+                Field::Unknown => {
+                    access.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        let world = world.ok_or_else(|| de::Error::missing_field("world"))?;
+        Ok((world, resources))
+    }
+}
+
+// 读取去过的楼层快照时使用的反序列化种子
+struct LoadLevels<'a> {
+    registry: &'a Registry<String>,
+    themes: &'a Themes,
+}
+
+impl<'de> DeserializeSeed<'de> for LoadLevels<'_> {
+    type Value = Levels;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LoadLevels<'_> {
+    type Value = Levels;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of visited levels")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut levels = Levels::default();
+        while let Some(map_level) = access.next_key::<u32>()? {
+            let snapshot = access.next_value_seed(LoadLevel { registry: self.registry, themes: self.themes })?;
+            levels.snapshots.insert(map_level, snapshot);
+        }
+        Ok(levels)
+    }
+}
+
+struct LoadLevel<'a> {
+    registry: &'a Registry<String>,
+    themes: &'a Themes,
+}
+
+impl<'de> DeserializeSeed<'de> for LoadLevel<'_> {
+    type Value = LevelSnapshot;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("Level", LEVEL_FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for LoadLevel<'_> {
+    type Value = LevelSnapshot;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a visited level")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let (mut map, mut theme, mut world) = (None, None, None);
+        while let Some(key) = access.next_key::<LevelField>()? {
+            match key {
+                LevelField::Map => map = Some(access.next_value::<Map>()?),
+                LevelField::Theme => theme = Some(self.themes.theme_by_name(&access.next_value::<String>()?)),
+                LevelField::World => world = Some(access.next_value_seed(self.registry.as_deserialize())?),
+                LevelField::Unknown => {
+                    access.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(LevelSnapshot {
+            map: map.ok_or_else(|| de::Error::missing_field("map"))?,
+            theme: theme.ok_or_else(|| de::Error::missing_field("theme"))?,
+            world: world.ok_or_else(|| de::Error::missing_field("world"))?,
+        })
+    }
+}
+
+// 将游戏世界和资源转换为存档文本
+pub fn serialize_game(ecs: &World, resources: &Resources) -> ron::Result<String> {
+    let registry = registry();
+    let save = SaveGame { ecs, resources, registry: &registry };
+    ron::ser::to_string_pretty(&save, PrettyConfig::new().compact_arrays(true))
+}
+
+// 从存档文本还原游戏世界和资源，themes是启动时加载的主题
+pub fn deserialize_game(text: &str, themes: &Themes) -> ron::Result<(World, Resources)> {
+    let registry = registry();
+    let loaded = ron::Options::default().from_str_seed(text, LoadGame { registry: &registry, themes })?;
+    Ok(loaded)
+}
+
+// 是否存在可以继续的存档
+pub fn has_save_game() -> bool {
+    Path::new(SAVE_FILE).exists()
+}
+
+pub fn save_game(ecs: &World, resources: &Resources) -> ron::Result<()> {
+    let text = serialize_game(ecs, resources)?;
+    fs::write(SAVE_FILE, text)?;
+    Ok(())
+}
+
+pub fn load_game(themes: &Themes) -> ron::Result<(World, Resources)> {
+    let text = fs::read_to_string(SAVE_FILE)?;
+    deserialize_game(&text, themes)
+}
+
+// 游戏结束或胜利后删除存档，避免继续一局已经结束的游戏
+pub fn delete_save_game() {
+    if has_save_game() {
+        let _ = fs::remove_file(SAVE_FILE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::save_load::{deserialize_game, serialize_game, SAVE_VERSION};
+
+    // 生成一局新游戏，并让玩家捡起一件物品、揭示部分地图
+    fn new_game() -> (World, Resources) {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        let mut rng = GameSeed(42).rng();
+        let templates = Templates::load();
+        let mapgen = MapGen::load();
+        let themes = Themes::load();
+        let campaign = Campaign::load(&templates, &mapgen, &themes);
+        let prefabs = Prefabs::load();
+        let mut map_builder = MapBuilder::new(&mut rng, campaign.level(0), 0, &mapgen, &themes, &prefabs);
+        spawn_player(&mut ecs, map_builder.player_start);
+        let mut uniques = Uniques::default();
+        spawn_level(&mut ecs, &mut rng, 0, campaign.level(0), &map_builder, &templates, &mut uniques);
+        let player = *<Entity>::query().filter(component::<Player>()).iter(&ecs).next().unwrap();
+        let item = *<Entity>::query().filter(component::<Item>()).iter(&ecs).next().unwrap();
+        ecs.entry(item).unwrap().remove_component::<Point>();
+        ecs.entry(item).unwrap().add_component(Carried(player));
+        map_builder.map.revealed_tiles.iter_mut().step_by(3).for_each(|t| *t = true);
+        resources.insert(Camera::new(map_builder.player_start, &map_builder.map));
+        resources.insert(map_builder.map);
+        resources.insert(TurnState::AwaitingInput);
+        resources.insert(map_builder.theme);
+        resources.insert(rng);
+        resources.insert(GameSeed(42));
+        resources.insert(GameStats { turns: 12, kills: 3, items_used: 1 });
+        let mut log = MessageLog::default();
+        log.add("You pick up the Healing Potion.", WHITE);
+        resources.insert(log);
+        // 去过的第二层
+        let mut rng = GameSeed(43).rng();
+        let map_builder = MapBuilder::new(&mut rng, campaign.level(1), 1, &mapgen, &themes, &prefabs);
+        let mut world = World::default();
+        spawn_level(&mut world, &mut rng, 1, campaign.level(1), &map_builder, &templates, &mut uniques);
+        let mut levels = Levels::default();
+        levels.snapshots.insert(1, LevelSnapshot { map: map_builder.map, theme: map_builder.theme, world });
+        resources.insert(levels);
+        resources.insert(uniques);
+        (ecs, resources)
+    }
+
+    // 按名字和位置排序后的实体列表，用来比较两个游戏世界
+    fn named_entities(ecs: &World) -> Vec<(String, Option<Point>, Option<i32>)> {
+        let mut entities: Vec<(String, Option<Point>, Option<i32>)> = <(Entity, &Name)>::query()
+            .iter(ecs)
+            .map(|(entity, name)| {
+                let entry = ecs.entry_ref(*entity).unwrap();
+                (
+                    name.0.clone(),
+                    entry.get_component::<Point>().ok().copied(),
+                    entry.get_component::<Health>().ok().map(|h| h.current),
+                )
+            })
+            .collect();
+        entities.sort_by(|a, b| format!("{:?}", a).cmp(&format!("{:?}", b)));
+        entities
+    }
+
+    #[test]
+    fn a_saved_game_is_restored_unchanged() {
+        let (ecs, resources) = new_game();
+        let text = serialize_game(&ecs, &resources).unwrap();
+        let (loaded_ecs, loaded_resources) = deserialize_game(&text, &Themes::load()).unwrap();
+
+        assert_eq!(ecs.len(), loaded_ecs.len());
+        assert_eq!(named_entities(&ecs), named_entities(&loaded_ecs));
+
+        let player = |ecs: &World| {
+            <(Entity, &Player, &Point, &Health, &FieldOfView, &Damage)>::query()
+                .iter(ecs)
+                .map(|(e, p, pos, hp, fov, dmg)| (*e, *p, *pos, *hp, fov.radius, *dmg))
+                .next()
+                .unwrap()
+        };
+        let (_, player_before, pos_before, hp_before, radius_before, dmg_before) = player(&ecs);
+        let (loaded_player, player_after, pos_after, hp_after, radius_after, dmg_after) = player(&loaded_ecs);
+        assert_eq!(
+            (player_before, pos_before, hp_before, radius_before, dmg_before),
+            (player_after, pos_after, hp_after, radius_after, dmg_after)
+        );
+
+        // 物品栏中的物品仍然属于还原后的玩家角色
+        let carried: Vec<Entity> = <&Carried>::query().iter(&loaded_ecs).map(|c| c.0).collect();
+        assert_eq!(carried, vec![loaded_player]);
+
+        assert_eq!(*resources.get::<Map>().unwrap(), *loaded_resources.get::<Map>().unwrap());
+        assert_eq!(*resources.get::<Camera>().unwrap(), *loaded_resources.get::<Camera>().unwrap());
+        assert_eq!(*resources.get::<TurnState>().unwrap(), *loaded_resources.get::<TurnState>().unwrap());
+        assert_eq!(*resources.get::<GameSeed>().unwrap(), *loaded_resources.get::<GameSeed>().unwrap());
+        assert_eq!(*resources.get::<GameStats>().unwrap(), *loaded_resources.get::<GameStats>().unwrap());
+        assert_eq!(*resources.get::<MessageLog>().unwrap(), *loaded_resources.get::<MessageLog>().unwrap());
+        assert_eq!(*resources.get::<Uniques>().unwrap(), *loaded_resources.get::<Uniques>().unwrap());
+        // 还原后的随机数生成器继续产生相同的随机序列
+        assert_eq!(
+            resources.get_mut::<RandomNumberGenerator>().unwrap().next_u64(),
+            loaded_resources.get_mut::<RandomNumberGenerator>().unwrap().next_u64()
+        );
+        assert_eq!(
+            resources.get::<Box<dyn MapTheme>>().unwrap().name(),
+            loaded_resources.get::<Box<dyn MapTheme>>().unwrap().name()
+        );
+
+        // 去过的楼层的地图和实体同样被还原
+        let levels = resources.get::<Levels>().unwrap();
+        let loaded_levels = loaded_resources.get::<Levels>().unwrap();
+        assert_eq!(levels.snapshots.keys().collect::<Vec<_>>(), loaded_levels.snapshots.keys().collect::<Vec<_>>());
+        let (level, loaded_level) = (&levels.snapshots[&1], &loaded_levels.snapshots[&1]);
+        assert_eq!(level.map, loaded_level.map);
+        assert_eq!(level.theme.name(), loaded_level.theme.name());
+        assert_eq!(named_entities(&level.world), named_entities(&loaded_level.world));
+    }
+
+    #[test]
+    fn a_save_with_another_version_is_rejected() {
+        let (ecs, resources) = new_game();
+        let text = serialize_game(&ecs, &resources).unwrap().replacen(
+            &format!("version: {}", SAVE_VERSION),
+            &format!("version: {}", SAVE_VERSION + 1),
+            1,
+        );
+        assert!(deserialize_game(&text, &Themes::load()).is_err());
+    }
+}
@@ -1,11 +1,17 @@
 mod template;
 
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
 use crate::prelude::*;
-use crate::spawner::template::Templates;
+pub use crate::spawner::template::{EntityType, Templates};
+
+// 这一局游戏中已经出现过的独特怪物的名字，每个独特怪物最多出现一次
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Uniques(pub HashSet<String>);
 
 pub fn spawn_player(ecs: &mut World, pos: Point) {
-    // 将多个组件聚合在一个实体中，由玩家、位置信息、渲染组件、生命值、视野构成。
-    ecs.push(
+    // 将多个组件聚合在一个实体中，由玩家、位置信息、渲染组件、生命值、视野、攻击力、食物值、经验值、能量构成。
+    let player = ecs.push(
         (
             Player { map_level: 0 },
             pos,
@@ -15,26 +21,40 @@ pub fn spawn_player(ecs: &mut World, pos: Point) {
             },
             Health { current: 10, max: 10 },
             FieldOfView::new(8), // 设置视野为8格
-            Damage(1)
+            Damage(1),
+            Hunger::new(),
+            Experience::new()
         )
     );
+    // 一个元组最多包含8个组件，其余的组件单独添加
+    ecs.entry(player).unwrap().add_component(Energy::new());
 }
 
-pub fn spawn_amulet_of_yala(ecs: &mut World, pos: Point) {
-    ecs.push((
-        Item, AmuletOfYala, pos,
-        Render {
-            color: ColorPair::new(WHITE, BLACK),
-            glyph: to_cp437('|'),
-        },
-        Name("Amulet of Yala".to_string())
-    ));
-}
-
+// 按照战役中这一层的设置放置首领、怪物和物品，depth用于选择模板中可以出现在这一层的实体
 pub fn spawn_level(ecs: &mut World,
                    rng: &mut RandomNumberGenerator,
-                   level: usize,
-                   spawn_points: &[Point]) {
-    let template = Templates::load();
-    template.spawn_entities(ecs, rng, level, spawn_points);
+                   depth: usize,
+                   level: &CampaignLevel,
+                   mb: &MapBuilder,
+                   templates: &Templates,
+                   uniques: &mut Uniques) {
+    // 首领占用离玩家角色起点最远的出生点
+    let mut spawn_points = mb.monster_spawns.clone();
+    let distance = |pt: &Point| DistanceAlg::Pythagoras.distance2d(mb.player_start, *pt);
+    level.bosses.iter().for_each(|boss| {
+        let farthest = spawn_points.iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| distance(a).partial_cmp(&distance(b)).unwrap())
+            .map(|(i, _)| i);
+        match farthest {
+            Some(i) => templates.spawn_named(ecs, boss, spawn_points.remove(i), uniques),
+            None => eprintln!("Warning: no room for {} at level {}", boss, depth),
+        }
+    });
+    templates.spawn_entities(ecs, rng, depth, level, &spawn_points);
+    templates.spawn_vault_entities(ecs, rng, depth, &mb.vault_spawns, uniques);
+    // 这一层巡逻的怪物在房间的中心之间巡逻
+    let waypoints = mb.patrol_points();
+    <&mut Patrolling>::query().iter_mut(ecs)
+        .for_each(|patrol| patrol.waypoints = waypoints.clone());
 }
@@ -21,9 +21,50 @@ pub struct Template {
     // 怪物的生命值
     pub hp: Option<i32>,
     // 基础伤害
-    pub base_damage: Option<i32>
+    pub base_damage: Option<i32>,
+    // 伤害骰子，例如"1d6+1"，掷出的点数加在基础伤害上
+    pub damage_dice: Option<String>,
+    // 命中值和闪避值，单位是命中率的百分点
+    pub accuracy: Option<i32>,
+    pub evasion: Option<i32>,
+    // 基础防御
+    pub base_defence: Option<i32>,
+    // 可以装备的物品占用的栏位
+    pub slot: Option<EquipmentSlot>,
+    // 远程武器、投掷物品和卷轴的射程
+    pub range: Option<i32>,
+    // 物品提供的状态效果持续的回合数
+    pub duration: Option<i32>,
+    // 消灭怪物获得的经验值
+    pub xp: Option<i32>,
+    // 怪物的行为方式，例如("Flee", 50)表示生命值低于一半时逃跑；没有设置移动方式时怪物追击玩家角色
+    pub ai: Option<Vec<(String, i32)>>,
+    // 怪物的速度，普通速度为100，200时每回合行动两次，50时每两个回合行动一次
+    pub speed: Option<i32>,
+    // 独特怪物每局游戏最多出现一次，不会随机出现，只由战役放在固定的层数或者由金库放置
+    #[serde(default)]
+    pub unique: bool,
+    // 首领的特殊能力，例如("Enrage", 50)表示生命值低于一半时狂暴
+    pub abilities: Option<Vec<(String, i32)>>,
+    // 召唤的随从（模板名称）
+    pub minion: Option<String>,
+    // 被消灭时写入消息记录的消息
+    pub death_message: Option<String>,
 }
 
+impl Template {
+    // 是否设置了移动方式，设置了的怪物不再默认追击玩家角色
+    fn has_movement_ai(&self) -> bool {
+        self.ai.as_ref().is_some_and(|ai| ai.iter()
+            .any(|(behaviour, _)| matches!(behaviour.as_str(), "Wander" | "KeepDistance" | "Patrol" | "Guard")))
+    }
+}
+
+// 没有设置持续回合数时，状态效果持续的回合数
+const DEFAULT_STATUS_DURATION: i32 = 5;
+// 金库中的怪物守卫的范围
+const VAULT_GUARD_RADIUS: i32 = 4;
+
 #[derive(Clone, Deserialize, Debug, PartialEq)]
 pub enum EntityType {
     // 怪物
@@ -45,38 +86,124 @@ impl Templates {
         from_reader(file).expect("Unable to load templates")
     }
 
+    // 在出生点上放置战役设置的数量的怪物和物品，出生点不够时按比例减少
     pub fn spawn_entities(&self,
                           ecs: &mut World,
                           rng: &mut RandomNumberGenerator,
-                          level: usize, spawn_points: &[Point]) {
-        // 存储在当前关卡中生成的实体
-        let mut available_entities = Vec::new();
-        // 获得可用实体的列表，包括治疗药水、地图、妖精、兽人等实体，并按照各个实体的频率数保存在列表中。
-        self.entities
-            .iter().filter(|e| e.levels.contains(&level))
-            .for_each(|t| {
-                for _ in 0..t.frequency {
-                    available_entities.push(t);
+                          level: usize,
+                          campaign_level: &CampaignLevel,
+                          spawn_points: &[Point]) {
+        let total = campaign_level.monsters + campaign_level.items;
+        let monsters = if spawn_points.len() < total {
+            spawn_points.len() * campaign_level.monsters / total
+        } else {
+            campaign_level.monsters
+        };
+        // 随机选择放置怪物的出生点，避免怪物集中在生成器先放置的几个房间里，其余的出生点放置物品
+        let mut item_points = spawn_points.to_vec();
+        let monster_points: Vec<Point> = (0..monsters)
+            .map(|_| item_points.remove(rng.random_slice_index(&item_points).unwrap()))
+            .collect();
+        item_points.truncate(campaign_level.items);
+
+        // 将实体放置在坐标点上，按照各个实体的频率随机选择当前关卡中可以出现的怪物和物品
+        let mut commands = CommandBuffer::new(ecs);
+        for (points, entity_type) in [(monster_points, EntityType::Enemy), (item_points, EntityType::Item)] {
+            points.iter().for_each(|pt| {
+                if let Some(template) = self.random_entity_of_type(rng, level, entity_type.clone()) {
+                    self.spawn_entity(pt, template, &mut commands);
                 }
             });
+        }
+        commands.flush(ecs);
+    }
 
-        // 将实体放置在坐标点上
+    // 在指定的位置放置指定名字的实体，例如首领和胜利物品；已经出现过的独特怪物不会再次放置
+    pub fn spawn_named(&self, ecs: &mut World, name: &str, pt: Point, uniques: &mut Uniques) {
+        match self.entities.iter().find(|t| t.name == name) {
+            Some(template) if !Self::claim_unique(template, uniques) => {}
+            Some(template) => {
+                let mut commands = CommandBuffer::new(ecs);
+                self.spawn_entity(&pt, template, &mut commands);
+                commands.flush(ecs);
+            }
+            None => eprintln!("Warning: we don't know how to spawn {}", name),
+        }
+    }
+
+    // 在游戏过程中放置指定名字的实体，例如首领召唤的随从
+    pub fn spawn_at(&self, name: &str, pt: Point, commands: &mut CommandBuffer) -> Option<Entity> {
+        match self.entities.iter().find(|t| t.name == name) {
+            Some(template) => Some(self.spawn_entity(&pt, template, commands)),
+            None => {
+                eprintln!("Warning: we don't know how to spawn {}", name);
+                None
+            }
+        }
+    }
+
+    // 记录即将出现的独特怪物，这一局游戏中已经出现过时返回false
+    fn claim_unique(template: &Template, uniques: &mut Uniques) -> bool {
+        !template.unique || uniques.0.insert(template.name.clone())
+    }
+
+    // 在金库中生成指定类型或指定名字的实体
+    pub fn spawn_vault_entities(&self,
+                                ecs: &mut World,
+                                rng: &mut RandomNumberGenerator,
+                                level: usize,
+                                spawns: &[(Point, VaultSpawn)],
+                                uniques: &mut Uniques) {
         let mut commands = CommandBuffer::new(ecs);
-        spawn_points.iter().for_each(|pt| {
-            if let Some(entity) = rng.random_slice_entry(&available_entities) {
-                self.spawn_entity(pt, entity, &mut commands);
+        spawns.iter().for_each(|(pt, spawn)| {
+            let template = match spawn {
+                VaultSpawn::Monster => self.random_entity_of_type(rng, level, EntityType::Enemy),
+                VaultSpawn::Item => self.random_entity_of_type(rng, level, EntityType::Item),
+                // 已经出现过的独特怪物换成一个随机的怪物
+                VaultSpawn::Template(name) => match self.entities.iter().find(|t| &t.name == name) {
+                    Some(template) if !Self::claim_unique(template, uniques) =>
+                        self.random_entity_of_type(rng, level, EntityType::Enemy),
+                    template => template,
+                },
+            };
+            match template {
+                Some(template) => {
+                    let entity = self.spawn_entity(pt, template, &mut commands);
+                    // 没有设置移动方式的怪物留在金库中守卫宝物
+                    if template.entity_type == EntityType::Enemy && !template.has_movement_ai() {
+                        commands.remove_component::<ChasingPlayer>(entity);
+                        commands.add_component(entity, Guarding { post: *pt, radius: VAULT_GUARD_RADIUS });
+                    }
+                }
+                None => eprintln!("Warning: we don't know how to spawn {:?} at level {}", spawn, level),
             }
         });
         commands.flush(ecs);
     }
 
+    // 按照频率随机选择一个当前关卡中可以出现的指定类型的实体
+    fn random_entity_of_type(&self,
+                             rng: &mut RandomNumberGenerator,
+                             level: usize,
+                             entity_type: EntityType) -> Option<&Template> {
+        let mut available_entities = Vec::new();
+        self.entities
+            .iter().filter(|e| e.levels.contains(&level) && e.entity_type == entity_type && !e.unique)
+            .for_each(|t| {
+                for _ in 0..t.frequency {
+                    available_entities.push(t);
+                }
+            });
+        rng.random_slice_entry(&available_entities).copied()
+    }
+
     fn spawn_entity(&self,
                     pt: &Point,
                     template: &Template,
-                    commands: &mut CommandBuffer) {
+                    commands: &mut CommandBuffer) -> Entity {
         // 存储与渲染相关的信息，包括位置、渲染信息、名字
         let entity = commands.push((
-            pt.clone(),
+            *pt,
             Render {
                 color: ColorPair::new(WHITE, BLACK),
                 glyph: to_cp437(template.glyph),
@@ -88,31 +215,115 @@ impl Templates {
             EntityType::Enemy => {
                 commands.add_component(entity, Enemy {});
                 commands.add_component(entity, FieldOfView::new(6));
-                commands.add_component(entity, ChasingPlayer {});
+                if !template.has_movement_ai() {
+                    commands.add_component(entity, ChasingPlayer {});
+                }
                 commands.add_component(entity, Health {
                     current: template.hp.unwrap(),
                     max: template.hp.unwrap(),
                 });
+                commands.add_component(entity, ExperienceValue(template.xp.unwrap_or(0)));
+                commands.add_component(entity, Energy::new());
+                if let Some(speed) = template.speed {
+                    commands.add_component(entity, Speed(speed));
+                }
             }
         }
         // 添加特殊效果对应的组件，包括治疗药水、地图
         if let Some(effects) = &template.provides {
+            let turns = template.duration.unwrap_or(DEFAULT_STATUS_DURATION);
+            let mut status = Vec::new();
             effects.iter().for_each(|(provides, n)| {
                 match provides.as_str() {
                     "Healing" => commands.add_component(entity, ProvidesHealing { amount: *n }),
                     "MagicMap" => commands.add_component(entity, ProvidesDungeonMap {}),
-                    _ => println!("Warning: we don't know how to provide {}", provides)
+                    "Food" => commands.add_component(entity, ProvidesFood { amount: *n }),
+                    // 状态效果：中毒和再生的数值是每回合的伤害和恢复的生命值
+                    "Poison" => status.push(StatusEffect::Poison { dmg: *n, turns }),
+                    "Confusion" => status.push(StatusEffect::Confusion { turns }),
+                    "Regeneration" => status.push(StatusEffect::Regeneration { amount: *n, turns }),
+                    "Haste" => status.push(StatusEffect::Haste { turns }),
+                    "Slow" => status.push(StatusEffect::Slow { turns }),
+                    "RevealMonsters" => status.push(StatusEffect::RevealMonsters { turns }),
+                    // 范围伤害的数值是半径，连锁伤害的数值是最多击中的怪物数量，伤害值由base_damage设置
+                    "AreaDamage" => commands.add_component(entity, ProvidesAreaDamage { radius: *n }),
+                    "ChainDamage" => commands.add_component(entity, ProvidesChainDamage { targets: *n }),
+                    "Teleport" => commands.add_component(entity, ProvidesTeleport {}),
+                    // 胜利物品，捡起之后游戏胜利
+                    "Victory" => commands.add_component(entity, AmuletOfYala),
+                    _ => eprintln!("Warning: we don't know how to provide {}", provides)
                 }
             });
+            if !status.is_empty() {
+                commands.add_component(entity, ProvidesStatus(status));
+            }
         }
 
         // 添加伤害值信息
         if let Some(damage) = &template.base_damage {
             commands.add_component(entity, Damage(*damage));
-
-            if template.entity_type == EntityType::Item {
-                commands.add_component(entity, Weapon{});
+        }
+        if let Some(dice) = &template.damage_dice {
+            match DamageDice::parse(dice) {
+                Some(dice) => commands.add_component(entity, dice),
+                None => eprintln!("Warning: we don't know how to roll {}", dice),
             }
         }
+        // 添加防御值信息
+        if let Some(defence) = &template.base_defence {
+            commands.add_component(entity, Defence(*defence));
+        }
+        // 添加命中值和闪避值信息
+        if let Some(accuracy) = template.accuracy {
+            commands.add_component(entity, Accuracy(accuracy));
+        }
+        if let Some(evasion) = template.evasion {
+            commands.add_component(entity, Evasion(evasion));
+        }
+        // 可以装备的物品：武器、盾牌、盔甲、头盔和戒指
+        if let Some(slot) = template.slot {
+            commands.add_component(entity, Equippable { slot });
+        }
+        // 可以远程攻击的物品：弓、飞刀和卷轴
+        if let Some(range) = template.range {
+            commands.add_component(entity, Ranged { range });
+        }
+        // 怪物的行为方式：数值分别是保持的距离、守卫的范围、逃跑时生命值的百分比和呼叫同伴的范围
+        if let Some(ai) = &template.ai {
+            ai.iter().for_each(|(behaviour, n)| {
+                match behaviour.as_str() {
+                    "Wander" => commands.add_component(entity, MovingRandomly {}),
+                    "KeepDistance" => {
+                        commands.add_component(entity, KeepsDistance);
+                        commands.add_component(entity, Ranged { range: *n });
+                    }
+                    // 巡逻的路点由spawn_level按照地图设置
+                    "Patrol" => commands.add_component(entity, Patrolling { waypoints: Vec::new(), waypoint: None }),
+                    "Guard" => commands.add_component(entity, Guarding { post: *pt, radius: *n }),
+                    "Flee" => commands.add_component(entity, FleesWhenHurt { percent: *n }),
+                    "AlertAllies" => commands.add_component(entity, AlertsAllies { radius: *n }),
+                    _ => eprintln!("Warning: we don't know how to behave like {}", behaviour)
+                }
+            });
+        }
+        // 首领的特殊能力：数值分别是狂暴时生命值的百分比、每次召唤的随从数量和喷吐的伤害
+        if let Some(abilities) = &template.abilities {
+            abilities.iter().for_each(|(ability, n)| {
+                match (ability.as_str(), &template.minion) {
+                    ("Enrage", _) => commands.add_component(entity, Enrages { percent: *n }),
+                    ("Summon", Some(minion)) =>
+                        commands.add_component(entity, Summons { minion: minion.clone(), count: *n, cooldown: 0 }),
+                    ("Breath", _) => commands.add_component(entity, BreathAttack { damage: *n, cooldown: 0 }),
+                    _ => eprintln!("Warning: we don't know how {} can use {}", template.name, ability)
+                }
+            });
+        }
+        if template.unique {
+            commands.add_component(entity, Unique);
+        }
+        if let Some(message) = &template.death_message {
+            commands.add_component(entity, DeathMessage(message.clone()));
+        }
+        entity
     }
 }
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use crate::prelude::*;
+
+// 喷吐的射程
+const BREATH_RANGE: i32 = 6;
+// 再次喷吐和召唤之前要等待的行动次数
+const BREATH_COOLDOWN: i32 = 4;
+const SUMMON_COOLDOWN: i32 = 10;
+
+/*
+ * 首领的特殊能力：看到玩家角色的首领在能力冷却完毕时喷吐或者召唤随从，代替这一次的移动和攻击。
+ * 使用能力时直接扣除这次行动的能量，chasing和behaviours系统不会再让它行动，回合结束时也不会重复扣除。
+ */
+#[system]
+#[read_component(Point)]
+#[read_component(Player)]
+#[read_component(Enemy)]
+#[write_component(Health)]
+#[read_component(FieldOfView)]
+#[read_component(Name)]
+#[read_component(Confused)]
+#[write_component(Summons)]
+#[write_component(BreathAttack)]
+#[write_component(Energy)]
+pub fn abilities(
+    ecs: &mut SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] map: &Map,
+    #[resource] templates: &Templates,
+    #[resource] log: &mut MessageLog,
+) {
+    let (player, player_pos) = <(Entity, &Point)>::query()
+        .filter(component::<Player>())
+        .iter(ecs)
+        .map(|(entity, pos)| (*entity, *pos))
+        .next()
+        .unwrap();
+    // 这一次行动的首领、位置以及能否看到玩家角色
+    let casters: Vec<(Entity, Point, bool)> = <(Entity, &Point, &FieldOfView, Option<&Energy>)>::query()
+        .filter(component::<Enemy>() & !component::<Confused>()
+            & (component::<Summons>() | component::<BreathAttack>()))
+        .iter(ecs)
+        .filter(|(_, _, _, energy)| can_act(*energy))
+        .map(|(entity, pos, fov, _)| (*entity, *pos, fov.visible_tiles.contains(&player_pos)))
+        .collect();
+    // 被生物占据的图块，召唤的随从不能出现在这些位置上
+    let mut occupied: HashSet<Point> = <&Point>::query()
+        .filter(component::<Health>())
+        .iter(ecs)
+        .copied()
+        .collect();
+
+    casters.iter().for_each(|(entity, pos, sees_player)| {
+        let name = name_of(ecs, *entity);
+        let mut entry = ecs.entry_mut(*entity).unwrap();
+        // 每次行动时冷却减少一次
+        let breath = entry.get_component_mut::<BreathAttack>().ok().map(|breath| {
+            breath.cooldown = i32::max(0, breath.cooldown - 1);
+            *breath
+        });
+        let summons = entry.get_component_mut::<Summons>().ok().map(|summons| {
+            summons.cooldown = i32::max(0, summons.cooldown - 1);
+            summons.clone()
+        });
+        if !sees_player {
+            return;
+        }
+
+        let used = if let Some(breath) = breath.filter(|b| b.cooldown == 0
+            && in_line_of_fire(map, *pos, player_pos, BREATH_RANGE))
+        {
+            entry.get_component_mut::<BreathAttack>().unwrap().cooldown = BREATH_COOLDOWN;
+            log.add(format!("{} breathes fire at you for {} damage!", name, breath.damage), RED);
+            if let Ok(health) = ecs.entry_mut(player).unwrap().get_component_mut::<Health>() {
+                health.current -= breath.damage;
+            }
+            true
+        } else if let Some(summons) = summons.filter(|s| s.cooldown == 0) {
+            // 随从出现在首领身边没有被占据的空地上
+            let spots: Vec<Point> = (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| *pos + Point::new(dx, dy)))
+                .filter(|pt| map.can_enter_tile(*pt) && !occupied.contains(pt))
+                .take(summons.count as usize)
+                .collect();
+            if !spots.is_empty() {
+                entry.get_component_mut::<Summons>().unwrap().cooldown = SUMMON_COOLDOWN;
+                spots.iter().for_each(|pt| {
+                    // 召唤出来的随从不提供经验值，否则玩家角色可以守在首领身边反复刷经验
+                    if let Some(minion) = templates.spawn_at(&summons.minion, *pt, commands) {
+                        commands.add_component(minion, ExperienceValue(0));
+                    }
+                    occupied.insert(*pt);
+                });
+                log.add(format!("{} calls for its minions!", name), MAGENTA);
+            }
+            !spots.is_empty()
+        } else {
+            false
+        };
+        if used {
+            if let Ok(energy) = ecs.entry_mut(*entity).unwrap().get_component_mut::<Energy>() {
+                energy.0 -= Energy::ACTION_COST;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // 在一张空地图上运行abilities系统
+    fn use_abilities(ecs: &mut World, resources: &mut Resources) {
+        let mut schedule = Schedule::builder().add_system(super::abilities_system()).build();
+        schedule.execute(ecs, resources);
+    }
+
+    #[test]
+    fn a_boss_breathes_at_a_distant_player_and_then_needs_to_recover() {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        resources.insert(Map::new(20, 20));
+        resources.insert(Templates::load());
+        resources.insert(MessageLog::default());
+        let player_pos = Point::new(5, 5);
+        let player = ecs.push((Player { map_level: 0 }, player_pos, Health { current: 10, max: 10 }));
+        let mut fov = FieldOfView::new(6);
+        fov.visible_tiles.insert(player_pos);
+        let boss = ecs.push((Enemy, Point::new(9, 5), fov, Name("Wyrm".to_string()),
+                             BreathAttack { damage: 3, cooldown: 0 }, Energy::new()));
+
+        use_abilities(&mut ecs, &mut resources);
+        assert_eq!(ecs.entry_ref(player).unwrap().get_component::<Health>().unwrap().current, 7);
+        // 喷吐代替了这一次行动
+        assert!(!ecs.entry_ref(boss).unwrap().get_component::<Energy>().unwrap().ready());
+
+        ecs.entry(boss).unwrap().add_component(Energy::new());
+        use_abilities(&mut ecs, &mut resources);
+        assert_eq!(ecs.entry_ref(player).unwrap().get_component::<Health>().unwrap().current, 7);
+    }
+
+    #[test]
+    fn a_boss_summons_minions_next_to_itself() {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        resources.insert(Map::new(20, 20));
+        resources.insert(Templates::load());
+        resources.insert(MessageLog::default());
+        let player_pos = Point::new(5, 5);
+        ecs.push((Player { map_level: 0 }, player_pos, Health { current: 10, max: 10 }));
+        let mut fov = FieldOfView::new(6);
+        fov.visible_tiles.insert(player_pos);
+        let boss_pos = Point::new(10, 10);
+        ecs.push((Enemy, boss_pos, fov, Name("Warlord".to_string()), Health { current: 10, max: 10 },
+                  Summons { minion: "Goblin".to_string(), count: 2, cooldown: 0 }));
+
+        use_abilities(&mut ecs, &mut resources);
+        let minions: Vec<(Point, i32)> = <(&Name, &Point, &ExperienceValue)>::query()
+            .iter(&ecs)
+            .filter(|(name, _, _)| name.0 == "Goblin")
+            .map(|(_, pos, xp)| (*pos, xp.0))
+            .collect();
+        assert_eq!(minions.len(), 2);
+        assert!(minions.iter().all(|(pt, _)| *pt != boss_pos && DistanceAlg::Chebyshev.distance2d(*pt, boss_pos) < 1.5));
+        // 随从不提供经验值
+        assert!(minions.iter().all(|(_, xp)| *xp == 0));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::prelude::*;
+
+/*
+ * 怪物的行为：逃跑、保持距离射击、守卫岗位、巡逻和响应同伴的呼叫。
+ * 看到玩家角色并且追击玩家角色的怪物由chasing系统处理，随机移动的怪物由random_move系统处理。
+ */
+#[system]
+#[read_component(Point)]
+#[read_component(Player)]
+#[read_component(Enemy)]
+#[read_component(Health)]
+#[read_component(FieldOfView)]
+#[read_component(ChasingPlayer)]
+#[read_component(Confused)]
+#[read_component(Fleeing)]
+#[read_component(KeepsDistance)]
+#[read_component(Ranged)]
+#[read_component(Guarding)]
+#[read_component(Patrolling)]
+#[read_component(Alerted)]
+#[read_component(Energy)]
+pub fn behaviours(
+    ecs: &SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] map: &Map,
+    #[resource] rng: &mut RandomNumberGenerator,
+) {
+    let (player, player_pos) = <(Entity, &Point)>::query()
+        .filter(component::<Player>())
+        .iter(ecs)
+        .map(|(entity, pos)| (*entity, *pos))
+        .next()
+        .unwrap();
+    // 以玩家角色为起点的迪杰斯特拉图，追击时走向数值更小的图块，逃跑时走向数值更大的图块
+    let dijkstra_map = DijkstraMap::new(
+        map.width,
+        map.height,
+        &[map.point2d_to_index(player_pos)],
+        map,
+        1024.0,
+    );
+    let mut positions = <(&Point, &Health)>::query();
+
+    <(Entity, &Point, &FieldOfView, Option<&Energy>)>::query()
+        .filter(component::<Enemy>() & !component::<Confused>())
+        .iter(ecs)
+        .filter(|(_, _, _, energy)| can_act(*energy))
+        .for_each(|(entity, pos, fov, _)| {
+            let entry = ecs.entry_ref(*entity).unwrap();
+            let sees_player = fov.visible_tiles.contains(&player_pos);
+            let alerted = entry.get_component::<Alerted>().ok();
+            // 保持距离的怪物在射程之内射击玩家角色
+            let mut shoots = false;
+            let destination = if entry.get_component::<Fleeing>().is_ok() {
+                if sees_player {
+                    flee_step(&dijkstra_map, map, *pos, player_pos)
+                } else {
+                    None
+                }
+            } else if entry.get_component::<KeepsDistance>().is_ok() {
+                let range = entry.get_component::<Ranged>().map_or(0, |r| r.range);
+                if !sees_player {
+                    alerted.and_then(|a| path_step(map, *pos, a.target))
+                } else if DistanceAlg::Pythagoras.distance2d(*pos, player_pos) < 1.5 {
+                    // 玩家角色靠得太近时后退，无路可退时近身攻击
+                    flee_step(&dijkstra_map, map, *pos, player_pos)
+                } else if in_line_of_fire(map, *pos, player_pos, range) {
+                    shoots = true;
+                    Some(player_pos)
+                } else {
+                    chase_step(&dijkstra_map, map, *pos, player_pos)
+                }
+            } else if let Ok(guard) = entry.get_component::<Guarding>() {
+                if sees_player && DistanceAlg::Pythagoras.distance2d(guard.post, player_pos) <= guard.radius as f32 {
+                    chase_step(&dijkstra_map, map, *pos, player_pos)
+                } else {
+                    path_step(map, *pos, guard.post)
+                }
+            } else if sees_player && entry.get_component::<ChasingPlayer>().is_ok() {
+                return;
+            } else if sees_player && (alerted.is_some() || entry.get_component::<Patrolling>().is_ok()) {
+                chase_step(&dijkstra_map, map, *pos, player_pos)
+            } else if let Some(alerted) = alerted {
+                let step = path_step(map, *pos, alerted.target);
+                // 无法到达呼叫的位置时放弃
+                if step.is_none() {
+                    commands.remove_component::<Alerted>(*entity);
+                }
+                step
+            } else if let Ok(patrol) = entry.get_component::<Patrolling>() {
+                // 无法到达路点时立即换一个路点，不在原地停留
+                patrol.waypoint
+                    .and_then(|waypoint| path_step(map, *pos, waypoint))
+                    .or_else(|| {
+                        let waypoint = patrol.next_waypoint(rng, *pos)?;
+                        commands.add_component(*entity, Patrolling { waypoints: patrol.waypoints.clone(), waypoint: Some(waypoint) });
+                        path_step(map, *pos, waypoint)
+                    })
+            } else {
+                None
+            };
+
+            let Some(destination) = destination else {
+                return;
+            };
+            if destination == player_pos {
+                // 射击时怪物自身就是远程武器，和玩家角色的远程攻击一样由战斗系统结算
+                commands.push(((), WantsToAttack {
+                    attacker: *entity,
+                    victim: player,
+                    weapon: shoots.then_some(*entity),
+                }));
+            } else if !positions.iter(ecs).any(|(target_pos, _)| *target_pos == destination) {
+                // 其他怪物挡住去路时原地等待
+                commands.push(((), WantsToMove { entity: *entity, destination }));
+            }
+        });
+}
+
+// 沿着迪杰斯特拉图走向玩家角色，相邻时攻击
+fn chase_step(dijkstra_map: &DijkstraMap, map: &Map, pos: Point, player_pos: Point) -> Option<Point> {
+    // 使用1.2可以保证怪物不会在对角线位置上发起攻击
+    if DistanceAlg::Pythagoras.distance2d(pos, player_pos) < 1.2 {
+        return Some(player_pos);
+    }
+    DijkstraMap::find_lowest_exit(dijkstra_map, map.point2d_to_index(pos), map)
+        .map(|idx| map.index_to_point2d(idx))
+}
+
+// 沿着迪杰斯特拉图远离玩家角色，走投无路并且与玩家角色相邻时攻击
+fn flee_step(dijkstra_map: &DijkstraMap, map: &Map, pos: Point, player_pos: Point) -> Option<Point> {
+    let idx = map.point2d_to_index(pos);
+    DijkstraMap::find_highest_exit(dijkstra_map, idx, map)
+        .filter(|exit| dijkstra_map.map[*exit] > dijkstra_map.map[idx])
+        .map(|exit| map.index_to_point2d(exit))
+        .or_else(|| (DistanceAlg::Pythagoras.distance2d(pos, player_pos) < 1.2).then_some(player_pos))
+}
+
+// 用A*算法寻找前往目标位置的路径，返回第一步，已经到达或者无法到达时返回None
+fn path_step(map: &Map, from: Point, to: Point) -> Option<Point> {
+    if from == to || map.try_idx(to).is_none() {
+        return None;
+    }
+    let path = a_star_search(map.point2d_to_index(from), map.point2d_to_index(to), map);
+    if path.success && path.steps.len() > 1 {
+        Some(map.index_to_point2d(path.steps[1]))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // 在一张空地图上运行behaviours系统，返回怪物想要移动到的位置
+    fn next_step(monster: impl storage::IntoComponentSource, player_pos: Point) -> Option<Point> {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        resources.insert(Map::new(20, 20));
+        resources.insert(RandomNumberGenerator::seeded(1));
+        ecs.push((Player { map_level: 0 }, player_pos, Health { current: 10, max: 10 }));
+        ecs.extend(monster);
+        let mut schedule = Schedule::builder().add_system(super::behaviours_system()).build();
+        schedule.execute(&mut ecs, &mut resources);
+        <&WantsToMove>::query().iter(&ecs).map(|m| m.destination).next()
+    }
+
+    #[test]
+    fn a_fleeing_monster_runs_away_from_the_player() {
+        let player_pos = Point::new(10, 10);
+        let mut fov = FieldOfView::new(6);
+        fov.visible_tiles.insert(player_pos);
+        let step = next_step(
+            vec![(Enemy, Point::new(12, 10), fov, Health { current: 1, max: 4 }, Fleeing)],
+            player_pos,
+        ).unwrap();
+        let distance = |pt: Point| DistanceAlg::Pythagoras.distance2d(pt, player_pos);
+        assert!(distance(step) > distance(Point::new(12, 10)));
+    }
+
+    // 弓箭手在距离玩家角色5格的位置，wall为真时两者之间隔着一堵墙，返回弓箭手发起的攻击
+    fn archer_attack(wall: bool) -> Option<(Entity, WantsToAttack)> {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        let mut map = Map::new(20, 20);
+        if wall {
+            let idx = map.point2d_to_index(Point::new(7, 5));
+            map.tiles[idx] = TileType::Wall;
+        }
+        resources.insert(map);
+        resources.insert(RandomNumberGenerator::seeded(1));
+        let player_pos = Point::new(5, 5);
+        ecs.push((Player { map_level: 0 }, player_pos, Health { current: 10, max: 10 }));
+        let mut fov = FieldOfView::new(6);
+        fov.visible_tiles.insert(player_pos);
+        let archer = ecs.push((Enemy, Point::new(10, 5), fov, Health { current: 2, max: 2 }, KeepsDistance, Ranged { range: 5 }));
+        let mut schedule = Schedule::builder().add_system(super::behaviours_system()).build();
+        schedule.execute(&mut ecs, &mut resources);
+        <&WantsToAttack>::query().iter(&ecs).map(|attack| (archer, *attack)).next()
+    }
+
+    #[test]
+    fn an_archer_shoots_only_with_a_clear_line_of_fire() {
+        let (archer, attack) = archer_attack(false).unwrap();
+        assert_eq!(attack.attacker, archer);
+        assert_eq!(attack.weapon, Some(archer));
+        assert!(archer_attack(true).is_none());
+    }
+
+    #[test]
+    fn a_guard_returns_to_its_post() {
+        let post = Point::new(3, 3);
+        let step = next_step(
+            vec![(Enemy, Point::new(6, 3), FieldOfView::new(6), Health { current: 4, max: 4 }, Guarding { post, radius: 4 })],
+            Point::new(15, 15),
+        );
+        assert_eq!(step, Some(Point::new(5, 3)));
+    }
+
+    #[test]
+    fn a_patrol_picks_another_waypoint_when_its_waypoint_is_walled_off() {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        let mut map = Map::new(20, 20);
+        let walled = Point::new(15, 15);
+        let idx = map.point2d_to_index(walled);
+        map.tiles[idx] = TileType::Wall;
+        resources.insert(map);
+        resources.insert(RandomNumberGenerator::seeded(1));
+        ecs.push((Player { map_level: 0 }, Point::new(18, 1), Health { current: 10, max: 10 }));
+        let start = Point::new(3, 3);
+        let patrol = Patrolling { waypoints: vec![walled, Point::new(8, 3)], waypoint: Some(walled) };
+        let ogre = ecs.push((Enemy, start, FieldOfView::new(6), Health { current: 4, max: 4 }, patrol));
+        let mut schedule = Schedule::builder().add_system(super::behaviours_system()).build();
+        schedule.execute(&mut ecs, &mut resources);
+        let patrol = ecs.entry(ogre).unwrap().get_component::<Patrolling>().unwrap().clone();
+        assert_eq!(patrol.waypoint, Some(Point::new(8, 3)));
+        let step = <&WantsToMove>::query().iter(&ecs).map(|m| m.destination).next();
+        assert_eq!(step, Some(Point::new(4, 3)));
+    }
+}
@@ -6,37 +6,42 @@ use crate::prelude::*;
 #[read_component(FieldOfView)]
 #[read_component(Health)]
 #[read_component(Player)]
+#[read_component(Confused)]
+#[read_component(Fleeing)]
+#[read_component(Energy)]
 pub fn chasing(
     #[resource] map: &Map,
     ecs: &SubWorld,
     commands: &mut CommandBuffer,
 ) {
-    let mut movers = <(Entity, &Point, &ChasingPlayer, &FieldOfView)>::query();
+    // 陷入混乱的怪物随机移动，正在逃跑的怪物由behaviours系统处理，都不追击玩家
+    let mut movers = <(Entity, &Point, &ChasingPlayer, &FieldOfView, Option<&Energy>)>::query()
+        .filter(!component::<Confused>() & !component::<Fleeing>());
     let mut positions = <(Entity, &Point, &Health)>::query();
     let mut player = <(&Point, &Player)>::query();
     // 查询玩家所在的位置
-    let player_pos = player.iter(ecs).nth(0).unwrap().0;
+    let player_pos = player.iter(ecs).next().unwrap().0;
     // 玩家所在的图块索引编号
-    let player_idx = map_idx(player_pos.x, player_pos.y);
+    let player_idx = map.idx(player_pos.x, player_pos.y);
 
     // 创建一个包含玩家角色当前坐标的向量，作为起始点
     let search_targets = vec![player_idx];
     // 初始化迪杰斯特拉图，1024表示在停止计算前可以走出的最远距离
     let dijkstra_map = DijkstraMap::new(
-        SCREEN_WIDTH,
-        SCREEN_HEIGHT,
+        map.width,
+        map.height,
         &search_targets,
         map,
         1024.0,
     );
 
     // 追击玩家：如果怪物与玩家处于相邻位置，一定会攻击玩家，如果不相邻，怪物沿着迪杰斯特拉图的路线追击玩家。
-    movers.iter(ecs).for_each(|(entity, pos, _, fov)| {
-        // 如果怪物的视野中没有玩家，就不用追击玩家
-        if !fov.visible_tiles.contains(&player_pos) {
+    movers.iter(ecs).for_each(|(entity, pos, _, fov, energy)| {
+        // 如果怪物的视野中没有玩家，或者还没有攒够行动的能量，就不用追击玩家
+        if !fov.visible_tiles.contains(player_pos) || !can_act(energy) {
             return;
         }
-        let idx = map_idx(pos.x, pos.y);
+        let idx = map.idx(pos.x, pos.y);
         // 找到最近的一个位置
         if let Some(destination) = DijkstraMap::find_lowest_exit(&dijkstra_map, idx, map) {
             // 计算怪物与玩家的距离
@@ -57,6 +62,7 @@ pub fn chasing(
                         commands.push(((), WantsToAttack {
                             attacker: *entity,
                             victim: *victim,
+                            weapon: None,
                         }));
                     }
                     attacked = true;
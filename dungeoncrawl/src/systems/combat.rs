@@ -1,40 +1,117 @@
+use legion::world::EntryRef;
 use crate::prelude::*;
 
+// 基础命中率（百分比），加上攻击者的命中值，再减去被攻击者的闪避值
+const BASE_HIT_CHANCE: i32 = 80;
+// 命中率的下限，闪避值再高也有机会被击中
+const MIN_HIT_CHANCE: i32 = 5;
+// 暴击率（百分比），暴击一定命中，伤害乘以暴击倍数
+const CRIT_CHANCE: i32 = 5;
+const CRIT_MULTIPLIER: i32 = 2;
+// 狂暴的首领额外造成的伤害
+const ENRAGED_BONUS: i32 = 2;
+
 #[system]
 #[read_component(WantsToAttack)]
 #[read_component(Player)]
 #[write_component(Health)]
 #[read_component(Damage)]
-#[read_component(Carried)]
-pub fn combat(ecs: &mut SubWorld, commands: &mut CommandBuffer) {
+#[read_component(DamageDice)]
+#[read_component(Defence)]
+#[read_component(Accuracy)]
+#[read_component(Evasion)]
+#[read_component(Equipped)]
+#[read_component(Item)]
+#[read_component(ProvidesStatus)]
+#[read_component(Hunger)]
+#[read_component(Enraged)]
+pub fn combat(ecs: &mut SubWorld,
+              commands: &mut CommandBuffer,
+              #[resource] rng: &mut RandomNumberGenerator,
+              #[resource] stats: &mut GameStats) {
     // 希望发起攻击的实体列表
     let mut attackers = <(Entity, &WantsToAttack)>::query();
     // 被攻击者的列表，根据攻击者信息来计算它们产生的破坏力输出
-    let victims: Vec<(Entity, Entity, Entity)> = attackers
+    let victims: Vec<(Entity, Entity, Entity, Option<Entity>)> = attackers
         .iter(ecs)
-        .map(|(entity, attack)| (*entity, attack.attacker, attack.victim))
+        .map(|(entity, attack)| (*entity, attack.attacker, attack.victim, attack.weapon))
         .collect();
-    victims.iter().for_each(|(message, attacker, victim)| {
-        // 获取玩家角色
-        let is_player = ecs.entry_ref(*victim).unwrap().get_component::<Player>().is_ok();
-        // 获得攻击者的基础伤害值
-        let base_damage = if let Ok(v) = ecs.entry_ref(*attacker) {
-            if let Ok(dmg) = v.get_component::<Damage>() {
-                dmg.0
-            } else {
-                0
+    victims.iter().for_each(|(message, attacker, victim, weapon)| {
+        let attacker_is_player = ecs.entry_ref(*attacker)
+            .map(|e| e.get_component::<Player>().is_ok())
+            .unwrap_or(false);
+        // 投掷物品和卷轴只造成物品本身的伤害，使用之后消失；弓这样已经装备的远程武器和近战攻击的伤害相同，
+        // 弓箭手这样自身可以远程攻击的怪物也一样
+        let thrown = weapon.filter(|item| ecs.entry_ref(*item)
+            .map(|e| e.get_component::<Item>().is_ok() && e.get_component::<Equipped>().is_err())
+            .unwrap_or(false));
+        if let Some(item) = thrown {
+            commands.remove(item);
+            if attacker_is_player {
+                stats.items_used += 1;
             }
+        }
+
+        // 掷百分骰决定是否命中以及是否暴击
+        let hit_chance = i32::max(
+            MIN_HIT_CHANCE,
+            BASE_HIT_CHANCE + stat_total(ecs, *attacker, |a: &Accuracy| a.0) - stat_total(ecs, *victim, |e: &Evasion| e.0),
+        );
+        let roll = rng.range(1, 101);
+        let outcome = if roll <= CRIT_CHANCE {
+            AttackOutcome::Critical
+        } else if roll <= hit_chance {
+            AttackOutcome::Hit
         } else {
-            0
+            AttackOutcome::Miss
         };
-        // 获得携带武器的伤害值总和
-        let weapon_damage: i32 = <(&Carried, &Damage)>::query().iter(ecs)
-            .filter(|(carried, _)| carried.0 == *attacker)
-            .map(|(_, dmg)| dmg.0)
-            .sum();
-        // 计算最终伤害值
-        let final_damage = base_damage + weapon_damage;
+        if outcome == AttackOutcome::Miss {
+            commands.push(((), CombatEvent {
+                attacker: *attacker, victim: *victim, weapon: *weapon, outcome, damage: 0, killed: false,
+            }));
+            commands.remove(*message);
+            return;
+        }
 
+        let attack_damage = match thrown {
+            Some(item) => {
+                let item = ecs.entry_ref(item).unwrap();
+                // 投掷物品提供的状态效果施加给被击中的目标，例如毒镖和混乱卷轴
+                if let Ok(status) = item.get_component::<ProvidesStatus>() {
+                    status.0.iter().for_each(|effect| effect.apply(*victim, commands));
+                }
+                roll_damage(&item, rng)
+            }
+            // 攻击者的基础伤害加上已装备物品的伤害
+            None => {
+                let equipped: Vec<Entity> = <(Entity, &Equipped)>::query().iter(ecs)
+                    .filter(|(_, equipped)| equipped.owner == *attacker)
+                    .map(|(entity, _)| *entity)
+                    .collect();
+                std::iter::once(*attacker)
+                    .chain(equipped)
+                    .filter_map(|entity| ecs.entry_ref(entity).ok())
+                    .map(|entry| roll_damage(&entry, rng))
+                    .sum()
+            }
+        };
+        let attack_damage = match outcome {
+            AttackOutcome::Critical => attack_damage * CRIT_MULTIPLIER,
+            _ => attack_damage,
+        };
+        // 饥饿的攻击者攻击力降低
+        let hunger_penalty = ecs.entry_ref(*attacker)
+            .ok()
+            .and_then(|a| a.get_component::<Hunger>().ok().map(|h| h.state().damage_penalty()))
+            .unwrap_or(0);
+        let enraged_bonus = ecs.entry_ref(*attacker)
+            .map(|a| if a.get_component::<Enraged>().is_ok() { ENRAGED_BONUS } else { 0 })
+            .unwrap_or(0);
+        let attack_damage = attack_damage - hunger_penalty + enraged_bonus;
+        // 计算最终伤害值，被攻击者自身和已装备物品的防御值最多抵消全部伤害
+        let final_damage = i32::max(0, attack_damage - stat_total(ecs, *victim, |d: &Defence| d.0));
+
+        let mut killed = false;
         // 针对只包含生命值的被攻击对象执行操作
         if let Ok(health) = ecs
             .entry_mut(*victim)
@@ -42,13 +119,227 @@ pub fn combat(ecs: &mut SubWorld, commands: &mut CommandBuffer) {
             .get_component_mut::<Health>()
         {
             // println!("Health before attack: {}", health.current);
+            let was_alive = health.current > 0;
             health.current -= final_damage;
-            // 消灭怪物
-            if health.current < 1 && !is_player {
-                commands.remove(*victim);
-            }
+            // 同一个回合中再次击中已经被消灭的怪物不会重复计算
+            killed = was_alive && health.current < 1;
             // println!("Health after attack: {}", health.current);
         }
+        // 消息记录、消灭怪物和奖励由combat_results系统根据战斗事件处理
+        commands.push(((), CombatEvent {
+            attacker: *attacker, victim: *victim, weapon: *weapon, outcome, damage: final_damage, killed,
+        }));
         commands.remove(*message);
     });
-}
\ No newline at end of file
+}
+
+// 实体造成的伤害：固定伤害值加上伤害骰子掷出的点数
+pub fn roll_damage(entry: &EntryRef, rng: &mut RandomNumberGenerator) -> i32 {
+    entry.get_component::<Damage>().map_or(0, |d| d.0)
+        + entry.get_component::<DamageDice>().map_or(0, |dice| rng.roll(dice.0))
+}
+
+/*
+ * 消灭生命值耗尽的怪物：移除实体，记录消灭的消息和独特怪物的遗言。
+ * 只有凶手是玩家角色时才计入击杀数并获得经验值，怪物之间的误伤不算。
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn slay_monster(ecs: &impl EntityStore,
+                    commands: &mut CommandBuffer,
+                    log: &mut MessageLog,
+                    stats: &mut GameStats,
+                    victim: Entity,
+                    killer: Option<Entity>,
+                    message: String,
+                    color: (u8, u8, u8)) {
+    commands.remove(victim);
+    log.add(message, color);
+    if let Some(last_words) = death_message(ecs, victim) {
+        log.add(last_words, MAGENTA);
+    }
+    let player = killer.filter(|k| ecs.entry_ref(*k).is_ok_and(|e| e.get_component::<Player>().is_ok()));
+    if let Some(player) = player {
+        let xp_reward = ecs.entry_ref(victim)
+            .ok()
+            .and_then(|v| v.get_component::<ExperienceValue>().ok().map(|xp| xp.0))
+            .unwrap_or(0);
+        stats.kills += 1;
+        commands.push(((), GainExperience { entity: player, amount: xp_reward }));
+    }
+}
+
+// 实体自身和已装备物品的某项属性之和，例如防御值、命中值和闪避值
+fn stat_total<T: storage::Component>(ecs: &SubWorld, entity: Entity, value: impl Fn(&T) -> i32) -> i32 {
+    let own = ecs.entry_ref(entity)
+        .ok()
+        .and_then(|e| e.get_component::<T>().ok().map(&value))
+        .unwrap_or(0);
+    let equipped: i32 = <(&Equipped, &T)>::query().iter(ecs)
+        .filter(|(equipped, _)| equipped.owner == entity)
+        .map(|(_, t)| value(t))
+        .sum();
+    own + equipped
+}
+
+/*
+ * 战斗结果：根据战斗系统产生的战斗事件记录命中、未命中和暴击的消息，
+ * 并且消灭生命值耗尽的怪物，玩家角色的死亡在回合结束时处理。
+ */
+#[system]
+#[read_component(CombatEvent)]
+#[read_component(Player)]
+#[read_component(Name)]
+#[read_component(Unique)]
+#[read_component(ExperienceValue)]
+#[read_component(DeathMessage)]
+pub fn combat_results(ecs: &SubWorld,
+                      commands: &mut CommandBuffer,
+                      #[resource] stats: &mut GameStats,
+                      #[resource] log: &mut MessageLog) {
+    let events: Vec<CombatEvent> = <&CombatEvent>::query().iter(ecs).copied().collect();
+    events.iter().for_each(|event| {
+        // 消息记录中攻击者和被攻击者的称呼
+        let attacker_name = name_of(ecs, event.attacker);
+        let victim_name = name_of(ecs, event.victim);
+        let is_player = ecs.entry_ref(event.victim).is_ok_and(|v| v.get_component::<Player>().is_ok());
+        if event.outcome == AttackOutcome::Miss {
+            log.add(format!("{} {} {}.", attacker_name, verb(&attacker_name, "miss", "misses"), victim_name), GRAY);
+            return;
+        }
+        // 玩家角色受到的伤害显示为红色，暴击显示为橙色
+        let color = match event.outcome {
+            AttackOutcome::Critical => ORANGE,
+            _ if is_player => RED,
+            _ => WHITE,
+        };
+        let critical = if event.outcome == AttackOutcome::Critical { " Critical hit!" } else { "" };
+        log.add(
+            format!("{} {} {} for {} damage.{}",
+                    attacker_name, verb(&attacker_name, "hit", "hits"), victim_name, event.damage, critical),
+            color,
+        );
+        if event.killed {
+            let message = format!("{} {} {}!", attacker_name, verb(&attacker_name, "kill", "kills"), victim_name);
+            if is_player {
+                log.add(message, RED);
+            } else {
+                slay_monster(ecs, commands, log, stats, event.victim, Some(event.attacker), message, GOLD);
+            }
+        }
+    });
+}
+
+// 战斗事件由combat_results等系统处理之后，在回合结束前删除
+#[system]
+#[read_component(CombatEvent)]
+pub fn clear_combat_events(ecs: &SubWorld, commands: &mut CommandBuffer) {
+    <Entity>::query()
+        .filter(component::<CombatEvent>())
+        .iter(ecs)
+        .for_each(|event| commands.remove(*event));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // 只包含攻击者、被攻击者和战斗系统的世界，让攻击者攻击count次，返回所有的战斗事件
+    fn resolve_attacks(attacker: impl storage::IntoComponentSource, victim: impl storage::IntoComponentSource, count: usize) -> Vec<CombatEvent> {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        resources.insert(RandomNumberGenerator::seeded(42));
+        resources.insert(GameStats::default());
+        resources.insert(MessageLog::default());
+        let attacker = ecs.extend(attacker)[0];
+        let victim = ecs.extend(victim)[0];
+        let mut schedule = Schedule::builder()
+            .add_system(super::combat_system())
+            .flush()
+            .add_system(super::combat_results_system())
+            .build();
+        let mut events = Vec::new();
+        for _ in 0..count {
+            ecs.push(((), WantsToAttack { attacker, victim, weapon: None }));
+            schedule.execute(&mut ecs, &mut resources);
+            let mut query = <(Entity, &CombatEvent)>::query();
+            let found: Vec<(Entity, CombatEvent)> = query.iter(&ecs).map(|(e, event)| (*e, *event)).collect();
+            found.iter().for_each(|(entity, event)| {
+                ecs.remove(*entity);
+                events.push(*event);
+            });
+        }
+        events
+    }
+
+    #[test]
+    fn an_evasive_target_is_only_hit_by_critical_hits() {
+        let events = resolve_attacks(
+            vec![(Damage(3), Accuracy(0))],
+            vec![(Health { current: 1000, max: 1000 }, Evasion(1000))],
+            200,
+        );
+        assert_eq!(events.len(), 200);
+        assert!(events.iter().all(|e| e.outcome != AttackOutcome::Hit));
+        let crits: Vec<&CombatEvent> = events.iter().filter(|e| e.outcome == AttackOutcome::Critical).collect();
+        assert!(!crits.is_empty());
+        assert!(crits.iter().all(|e| e.damage == 6));
+        assert!(events.iter().filter(|e| e.outcome == AttackOutcome::Miss).all(|e| e.damage == 0));
+    }
+
+    #[test]
+    fn a_monster_hit_twice_in_one_turn_is_only_killed_once() {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        resources.insert(RandomNumberGenerator::seeded(42));
+        resources.insert(GameStats::default());
+        resources.insert(MessageLog::default());
+        let player = ecs.push((Player { map_level: 0 }, Damage(5), Accuracy(1000)));
+        let victim = ecs.push((Enemy, Health { current: 1, max: 1 }, ExperienceValue(3)));
+        ecs.push(((), WantsToAttack { attacker: player, victim, weapon: None }));
+        ecs.push(((), WantsToAttack { attacker: player, victim, weapon: None }));
+        let mut schedule = Schedule::builder()
+            .add_system(super::combat_system())
+            .flush()
+            .add_system(super::combat_results_system())
+            .build();
+        schedule.execute(&mut ecs, &mut resources);
+        assert_eq!(resources.get::<GameStats>().unwrap().kills, 1);
+        assert_eq!(<&GainExperience>::query().iter(&ecs).count(), 1);
+    }
+
+    #[test]
+    fn a_monster_killed_by_another_monster_gives_no_reward() {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        resources.insert(RandomNumberGenerator::seeded(42));
+        resources.insert(GameStats::default());
+        resources.insert(MessageLog::default());
+        let attacker = ecs.push((Enemy, Damage(5), Accuracy(1000)));
+        let victim = ecs.push((Enemy, Health { current: 1, max: 1 }, ExperienceValue(3)));
+        ecs.push(((), WantsToAttack { attacker, victim, weapon: None }));
+        let mut schedule = Schedule::builder()
+            .add_system(super::combat_system())
+            .flush()
+            .add_system(super::combat_results_system())
+            .build();
+        schedule.execute(&mut ecs, &mut resources);
+        assert!(ecs.entry_ref(victim).is_err());
+        assert_eq!(resources.get::<GameStats>().unwrap().kills, 0);
+        assert_eq!(<&GainExperience>::query().iter(&ecs).count(), 0);
+    }
+
+    #[test]
+    fn damage_dice_roll_within_their_range() {
+        let dice = DamageDice::parse("1d6+1").unwrap();
+        assert_eq!(dice.describe(), "1d6+1");
+        let events = resolve_attacks(
+            vec![(dice, Accuracy(100))],
+            vec![(Health { current: 10000, max: 10000 }, Defence(0))],
+            200,
+        );
+        assert!(events.iter().all(|e| e.outcome != AttackOutcome::Miss));
+        let hits: Vec<i32> = events.iter().filter(|e| e.outcome == AttackOutcome::Hit).map(|e| e.damage).collect();
+        assert!(hits.iter().all(|d| (2..=7).contains(d)));
+        assert!(hits.contains(&2) && hits.contains(&7));
+    }
+}
@@ -0,0 +1,23 @@
+use crate::prelude::*;
+
+#[system]
+#[read_component(WantsToDrop)]
+#[read_component(Point)]
+#[read_component(Player)]
+#[read_component(Name)]
+pub fn drop_items(ecs: &SubWorld, commands: &mut CommandBuffer, #[resource] log: &mut MessageLog) {
+    // 把物品从物品列表中取出，放在丢弃者所在的位置
+    <(Entity, &WantsToDrop)>::query().iter(ecs)
+        .for_each(|(message, want)| {
+            let pos = ecs.entry_ref(want.entity)
+                .ok()
+                .and_then(|e| e.get_component::<Point>().ok().copied());
+            if let Some(pos) = pos {
+                let user = name_of(ecs, want.entity);
+                log.add(format!("{} {} {}.", user, verb(&user, "drop", "drops"), name_of(ecs, want.item)), WHITE);
+                commands.remove_component::<Carried>(want.item);
+                commands.add_component(want.item, pos);
+            }
+            commands.remove(*message);
+        });
+}
@@ -1,41 +1,374 @@
 use crate::prelude::*;
+use super::combat::slay_monster;
 
 #[system]
-#[read_component(Health)]
+#[write_component(Health)]
+#[write_component(Hunger)]
 #[read_component(Point)]
 #[read_component(Player)]
 #[read_component(AmuletOfYala)]
-pub fn end_turn(ecs: &SubWorld,
+#[read_component(Name)]
+#[read_component(Enemy)]
+#[write_component(Poisoned)]
+#[write_component(Confused)]
+#[write_component(Regenerating)]
+#[write_component(Hasted)]
+#[write_component(Slowed)]
+#[write_component(Telepathic)]
+#[read_component(ExperienceValue)]
+#[read_component(DeathMessage)]
+#[read_component(Speed)]
+#[write_component(Energy)]
+#[read_component(Experience)]
+#[read_component(WantsToTakeStairs)]
+#[read_component(Guardian)]
+pub fn end_turn(ecs: &mut SubWorld,
+                commands: &mut CommandBuffer,
                 #[resource] turn_state: &mut TurnState,
-                #[resource] map: &Map) {
+                #[resource] stats: &mut GameStats,
+                #[resource] log: &mut MessageLog) {
     let mut player_hp = <(&Health, &Point)>::query().filter(component::<Player>());
-    // 获得护身符的位置
-    let mut amulet = <&Point>::query().filter(component::<AmuletOfYala>());
-    // let amulet_pos =  amulet.iter(ecs).nth(0).unwrap();
-    let amulet_default = Point::new(-1, -1);
-    let amulet_pos = amulet.iter(ecs).nth(0).unwrap_or(&amulet_default);
-
-    let current_state = turn_state.clone();
-    // 状态转移
+    // 获得胜利物品（护身符）的位置和名字
+    let mut amulet = <(&Point, &Name)>::query().filter(component::<AmuletOfYala>());
+    // let amulet_pos =  amulet.iter(ecs).next().unwrap();
+    let amulet_default = (Point::new(-1, -1), String::new());
+    let (amulet_pos, amulet_name) = amulet.iter(ecs).next()
+        .map(|(pos, name)| (*pos, name.0.clone()))
+        .unwrap_or(amulet_default);
+
+    // 守护胜利物品的首领，它还活着的时候无法拿起胜利物品
+    let guardian = <&Name>::query()
+        .filter(component::<Guardian>())
+        .iter(ecs)
+        .map(|name| name.0.clone())
+        .next();
+
+    let current_state = *turn_state;
+    /* 状态转移：玩家角色和怪物每次行动消耗能量，没有人攒够能量时经过一个时刻，所有实体按照速度获得能量。
+     * 玩家角色攒够能量时等待输入，否则攒够能量的怪物依次行动，速度快的怪物可以在玩家角色两次行动之间行动多次。
+     */
     let mut new_state = match turn_state {
         TurnState::AwaitingInput => return,
-        TurnState::PlayerTurn => TurnState::MonsterTurn,
-        TurnState::MonsterTurn => TurnState::AwaitingInput,
+        TurnState::PlayerTurn => {
+            spend_energy(ecs, true);
+            // 加速时能量足够，玩家角色可以再行动一次
+            if player_can_act(ecs) {
+                TurnState::AwaitingInput
+            } else {
+                TurnState::MonsterTurn
+            }
+        }
+        TurnState::MonsterTurn => {
+            spend_energy(ecs, false);
+            if monsters_can_act(ecs) {
+                TurnState::MonsterTurn
+            } else {
+                loop {
+                    tick(ecs, commands, stats, log);
+                    if player_can_act(ecs) {
+                        break TurnState::AwaitingInput;
+                    }
+                    if monsters_can_act(ecs) {
+                        break TurnState::MonsterTurn;
+                    }
+                }
+            }
+        }
         _ => current_state
     };
+    // 升级之后先选择天赋，再轮到玩家角色行动
+    let has_perks = <&Experience>::query()
+        .filter(component::<Player>())
+        .iter(ecs)
+        .any(|experience| experience.perks > 0);
+    if new_state == TurnState::AwaitingInput && has_perks {
+        new_state = TurnState::LevelUp;
+    }
+    // 走上楼梯之后前往下一层或者回到上一层
+    <(Entity, &WantsToTakeStairs)>::query().iter(ecs).for_each(|(message, stairs)| {
+        if stairs.down {
+            new_state = TurnState::NextLevel;
+            log.add("You take the stairs down.", CYAN);
+        } else {
+            new_state = TurnState::PreviousLevel;
+            log.add("You take the stairs up.", CYAN);
+        }
+        commands.remove(*message);
+    });
     player_hp.iter(ecs).for_each(|(hp, pos)| {
         // 如果玩家角色的生命值已经耗尽，游戏结束
         if hp.current < 1 {
             new_state = TurnState::GameOver;
+            log.add("You die...", RED);
         }
-        if pos == amulet_pos {
-            new_state = TurnState::Victory;
-        }
-        // 走进地下城的下一层
-        let idx = map.point2d_to_index(*pos);
-        if map.tiles[idx] == TileType::Exit {
-            new_state = TurnState::NextLevel;
+        if *pos == amulet_pos {
+            match &guardian {
+                // 只在玩家角色的回合提醒一次，怪物的回合不重复
+                Some(guardian) if current_state == TurnState::PlayerTurn => log.add(
+                    format!("The {} is bound by {}'s power. Defeat {} first!", amulet_name, guardian, guardian),
+                    MAGENTA,
+                ),
+                Some(_) => {}
+                None => {
+                    new_state = TurnState::Victory;
+                    log.add(format!("You pick up the {}!", amulet_name), GOLD);
+                }
+            }
         }
     });
     *turn_state = new_state;
-}
\ No newline at end of file
+}
+
+// 刚刚行动过的实体消耗一次行动的能量：玩家角色的回合是玩家角色，怪物的回合是攒够能量的怪物
+fn spend_energy(ecs: &mut SubWorld, players_turn: bool) {
+    if players_turn {
+        <&mut Energy>::query()
+            .filter(component::<Player>())
+            .iter_mut(ecs)
+            .for_each(|energy| energy.0 -= Energy::ACTION_COST);
+    } else {
+        <&mut Energy>::query()
+            .filter(component::<Enemy>())
+            .iter_mut(ecs)
+            .filter(|energy| energy.ready())
+            .for_each(|energy| energy.0 -= Energy::ACTION_COST);
+    }
+}
+
+fn player_can_act(ecs: &SubWorld) -> bool {
+    <&Energy>::query().filter(component::<Player>()).iter(ecs).any(|energy| energy.ready())
+}
+
+fn monsters_can_act(ecs: &SubWorld) -> bool {
+    <&Energy>::query().filter(component::<Enemy>()).iter(ecs).any(|energy| energy.ready())
+}
+
+// 经过一个时刻：所有实体按照速度获得能量，结算状态效果，玩家角色消耗食物
+fn tick(ecs: &mut SubWorld, commands: &mut CommandBuffer, stats: &mut GameStats, log: &mut MessageLog) {
+    stats.turns += 1;
+    <(&mut Energy, Option<&Speed>, Option<&Hasted>, Option<&Slowed>)>::query()
+        .iter_mut(ecs)
+        .for_each(|(energy, speed, hasted, slowed)| {
+            let mut speed = speed.map(|s| s.0).unwrap_or(Speed::NORMAL);
+            // 加速时速度加倍，减速时速度减半；已经结束的效果在命令缓冲区执行之前仍然存在
+            if hasted.is_some_and(|h| h.turns > 0) {
+                speed *= 2;
+            }
+            if slowed.is_some_and(|s| s.turns > 0) {
+                speed /= 2;
+            }
+            energy.0 += i32::max(1, speed);
+        });
+    tick_status_effects(ecs, commands, stats, log);
+    // 每个时刻消耗食物，饿死之前每个时刻失去1点生命值
+    <(&mut Hunger, &mut Health)>::query()
+        .filter(component::<Player>())
+        .iter_mut(ecs)
+        .for_each(|(hunger, health)| {
+            let before = hunger.state();
+            hunger.food = i32::max(0, hunger.food - 1);
+            // 饥饿程度加深时提醒玩家
+            match hunger.state() {
+                state if state == before => {}
+                HungerState::Hungry => log.add("You are getting hungry.", YELLOW),
+                HungerState::Weak => log.add("You feel weak with hunger.", ORANGE),
+                HungerState::Starving => log.add("You are starving!", RED),
+                HungerState::Fed => {}
+            }
+            if hunger.state() == HungerState::Starving {
+                health.current -= 1;
+            }
+        });
+}
+
+/*
+ * 状态效果按照时刻而不是行动次数结算，行动多的实体不会更快地用完效果或者受到更多的毒害。
+ * 中毒扣除生命值，再生恢复生命值，每种效果的剩余回合数减1，减到0时移除。
+ */
+fn tick_status_effects(ecs: &mut SubWorld, commands: &mut CommandBuffer, stats: &mut GameStats, log: &mut MessageLog) {
+    let affected: Vec<Entity> = <(Entity, &Health)>::query()
+        .iter(ecs)
+        .filter(|(_, health)| health.current > 0)
+        .map(|(entity, _)| *entity)
+        .collect();
+    // 只有玩家角色的武器和物品会让怪物中毒，毒死的怪物算作玩家角色消灭的
+    let player = <Entity>::query().filter(component::<Player>()).iter(ecs).copied().next();
+
+    affected.iter().for_each(|entity| {
+        let name = name_of(ecs, *entity);
+        let mut entry = ecs.entry_mut(*entity).unwrap();
+        let is_player = entry.get_component::<Player>().is_ok();
+        let mut change = 0;
+        if let Some(poison) = entry.get_component_mut::<Poisoned>().ok().filter(|p| p.turns > 0) {
+            change -= poison.dmg;
+            poison.turns -= 1;
+            if poison.turns < 1 {
+                commands.remove_component::<Poisoned>(*entity);
+            }
+        }
+        if let Some(regen) = entry.get_component_mut::<Regenerating>().ok().filter(|r| r.turns > 0) {
+            change += regen.amount;
+            regen.turns -= 1;
+            if regen.turns < 1 {
+                commands.remove_component::<Regenerating>(*entity);
+            }
+        }
+        if let Some(confused) = entry.get_component_mut::<Confused>().ok().filter(|c| c.turns > 0) {
+            confused.turns -= 1;
+            if confused.turns < 1 {
+                commands.remove_component::<Confused>(*entity);
+            }
+        }
+        if let Some(hasted) = entry.get_component_mut::<Hasted>().ok().filter(|h| h.turns > 0) {
+            hasted.turns -= 1;
+            if hasted.turns < 1 {
+                commands.remove_component::<Hasted>(*entity);
+            }
+        }
+        if let Some(slowed) = entry.get_component_mut::<Slowed>().ok().filter(|s| s.turns > 0) {
+            slowed.turns -= 1;
+            if slowed.turns < 1 {
+                commands.remove_component::<Slowed>(*entity);
+            }
+        }
+        if let Some(telepathic) = entry.get_component_mut::<Telepathic>().ok().filter(|t| t.turns > 0) {
+            telepathic.turns -= 1;
+            if telepathic.turns < 1 {
+                commands.remove_component::<Telepathic>(*entity);
+            }
+        }
+        let mut killed = false;
+        if change != 0 {
+            if let Ok(health) = entry.get_component_mut::<Health>() {
+                health.current = i32::min(health.max, health.current + change);
+                killed = health.current < 1;
+            }
+        }
+        // 毒死的怪物和被消灭的怪物一样移除，玩家角色的死亡在回合结束时处理
+        if killed && !is_player {
+            slay_monster(ecs, commands, log, stats, *entity, player, format!("{} dies of poison.", name), GREEN);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // 没有怪物的游戏，状态效果的结算不受怪物攻击的影响
+    fn game_without_monsters() -> (Game, Entity) {
+        let mut game = Game::new(GameSeed(2));
+        let enemies: Vec<Entity> = <Entity>::query().filter(component::<Enemy>()).iter(&game.ecs).copied().collect();
+        enemies.iter().for_each(|e| { game.ecs.remove(*e); });
+        let player = *<Entity>::query().filter(component::<Player>()).iter(&game.ecs).next().unwrap();
+        (game, player)
+    }
+
+    // 原地等待一个回合
+    fn wait(game: &mut Game) {
+        game.step(Some(VirtualKeyCode::Space));
+        while game.turn_state() != TurnState::AwaitingInput {
+            game.step(None);
+        }
+    }
+
+    #[test]
+    fn poison_hurts_every_turn_and_wears_off() {
+        let (mut game, player) = game_without_monsters();
+        game.ecs.entry(player).unwrap().add_component(Poisoned { dmg: 1, turns: 2 });
+        let max = game.ecs.entry_ref(player).unwrap().get_component::<Health>().unwrap().max;
+        for _ in 0..3 {
+            wait(&mut game);
+        }
+        let entry = game.ecs.entry_ref(player).unwrap();
+        assert_eq!(entry.get_component::<Health>().unwrap().current, max - 2);
+        assert!(entry.get_component::<Poisoned>().is_err());
+    }
+
+    #[test]
+    fn haste_gives_the_player_extra_actions() {
+        let (mut game, player) = game_without_monsters();
+        game.ecs.entry(player).unwrap().add_component(Hasted { turns: 2 });
+        // 加速持续2个回合，玩家角色在这段时间里行动4次
+        for _ in 0..4 {
+            wait(&mut game);
+        }
+        assert_eq!(game.resources.get::<GameStats>().unwrap().turns, 2);
+        assert!(game.ecs.entry_ref(player).unwrap().get_component::<Hasted>().is_err());
+    }
+
+    #[test]
+    fn a_fast_monster_is_poisoned_per_turn_not_per_action() {
+        let (mut game, _) = game_without_monsters();
+        let goblin = game.ecs.push((Enemy, Point::new(0, 0), Health { current: 100, max: 100 },
+                                    Poisoned { dmg: 1, turns: 10 }, Energy::new(), Speed(150)));
+        for _ in 0..4 {
+            wait(&mut game);
+        }
+        let entry = game.ecs.entry_ref(goblin).unwrap();
+        assert_eq!(entry.get_component::<Health>().unwrap().current, 96);
+        assert_eq!(entry.get_component::<Poisoned>().unwrap().turns, 6);
+    }
+
+    #[test]
+    fn starving_drains_health_until_the_player_eats() {
+        let mut game = Game::new(GameSeed(6));
+        let enemies: Vec<Entity> = <Entity>::query().filter(component::<Enemy>()).iter(&game.ecs).copied().collect();
+        enemies.iter().for_each(|e| { game.ecs.remove(*e); });
+        let player = *<Entity>::query().filter(component::<Player>()).iter(&game.ecs).next().unwrap();
+        game.ecs.entry(player).unwrap().add_component(Hunger { food: 2 });
+        game.ecs.push((Item, Name("Ration".to_string()), ProvidesFood { amount: 600 }, Carried(player)));
+
+        let take_turn = |game: &mut Game, key| {
+            game.step(Some(key));
+            while game.turn_state() != TurnState::AwaitingInput {
+                game.step(None);
+            }
+        };
+        for _ in 0..4 {
+            take_turn(&mut game, VirtualKeyCode::Space);
+        }
+        let entry = game.ecs.entry_ref(player).unwrap();
+        assert_eq!(entry.get_component::<Hunger>().unwrap().state(), HungerState::Starving);
+        // 第2个回合结束时开始挨饿，之后每回合失去1点生命值
+        assert_eq!(entry.get_component::<Health>().unwrap().current, 7);
+
+        take_turn(&mut game, VirtualKeyCode::Key1);
+        let entry = game.ecs.entry_ref(player).unwrap();
+        assert_eq!(entry.get_component::<Hunger>().unwrap().food, 599);
+        assert_eq!(entry.get_component::<Hunger>().unwrap().state(), HungerState::Fed);
+    }
+
+    #[test]
+    fn fast_monsters_act_more_often_than_slow_ones() {
+        let attacks_in_four_turns = |speed: i32| {
+            let mut game = Game::new(GameSeed(6));
+            let enemies: Vec<Entity> = <Entity>::query().filter(component::<Enemy>()).iter(&game.ecs).copied().collect();
+            enemies.iter().for_each(|e| { game.ecs.remove(*e); });
+            let pos = <&Point>::query().filter(component::<Player>()).iter(&game.ecs).copied().next().unwrap();
+            let next_to_player = {
+                let map = game.resources.get::<Map>().unwrap();
+                [Point::new(-1, 0), Point::new(1, 0), Point::new(0, -1), Point::new(0, 1)]
+                    .iter()
+                    .map(|delta| pos + *delta)
+                    .find(|pt| map.can_enter_tile(*pt))
+                    .unwrap()
+            };
+            // 没有伤害的怪物，每次行动都会攻击玩家角色
+            game.ecs.push((Enemy, next_to_player, Name("Rat".to_string()), Health { current: 1, max: 1 },
+                           FieldOfView::new(6), ChasingPlayer, Energy::new(), Speed(speed)));
+            for _ in 0..4 {
+                game.step(Some(VirtualKeyCode::Space));
+                while game.turn_state() != TurnState::AwaitingInput {
+                    game.step(None);
+                }
+            }
+            let log = game.resources.get::<MessageLog>().unwrap();
+            log.entries.iter().filter(|entry| entry.text.starts_with("The Rat")).count()
+        };
+        assert_eq!(attacks_in_four_turns(100), 4);
+        // 第一个回合之后，速度加倍的怪物每回合行动两次，速度减半的怪物每两个回合行动一次
+        assert_eq!(attacks_in_four_turns(200), 7);
+        assert_eq!(attacks_in_four_turns(50), 2);
+    }
+}
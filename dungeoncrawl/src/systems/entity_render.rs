@@ -10,21 +10,25 @@ use crate::prelude::*;
 #[read_component(Render)]
 #[read_component(FieldOfView)]
 #[read_component(Player)]
+#[read_component(Enemy)]
+#[read_component(Telepathic)]
 pub fn entity_render(#[resource] camera: &Camera,
                      ecs: &SubWorld,) {
-    let mut renderables = <(&Point, &Render)>::query();
     let mut fov = <&FieldOfView>::query().filter(component::<Player>());
     // 开启一个新批量绘制
     let mut draw_batch = DrawBatch::new();
     draw_batch.target(1);
     let offset = Point::new(camera.left_x, camera.top_y);
     // 获取玩家的视野
-    let player_fov = fov.iter(ecs).nth(0).unwrap();
+    let player_fov = fov.iter(ecs).next().unwrap();
 
-    renderables
+    // 心灵感应时视野之外的怪物也会显示出来
+    let telepathic = <&Telepathic>::query().filter(component::<Player>()).iter(ecs).next().is_some();
+
+    <(&Point, &Render, Option<&Enemy>)>::query()
         .iter(ecs)
-        .filter(|(pos, _)| player_fov.visible_tiles.contains(&pos))
-        .for_each(|(pos, render)| {
+        .filter(|(pos, _, enemy)| player_fov.visible_tiles.contains(pos) || (telepathic && enemy.is_some()))
+        .for_each(|(pos, render, _)| {
             draw_batch.set(
                 *pos - offset,
                 render.color,
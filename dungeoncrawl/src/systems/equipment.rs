@@ -0,0 +1,89 @@
+use crate::prelude::*;
+
+#[system]
+#[read_component(WantsToEquip)]
+#[read_component(WantsToUnequip)]
+#[read_component(Equippable)]
+#[read_component(Equipped)]
+#[read_component(Player)]
+#[read_component(Name)]
+pub fn equipment(ecs: &SubWorld, commands: &mut CommandBuffer, #[resource] log: &mut MessageLog) {
+    let mut equipped = <(Entity, &Equipped)>::query();
+
+    // 装备物品：先把同一栏位中原来的装备放回物品列表，再把新的装备从物品列表中取出
+    <(Entity, &WantsToEquip)>::query().iter(ecs)
+        .for_each(|(message, want)| {
+            let slot = ecs.entry_ref(want.item)
+                .ok()
+                .and_then(|item| item.get_component::<Equippable>().ok().map(|e| e.slot));
+            if let Some(slot) = slot {
+                equipped.iter(ecs)
+                    .filter(|(_, e)| e.owner == want.entity && e.slot == slot)
+                    .for_each(|(item, _)| {
+                        commands.remove_component::<Equipped>(*item);
+                        commands.add_component(*item, Carried(want.entity));
+                    });
+                let user = name_of(ecs, want.entity);
+                log.add(format!("{} {} {}.", user, verb(&user, "equip", "equips"), name_of(ecs, want.item)), WHITE);
+                commands.remove_component::<Carried>(want.item);
+                commands.add_component(want.item, Equipped { owner: want.entity, slot });
+            }
+            commands.remove(*message);
+        });
+
+    // 卸下装备，放回物品列表
+    <(Entity, &WantsToUnequip)>::query().iter(ecs)
+        .for_each(|(message, want)| {
+            equipped.iter(ecs)
+                .filter(|(_, e)| e.owner == want.entity && e.slot == want.slot)
+                .for_each(|(item, _)| {
+                    let user = name_of(ecs, want.entity);
+                    log.add(format!("{} {} {}.", user, verb(&user, "unequip", "unequips"), name_of(ecs, *item)), WHITE);
+                    commands.remove_component::<Equipped>(*item);
+                    commands.add_component(*item, Carried(want.entity));
+                });
+            commands.remove(*message);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // 按下一个键，并运行游戏直到重新等待玩家输入
+    fn take_turn(game: &mut Game, key: VirtualKeyCode) {
+        game.step(Some(key));
+        while game.turn_state() != TurnState::AwaitingInput {
+            game.step(None);
+        }
+    }
+
+    fn is_equipped(game: &Game, item: Entity) -> bool {
+        let entry = game.ecs.entry_ref(item).unwrap();
+        entry.get_component::<Equipped>().is_ok() && entry.get_component::<Carried>().is_err()
+    }
+
+    #[test]
+    fn equipping_swaps_the_item_in_the_same_slot() {
+        let mut game = Game::new(GameSeed(5));
+        let player = *<Entity>::query().filter(component::<Player>()).iter(&game.ecs).next().unwrap();
+        let rusty = game.ecs.push((Item, Name("Rusty Sword".to_string()), Damage(1),
+                                   Equippable { slot: EquipmentSlot::MainHand }, Carried(player)));
+        let shiny = game.ecs.push((Item, Name("Shiny Sword".to_string()), Damage(2),
+                                   Equippable { slot: EquipmentSlot::MainHand }, Carried(player)));
+
+        take_turn(&mut game, VirtualKeyCode::Key1);
+        assert!(is_equipped(&game, rusty));
+        assert!(!is_equipped(&game, shiny));
+
+        // 装备到同一个栏位时，原来的装备放回物品列表
+        take_turn(&mut game, VirtualKeyCode::Key1);
+        assert!(is_equipped(&game, shiny));
+        assert!(!is_equipped(&game, rusty));
+        assert_eq!(game.ecs.entry_ref(rusty).unwrap().get_component::<Carried>().unwrap().0, player);
+
+        take_turn(&mut game, VirtualKeyCode::F1);
+        assert!(!is_equipped(&game, shiny));
+        assert_eq!(<&Equipped>::query().iter(&game.ecs).count(), 0);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::prelude::*;
+
+// 每次升级增加的最大生命值和基础伤害
+const HEALTH_PER_LEVEL: i32 = 3;
+const DAMAGE_PER_LEVEL: i32 = 1;
+
+/*
+ * 经验值：消灭怪物获得经验值，达到升级所需的经验值时升级，超出的部分计入下一级。
+ * 每次升级增加最大生命值和基础伤害并恢复全部生命值，同时得到一项天赋，回合结束时打开升级界面选择。
+ */
+#[system]
+#[read_component(GainExperience)]
+#[write_component(Experience)]
+#[write_component(Health)]
+#[write_component(Damage)]
+pub fn experience(ecs: &mut SubWorld, commands: &mut CommandBuffer, #[resource] log: &mut MessageLog) {
+    let gains: Vec<(Entity, Entity, i32)> = <(Entity, &GainExperience)>::query()
+        .iter(ecs)
+        .map(|(message, gain)| (*message, gain.entity, gain.amount))
+        .collect();
+    gains.iter().for_each(|(message, entity, amount)| {
+        if let Ok(mut entry) = ecs.entry_mut(*entity) {
+            let mut levels = 0;
+            if let Ok(experience) = entry.get_component_mut::<Experience>() {
+                experience.xp += amount;
+                while experience.xp >= experience.next_level() {
+                    experience.xp -= experience.next_level();
+                    experience.level += 1;
+                    experience.perks += 1;
+                    levels += 1;
+                    log.add(format!("Welcome to level {}!", experience.level), MAGENTA);
+                }
+            }
+            if levels > 0 {
+                if let Ok(health) = entry.get_component_mut::<Health>() {
+                    health.max += HEALTH_PER_LEVEL * levels;
+                    health.current = health.max;
+                }
+                if let Ok(damage) = entry.get_component_mut::<Damage>() {
+                    damage.0 += DAMAGE_PER_LEVEL * levels;
+                }
+            }
+        }
+        commands.remove(*message);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn killing_monsters_levels_the_player_up() {
+        let mut game = Game::new(GameSeed(4));
+        let enemies: Vec<Entity> = <Entity>::query().filter(component::<Enemy>()).iter(&game.ecs).copied().collect();
+        enemies.iter().for_each(|e| { game.ecs.remove(*e); });
+        let (player, pos) = <(Entity, &Point)>::query()
+            .filter(component::<Player>())
+            .iter(&game.ecs)
+            .map(|(entity, pos)| (*entity, *pos))
+            .next()
+            .unwrap();
+        // 玩家角色右边放一个经验值足够升级的怪物
+        let mut map = game.resources.get_mut::<Map>().unwrap();
+        let idx = map.point2d_to_index(pos + Point::new(1, 0));
+        map.tiles[idx] = TileType::Floor;
+        drop(map);
+        game.ecs.push((Enemy, pos + Point::new(1, 0), Health { current: 1, max: 1 }, ExperienceValue(12)));
+
+        game.step(Some(VirtualKeyCode::Right));
+        while game.turn_state() != TurnState::AwaitingInput && game.turn_state() != TurnState::LevelUp {
+            game.step(None);
+        }
+        assert_eq!(game.turn_state(), TurnState::LevelUp);
+        let entry = game.ecs.entry_ref(player).unwrap();
+        assert_eq!(*entry.get_component::<Experience>().unwrap(), Experience { level: 2, xp: 2, perks: 1 });
+        assert_eq!(entry.get_component::<Health>().unwrap().max, 13);
+        assert_eq!(entry.get_component::<Damage>().unwrap().0, 2);
+
+        // 选择第二项天赋：增加基础伤害，选择之后回到游戏，不消耗回合
+        game.step(Some(VirtualKeyCode::Key2));
+        assert_eq!(game.turn_state(), TurnState::AwaitingInput);
+        assert_eq!(game.resources.get::<GameStats>().unwrap().turns, 1);
+        let entry = game.ecs.entry_ref(player).unwrap();
+        assert_eq!(entry.get_component::<Experience>().unwrap().perks, 0);
+        assert_eq!(entry.get_component::<Damage>().unwrap().0, 3);
+    }
+}
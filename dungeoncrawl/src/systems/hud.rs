@@ -6,15 +6,27 @@ use crate::prelude::*;
 #[read_component(Item)]
 #[read_component(Carried)]
 #[read_component(Name)]
+#[read_component(Equipped)]
+#[read_component(Damage)]
+#[read_component(DamageDice)]
+#[read_component(Defence)]
+#[read_component(Poisoned)]
+#[read_component(Confused)]
+#[read_component(Regenerating)]
+#[read_component(Hasted)]
+#[read_component(Slowed)]
+#[read_component(Telepathic)]
+#[read_component(Hunger)]
+#[read_component(Experience)]
 pub fn hud(ecs: &SubWorld) {
     // 筛选出玩家角色对应的组件
     let mut health_query = <&Health>::query().filter(component::<Player>());
-    let player_health = health_query.iter(ecs).nth(0).unwrap();
+    let player_health = health_query.iter(ecs).next().unwrap();
 
     let mut draw_batch = DrawBatch::new();
     // 批量绘制平视显示区
     draw_batch.target(2);
-    draw_batch.print_centered(1, "Explore the Dungeon. Cursor keys to move.");
+    draw_batch.print_centered(1, "Explore the Dungeon. Cursor keys to move, I for the inventory, F to fire, S to search, L for the message log.");
     // 血条起始坐标为0、血条宽度、血条当前值、血条最大值、显示血条（空为红色、满为黑色）
     draw_batch.bar_horizontal(
         Point::zero(),
@@ -30,25 +42,121 @@ pub fn hud(ecs: &SubWorld) {
     );
 
     let player = <(Entity, &Player)>::query().iter(ecs)
-        .find_map(|(entity, _player)| Some(*entity)).unwrap();
+        .map(|(entity, _player)| *entity).next().unwrap();
+    // 等级和升到下一级的经验值进度，显示在左上角
+    if let Ok(experience) = ecs.entry_ref(player).unwrap().get_component::<Experience>() {
+        draw_batch.print_color(
+            Point::new(1, 1),
+            format!("Level {}  XP {} / {}", experience.level, experience.xp, experience.next_level()),
+            ColorPair::new(CYAN, BLACK)
+        );
+    }
     let mut item_query = <(&Item, &Name, &Carried)>::query();
     // 物品列表的渲染位置，在屏幕的第3行
     let mut y = 3;
-    item_query.iter(ecs).filter(|(_, _, carried)| carried.0 == player)
-        .for_each(|(_, name, _)| {
-            // 显示在第3列第y行，显示获取物品的列表
-            draw_batch.print(Point::new(3, y), format!("{} : {}", y - 2, &name.0));
-            y += 1;
-        });
+    let carried: Vec<&Name> = item_query.iter(ecs)
+        .filter(|(_, _, carried)| carried.0 == player)
+        .map(|(_, name, _)| name)
+        .collect();
+    // 只显示可以用数字键使用的前9个物品，其余的物品在物品栏中查看
+    carried.iter().take(9).for_each(|name| {
+        // 显示在第3列第y行，显示获取物品的列表
+        draw_batch.print(Point::new(3, y), format!("{} : {}", y - 2, &name.0));
+        y += 1;
+    });
+    if carried.len() > 9 {
+        draw_batch.print_color(
+            Point::new(3, y),
+            format!("... {} more (I: inventory)", carried.len() - 9),
+            ColorPair::new(GRAY, BLACK)
+        );
+        y += 1;
+    }
     if y > 3 {
         // 添加物品列表标题
         draw_batch.print_color(Point::new(3, 2), "Items carried", ColorPair::new(YELLOW, BLACK));
     }
 
+    // 装备列表显示在物品列表下方，每个栏位一行，F1~F5键卸下对应栏位的装备
+    let equipment: Vec<(EquipmentSlot, String)> = <(&Equipped, &Name)>::query().iter(ecs)
+        .filter(|(equipped, _)| equipped.owner == player)
+        .map(|(equipped, name)| (equipped.slot, name.0.clone()))
+        .collect();
+    if !equipment.is_empty() {
+        y += 1;
+        // 玩家角色的攻击力和防御力
+        let damage: i32 = <(&Equipped, &Damage)>::query().iter(ecs)
+            .filter(|(equipped, _)| equipped.owner == player)
+            .map(|(_, d)| d.0)
+            .sum::<i32>() + ecs.entry_ref(player).ok()
+                .and_then(|p| p.get_component::<Damage>().ok().map(|d| d.0))
+                .unwrap_or(0);
+        // 武器的伤害骰子写在固定伤害值后面，例如1+1d3
+        let dice: Vec<String> = <(&Equipped, &DamageDice)>::query().iter(ecs)
+            .filter(|(equipped, _)| equipped.owner == player)
+            .map(|(_, dice)| dice.describe())
+            .collect();
+        let damage = std::iter::once(damage.to_string()).chain(dice).collect::<Vec<_>>().join("+");
+        let defence: i32 = <(&Equipped, &Defence)>::query().iter(ecs)
+            .filter(|(equipped, _)| equipped.owner == player)
+            .map(|(_, d)| d.0)
+            .sum();
+        draw_batch.print_color(
+            Point::new(3, y),
+            format!("Equipment (damage {}, defence {})", damage, defence),
+            ColorPair::new(YELLOW, BLACK)
+        );
+        y += 1;
+        for (n, slot) in EquipmentSlot::ALL.iter().enumerate() {
+            if let Some((_, name)) = equipment.iter().find(|(s, _)| s == slot) {
+                draw_batch.print(Point::new(3, y), format!("F{} {} : {}", n + 1, slot.name(), name));
+                y += 1;
+            }
+        }
+    }
+
+    // 玩家角色身上的状态效果和剩余回合数，显示在当前关卡的下一行
+    if let Ok(entry) = ecs.entry_ref(player) {
+        let mut statuses = Vec::new();
+        // 饥饿程度
+        if let Ok(hunger) = entry.get_component::<Hunger>() {
+            let color = match hunger.state() {
+                HungerState::Fed => GREEN,
+                HungerState::Hungry => YELLOW,
+                HungerState::Weak => ORANGE,
+                HungerState::Starving => RED,
+            };
+            statuses.push((hunger.state().name().to_string(), color));
+        }
+        if let Ok(poison) = entry.get_component::<Poisoned>() {
+            statuses.push((format!("Poisoned ({})", poison.turns), GREEN));
+        }
+        if let Ok(confused) = entry.get_component::<Confused>() {
+            statuses.push((format!("Confused ({})", confused.turns), MAGENTA));
+        }
+        if let Ok(regen) = entry.get_component::<Regenerating>() {
+            statuses.push((format!("Regenerating ({})", regen.turns), PINK));
+        }
+        if let Ok(hasted) = entry.get_component::<Hasted>() {
+            statuses.push((format!("Hasted ({})", hasted.turns), CYAN));
+        }
+        if let Ok(slowed) = entry.get_component::<Slowed>() {
+            statuses.push((format!("Slowed ({})", slowed.turns), BROWN1));
+        }
+        if let Ok(telepathic) = entry.get_component::<Telepathic>() {
+            statuses.push((format!("Telepathic ({})", telepathic.turns), ORANGE));
+        }
+        let mut x = SCREEN_WIDTH * 2;
+        statuses.iter().rev().for_each(|(text, color)| {
+            draw_batch.print_color_right(Point::new(x, 2), text, ColorPair::new(*color, BLACK));
+            x -= text.len() as i32 + 1;
+        });
+    }
+
     // 显示当前关卡
     let (_player, map_level) = <(Entity, &Player)>::query()
         .iter(ecs)
-        .find_map(|(entity, player)| Some((*entity, player.map_level))).unwrap();
+        .map(|(entity, player)| (*entity, player.map_level)).next().unwrap();
     draw_batch.print_color_right(
         Point::new(SCREEN_WIDTH*2, 1),
         format!("Dungeon Level: {}", map_level + 1),
@@ -0,0 +1,130 @@
+use crate::prelude::*;
+use super::player_input::activate_item;
+
+/*
+ * 物品栏界面：上下方向键选择物品，回车键或U键使用，E键装备，D键丢弃，X键查看详细信息。
+ * 使用、装备和丢弃物品会消耗一个回合，Esc键或I键关闭物品栏，不消耗回合。
+ */
+#[system]
+#[read_component(Player)]
+#[read_component(Item)]
+#[read_component(Carried)]
+#[read_component(Equippable)]
+#[read_component(Ranged)]
+#[read_component(Point)]
+#[read_component(Enemy)]
+#[read_component(FieldOfView)]
+pub fn inventory(
+    ecs: &SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] key: &Option<VirtualKeyCode>,
+    #[resource] map: &Map,
+    #[resource] menu: &mut InventoryMenu,
+    #[resource] targeting: &mut Targeting,
+    #[resource] turn_state: &mut TurnState,
+) {
+    let Some(key) = *key else {
+        return;
+    };
+    let player = <Entity>::query().filter(component::<Player>()).iter(ecs).copied().next().unwrap();
+    let items = carried_items(ecs, player);
+    // 上次打开物品栏之后物品可能已经变少了
+    menu.clamp(items.len());
+    let selected = items.get(menu.selected).copied();
+
+    match key {
+        VirtualKeyCode::Escape | VirtualKeyCode::I => {
+            menu.inspecting = false;
+            *turn_state = TurnState::AwaitingInput;
+        }
+        VirtualKeyCode::Up => {
+            menu.selected = menu.selected.saturating_sub(1);
+            menu.inspecting = false;
+        }
+        VirtualKeyCode::Down => {
+            menu.selected += 1;
+            menu.inspecting = false;
+        }
+        VirtualKeyCode::PageUp => menu.selected = menu.selected.saturating_sub(INVENTORY_PAGE_SIZE),
+        VirtualKeyCode::PageDown => menu.selected += INVENTORY_PAGE_SIZE,
+        VirtualKeyCode::X => menu.inspecting = !menu.inspecting && selected.is_some(),
+        VirtualKeyCode::Return | VirtualKeyCode::U => {
+            if let Some(item) = selected {
+                menu.inspecting = false;
+                *turn_state = activate_item(player, item, ecs, commands, map, targeting);
+            }
+        }
+        VirtualKeyCode::E => {
+            // 只有装备可以装备到栏位上
+            let equippable = selected.filter(|item| ecs.entry_ref(*item)
+                .map(|e| e.get_component::<Equippable>().is_ok())
+                .unwrap_or(false));
+            if let Some(item) = equippable {
+                commands.push(((), WantsToEquip { entity: player, item }));
+                end_turn(menu, turn_state);
+            }
+        }
+        VirtualKeyCode::D => {
+            if let Some(item) = selected {
+                commands.push(((), WantsToDrop { entity: player, item }));
+                end_turn(menu, turn_state);
+            }
+        }
+        _ => {}
+    }
+    menu.clamp(items.len());
+}
+
+// 执行了一个动作，关闭物品栏并结束玩家角色的回合
+fn end_turn(menu: &mut InventoryMenu, turn_state: &mut TurnState) {
+    menu.inspecting = false;
+    *turn_state = TurnState::PlayerTurn;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    fn carry(game: &mut Game, name: &str) -> Entity {
+        let player = *<Entity>::query().filter(component::<Player>()).iter(&game.ecs).next().unwrap();
+        game.ecs.push((Item, Name(name.to_string()), Carried(player)))
+    }
+
+    fn player_pos(game: &Game) -> Point {
+        *<&Point>::query().filter(component::<Player>()).iter(&game.ecs).next().unwrap()
+    }
+
+    #[test]
+    fn closing_the_inventory_does_not_use_a_turn() {
+        let mut game = Game::new(GameSeed(3));
+        carry(&mut game, "Rock");
+        game.step(Some(VirtualKeyCode::I));
+        assert_eq!(game.turn_state(), TurnState::Inventory);
+        game.step(Some(VirtualKeyCode::X));
+        assert!(game.resources.get::<InventoryMenu>().unwrap().inspecting);
+        game.step(Some(VirtualKeyCode::Escape));
+        assert_eq!(game.turn_state(), TurnState::AwaitingInput);
+        assert_eq!(game.resources.get::<GameStats>().unwrap().turns, 0);
+    }
+
+    #[test]
+    fn dropping_puts_the_item_at_the_players_feet() {
+        let mut game = Game::new(GameSeed(3));
+        // 物品多于9个时也可以在物品栏中选中
+        let items: Vec<Entity> = (0..12).map(|n| carry(&mut game, &format!("Rock {}", n))).collect();
+        game.step(Some(VirtualKeyCode::I));
+        for _ in 0..11 {
+            game.step(Some(VirtualKeyCode::Down));
+        }
+        game.step(Some(VirtualKeyCode::D));
+        assert_eq!(game.turn_state(), TurnState::PlayerTurn);
+        while !game.turn_state().awaits_input() {
+            game.step(None);
+        }
+
+        let last = game.ecs.entry_ref(items[11]).unwrap();
+        assert!(last.get_component::<Carried>().is_err());
+        assert_eq!(*last.get_component::<Point>().unwrap(), player_pos(&game));
+        assert_eq!(game.resources.get::<GameStats>().unwrap().turns, 1);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::prelude::*;
+
+// 物品栏窗口在平视显示区图层上的位置和大小
+const LEFT: i32 = 40;
+const TOP: i32 = 20;
+const WIDTH: i32 = 80;
+
+#[system]
+#[read_component(Player)]
+#[read_component(Item)]
+#[read_component(Carried)]
+#[read_component(Name)]
+#[read_component(ProvidesHealing)]
+#[read_component(ProvidesDungeonMap)]
+#[read_component(Equippable)]
+#[read_component(Damage)]
+#[read_component(Defence)]
+#[read_component(DamageDice)]
+#[read_component(Accuracy)]
+#[read_component(Evasion)]
+#[read_component(Ranged)]
+#[read_component(ProvidesStatus)]
+#[read_component(ProvidesAreaDamage)]
+#[read_component(ProvidesChainDamage)]
+#[read_component(ProvidesTeleport)]
+#[read_component(ProvidesFood)]
+pub fn inventory_render(
+    ecs: &SubWorld,
+    #[resource] menu: &InventoryMenu,
+    #[resource] turn_state: &TurnState,
+) {
+    if *turn_state != TurnState::Inventory {
+        return;
+    }
+    let player = <Entity>::query().filter(component::<Player>()).iter(ecs).copied().next().unwrap();
+    let items = carried_items(ecs, player);
+    let page = INVENTORY_PAGE_SIZE as i32;
+    // 查看物品时在列表下方多留出几行显示物品的效果
+    let height = page + if menu.inspecting { 14 } else { 5 };
+
+    let mut draw_batch = DrawBatch::new();
+    draw_batch.target(2);
+    draw_batch.draw_double_box(Rect::with_size(LEFT, TOP, WIDTH, height), ColorPair::new(WHITE, BLACK));
+    draw_batch.print_color(
+        Point::new(LEFT + 2, TOP),
+        format!(" Inventory ({} items) ", items.len()),
+        ColorPair::new(YELLOW, BLACK),
+    );
+
+    if items.is_empty() {
+        draw_batch.print(Point::new(LEFT + 2, TOP + 2), "You are not carrying anything.");
+    }
+    items.iter()
+        .enumerate()
+        .skip(menu.scroll)
+        .take(INVENTORY_PAGE_SIZE)
+        .for_each(|(n, item)| {
+            let name = ecs.entry_ref(*item).ok()
+                .and_then(|e| e.get_component::<Name>().ok().map(|name| name.0.clone()))
+                .unwrap_or_default();
+            let y = TOP + 2 + (n - menu.scroll) as i32;
+            // 高亮显示选中的物品
+            let color = if n == menu.selected {
+                ColorPair::new(BLACK, WHITE)
+            } else {
+                ColorPair::new(WHITE, BLACK)
+            };
+            draw_batch.print_color(Point::new(LEFT + 2, y), format!("{:>3}. {}", n + 1, name), color);
+        });
+    // 列表上方或下方还有物品时提示可以滚动
+    if menu.scroll > 0 {
+        draw_batch.print_color(Point::new(LEFT + WIDTH - 8, TOP + 1), "more ^", ColorPair::new(GRAY, BLACK));
+    }
+    if menu.scroll + INVENTORY_PAGE_SIZE < items.len() {
+        draw_batch.print_color(Point::new(LEFT + WIDTH - 8, TOP + 2 + page), "more v", ColorPair::new(GRAY, BLACK));
+    }
+
+    if menu.inspecting {
+        if let Some(entry) = items.get(menu.selected).and_then(|item| ecs.entry_ref(*item).ok()) {
+            let y = TOP + 4 + page;
+            let name = entry.get_component::<Name>().map(|name| name.0.clone()).unwrap_or_default();
+            draw_batch.print_color(Point::new(LEFT + 2, y), name, ColorPair::new(YELLOW, BLACK));
+            describe_item(&entry).iter().enumerate().for_each(|(n, line)| {
+                draw_batch.print(Point::new(LEFT + 4, y + 1 + n as i32), line);
+            });
+        }
+    }
+
+    draw_batch.print_color(
+        Point::new(LEFT + 2, TOP + height - 1),
+        " Up/Down: select  Enter/U: use  E: equip  D: drop  X: inspect  Esc/I: close ",
+        ColorPair::new(WHITE, BLACK),
+    );
+    draw_batch.submit(10200).expect("Batch error");
+}
@@ -0,0 +1,54 @@
+use crate::prelude::*;
+
+// 数字键1~4选择对应的天赋
+const PERK_KEYS: [VirtualKeyCode; 4] = [
+    VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3, VirtualKeyCode::Key4,
+];
+
+/*
+ * 升级界面：玩家角色每升一级可以选择一项天赋，全部选择完之后回到游戏。
+ * 选择天赋不消耗回合，也不能跳过。
+ */
+#[system]
+#[read_component(Player)]
+#[write_component(Experience)]
+#[write_component(Health)]
+#[write_component(Damage)]
+#[write_component(Defence)]
+#[write_component(FieldOfView)]
+pub fn level_up(
+    ecs: &mut SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] key: &Option<VirtualKeyCode>,
+    #[resource] turn_state: &mut TurnState,
+) {
+    let Some(n) = key.and_then(|key| PERK_KEYS.iter().position(|k| *k == key)) else {
+        return;
+    };
+    let player = <Entity>::query().filter(component::<Player>()).iter(ecs).copied().next().unwrap();
+    let mut entry = ecs.entry_mut(player).unwrap();
+    match Perk::ALL[n] {
+        Perk::Vitality => if let Ok(health) = entry.get_component_mut::<Health>() {
+            health.max += 5;
+            health.current += 5;
+        },
+        Perk::Strength => if let Ok(damage) = entry.get_component_mut::<Damage>() {
+            damage.0 += 1;
+        },
+        // 玩家角色一开始没有基础防御
+        Perk::Toughness => match entry.get_component_mut::<Defence>() {
+            Ok(defence) => defence.0 += 1,
+            Err(_) => commands.add_component(player, Defence(1)),
+        },
+        Perk::Perception => if let Ok(fov) = entry.get_component_mut::<FieldOfView>() {
+            fov.radius += 2;
+            fov.is_dirty = true;
+        },
+    }
+    if let Ok(experience) = entry.get_component_mut::<Experience>() {
+        experience.perks -= 1;
+        if experience.perks < 1 {
+            *turn_state = TurnState::AwaitingInput;
+        }
+    }
+}
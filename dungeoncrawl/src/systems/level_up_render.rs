@@ -0,0 +1,47 @@
+use crate::prelude::*;
+
+// 升级窗口在平视显示区图层上的位置和大小
+const LEFT: i32 = 50;
+const TOP: i32 = 30;
+const WIDTH: i32 = 60;
+const HEIGHT: i32 = 11;
+
+#[system]
+#[read_component(Player)]
+#[read_component(Experience)]
+pub fn level_up_render(ecs: &SubWorld, #[resource] turn_state: &TurnState) {
+    if *turn_state != TurnState::LevelUp {
+        return;
+    }
+    let Some(experience) = <&Experience>::query().filter(component::<Player>()).iter(ecs).next() else {
+        return;
+    };
+
+    let mut draw_batch = DrawBatch::new();
+    draw_batch.target(2);
+    draw_batch.draw_double_box(Rect::with_size(LEFT, TOP, WIDTH, HEIGHT), ColorPair::new(WHITE, BLACK));
+    draw_batch.print_color(
+        Point::new(LEFT + 2, TOP),
+        format!(" Level up! You are now level {} ", experience.level),
+        ColorPair::new(YELLOW, BLACK),
+    );
+    // 连续升了几级时提示还剩几项天赋可以选择
+    draw_batch.print(
+        Point::new(LEFT + 2, TOP + 2),
+        match experience.perks {
+            1 => "Choose a perk:".to_string(),
+            n => format!("Choose a perk ({} left):", n),
+        },
+    );
+    Perk::ALL.iter().enumerate().for_each(|(n, perk)| {
+        let y = TOP + 4 + n as i32;
+        draw_batch.print_color(Point::new(LEFT + 4, y), format!("{}. {}", n + 1, perk.name()), ColorPair::new(CYAN, BLACK));
+        draw_batch.print(Point::new(LEFT + 22, y), perk.describe());
+    });
+    draw_batch.print_color(
+        Point::new(LEFT + 2, TOP + HEIGHT - 1),
+        " 1-4: choose a perk ",
+        ColorPair::new(WHITE, BLACK),
+    );
+    draw_batch.submit(10300).expect("Batch error");
+}
@@ -1,6 +1,7 @@
 use crate::prelude::*;
 
 #[system]
+#[allow(clippy::borrowed_box)]
 #[read_component(FieldOfView)]
 #[read_component(Player)]
 pub fn map_render(#[resource] map: &Map,
@@ -12,26 +13,27 @@ pub fn map_render(#[resource] map: &Map,
     let mut draw_batch = DrawBatch::new();
     draw_batch.target(0);
     // 获取玩家的视野
-    let player_fov = fov.iter(ecs).nth(0).unwrap();
+    let player_fov = fov.iter(ecs).next().unwrap();
 
     // 地图渲染
     for y in camera.top_y..camera.bottom_y {
         for x in camera.left_x..camera.right_x {
             let pt = Point::new(x, y);
             let offset = Point::new(camera.left_x, camera.top_y);
-            let idx = map_idx(x, y);
+            // 跳过地图之外的区域
+            let Some(idx) = map.try_idx(pt) else {
+                continue;
+            };
             // 检查图块是否在玩家视野中，或者是否在已揭示的图块列表中
-            if map.in_bounds(pt) && player_fov.visible_tiles.contains(&pt) | map.revealed_tiles[idx] {
-                let tint = if player_fov.visible_tiles.contains(&pt) {
-                    WHITE
-                } else {
-                    DARK_GRAY
-                };
-                // 获取主题风格并渲染
-                let glyph = theme.tile_to_render(map.tiles[idx]);
+            if player_fov.visible_tiles.contains(&pt) | map.revealed_tiles[idx] {
+                // 视野之外的图块使用主题中较暗的颜色
+                let visible = player_fov.visible_tiles.contains(&pt);
+                // 获取主题风格并渲染，隐藏的陷阱和暗门按照伪装的样子渲染
+                let tile = map.tiles[idx].appearance();
+                let glyph = theme.tile_to_render(tile);
                 draw_batch.set(
                     pt - offset,
-                    ColorPair::new(tint, BLACK),
+                    theme.tile_colors(tile, visible),
                     glyph
                 );
             }
@@ -0,0 +1,33 @@
+use crate::prelude::*;
+
+// 完整消息记录界面一页显示的消息条数
+pub const HISTORY_PAGE_SIZE: usize = (SCREEN_HEIGHT * 2 - 4) as usize;
+
+/*
+ * 完整的消息记录：上下方向键逐行滚动，PageUp、PageDown键翻页，Home键跳到最早的消息，End键回到最新的消息。
+ * Esc键或L键关闭，不消耗回合。
+ */
+#[system]
+pub fn message_history(
+    #[resource] key: &Option<VirtualKeyCode>,
+    #[resource] log: &mut MessageLog,
+    #[resource] turn_state: &mut TurnState,
+) {
+    let Some(key) = *key else {
+        return;
+    };
+    match key {
+        VirtualKeyCode::Escape | VirtualKeyCode::L => {
+            log.scroll = 0;
+            *turn_state = TurnState::AwaitingInput;
+        }
+        VirtualKeyCode::Up => log.scroll += 1,
+        VirtualKeyCode::Down => log.scroll = log.scroll.saturating_sub(1),
+        VirtualKeyCode::PageUp => log.scroll += HISTORY_PAGE_SIZE,
+        VirtualKeyCode::PageDown => log.scroll = log.scroll.saturating_sub(HISTORY_PAGE_SIZE),
+        VirtualKeyCode::Home => log.scroll = log.entries.len(),
+        VirtualKeyCode::End => log.scroll = 0,
+        _ => {}
+    }
+    log.clamp_scroll(HISTORY_PAGE_SIZE);
+}
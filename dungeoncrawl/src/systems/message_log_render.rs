@@ -0,0 +1,44 @@
+use crate::prelude::*;
+use super::message_history::HISTORY_PAGE_SIZE;
+
+// 在平视显示区图层上绘制消息记录：平时在屏幕下方显示最新的几条消息，打开消息记录时显示全屏的历史记录
+#[system]
+pub fn message_log_render(#[resource] log: &MessageLog, #[resource] turn_state: &TurnState) {
+    let mut draw_batch = DrawBatch::new();
+    draw_batch.target(2);
+    let bottom = SCREEN_HEIGHT * 2 - 1;
+
+    if *turn_state == TurnState::MessageLog {
+        let width = SCREEN_WIDTH * 2;
+        draw_batch.draw_double_box(Rect::with_size(0, 0, width - 1, bottom), ColorPair::new(WHITE, BLACK));
+        draw_batch.print_color(
+            Point::new(2, 0),
+            format!(" Message log ({} messages) ", log.entries.len()),
+            ColorPair::new(YELLOW, BLACK),
+        );
+        // 从最新的消息往前数，跳过向上滚动的行数，最新的消息显示在最下面
+        let end = log.entries.len() - log.scroll;
+        let start = end.saturating_sub(HISTORY_PAGE_SIZE);
+        log.entries[start..end].iter().enumerate().for_each(|(n, entry)| {
+            draw_batch.print_color(Point::new(2, 2 + n as i32), &entry.text, ColorPair::new(entry.color, BLACK));
+        });
+        if start > 0 {
+            draw_batch.print_color(Point::new(width - 10, 1), "more ^", ColorPair::new(GRAY, BLACK));
+        }
+        if log.scroll > 0 {
+            draw_batch.print_color(Point::new(width - 10, bottom - 1), "more v", ColorPair::new(GRAY, BLACK));
+        }
+        draw_batch.print_color(
+            Point::new(2, bottom),
+            " Up/Down/PageUp/PageDown: scroll  Home/End: oldest/newest  Esc/L: close ",
+            ColorPair::new(WHITE, BLACK),
+        );
+    } else {
+        // 消息面板：最新的消息在最下面，越早的消息颜色越暗
+        log.entries.iter().rev().take(LOG_PANEL_LINES).enumerate().for_each(|(n, entry)| {
+            let color = if n == 0 { entry.color } else { entry.color * 0.7 };
+            draw_batch.print_color(Point::new(1, bottom - n as i32), &entry.text, ColorPair::new(color, BLACK));
+        });
+    }
+    draw_batch.submit(10400).expect("Batch error");
+}
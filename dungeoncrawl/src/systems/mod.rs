@@ -12,20 +12,58 @@ mod tooltips;
 mod combat;
 mod fov;
 mod use_items;
+mod equipment;
+mod drop_items;
+mod inventory;
+mod inventory_render;
+mod targeting;
+mod targeting_render;
+mod experience;
+mod level_up;
+mod level_up_render;
+mod message_history;
+mod message_log_render;
+mod monster_ai;
+mod behaviours;
+mod traps;
+mod abilities;
+
+pub(crate) use player_input::ITEM_KEYS;
 
 
 pub fn build_input_scheduler() -> Schedule {
-    /* 等待输入阶段
-     * flush：立即执行已经在排队等待的变更指令
-     */
+    // 等待输入阶段
     Schedule::builder()
         .add_system(player_input::player_input_system())
         .add_system(fov::fov_system())
-        .flush()
-        .add_system(map_render::map_render_system())
-        .add_system(entity_render::entity_render_system())
-        .add_system(hud::hud_system())
-        .add_system(tooltips::tooltips_system())
+        .build()
+}
+
+pub fn build_inventory_scheduler() -> Schedule {
+    // 物品栏界面阶段
+    Schedule::builder()
+        .add_system(inventory::inventory_system())
+        .build()
+}
+
+pub fn build_targeting_scheduler() -> Schedule {
+    // 瞄准阶段
+    Schedule::builder()
+        .add_system(targeting::targeting_system())
+        .build()
+}
+
+pub fn build_level_up_scheduler() -> Schedule {
+    // 升级阶段
+    Schedule::builder()
+        .add_system(level_up::level_up_system())
+        .build()
+}
+
+pub fn build_message_log_scheduler() -> Schedule {
+    // 消息记录阶段
+    Schedule::builder()
+        .add_system(message_history::message_history_system())
         .build()
 }
 
@@ -35,15 +73,19 @@ pub fn build_player_scheduler() -> Schedule {
      */
     Schedule::builder()
         .add_system(use_items::use_items_system())
+        .add_system(equipment::equipment_system())
+        .add_system(drop_items::drop_items_system())
+        .add_system(traps::traps_system())
         .add_system(combat::combat_system())
         .flush()
+        .add_system(combat::combat_results_system())
+        .flush()
+        .add_system(experience::experience_system())
         .add_system(movement::movement_system())
         .flush()
         .add_system(fov::fov_system())
         .flush()
-        .add_system(map_render::map_render_system())
-        .add_system(entity_render::entity_render_system())
-        .add_system(hud::hud_system())
+        .add_system(combat::clear_combat_events_system())
         .add_system(end_turn::end_turn_system())
         .build()
 }
@@ -53,19 +95,41 @@ pub fn build_monster_scheduler() -> Schedule {
      * flush：立即执行已经在排队等待的变更指令
      */
     Schedule::builder()
+        .add_system(monster_ai::monster_ai_system())
+        .flush()
+        .add_system(abilities::abilities_system())
+        .flush()
         .add_system(random_move::random_move_system())
         .add_system(chasing::chasing_system())
+        .add_system(behaviours::behaviours_system())
         .flush()
         .add_system(use_items::use_items_system())
         .add_system(combat::combat_system())
         .flush()
+        .add_system(combat::combat_results_system())
+        .flush()
+        .add_system(experience::experience_system())
         .add_system(movement::movement_system())
         .flush()
         .add_system(fov::fov_system())
         .flush()
+        .add_system(combat::clear_combat_events_system())
+        .add_system(end_turn::end_turn_system())
+        .build()
+}
+
+pub fn build_render_scheduler() -> Schedule {
+    /* 渲染阶段：在游戏逻辑之后执行，只负责绘制，不修改游戏世界
+     * 无界面模式下不执行这个计划
+     */
+    Schedule::builder()
         .add_system(map_render::map_render_system())
         .add_system(entity_render::entity_render_system())
         .add_system(hud::hud_system())
-        .add_system(end_turn::end_turn_system())
+        .add_system(tooltips::tooltips_system())
+        .add_system(inventory_render::inventory_render_system())
+        .add_system(targeting_render::targeting_render_system())
+        .add_system(level_up_render::level_up_render_system())
+        .add_system(message_log_render::message_log_render_system())
         .build()
-}
\ No newline at end of file
+}
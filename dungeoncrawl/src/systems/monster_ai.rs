@@ -0,0 +1,106 @@
+use crate::prelude::*;
+
+/*
+ * 怪物的行为状态：在怪物行动之前更新逃跑、被呼叫和巡逻路点等状态，
+ * 移动和攻击由behaviours、chasing和random_move系统根据这些状态决定。
+ */
+#[system]
+#[read_component(Point)]
+#[read_component(Player)]
+#[read_component(Enemy)]
+#[read_component(Health)]
+#[read_component(FieldOfView)]
+#[read_component(Name)]
+#[read_component(FleesWhenHurt)]
+#[read_component(Fleeing)]
+#[read_component(AlertsAllies)]
+#[read_component(Alerted)]
+#[read_component(Guarding)]
+#[read_component(Patrolling)]
+#[read_component(Enrages)]
+#[read_component(Enraged)]
+pub fn monster_ai(
+    ecs: &SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] rng: &mut RandomNumberGenerator,
+    #[resource] log: &mut MessageLog,
+) {
+    let (player_pos, player_fov) = <(&Point, &FieldOfView)>::query()
+        .filter(component::<Player>())
+        .iter(ecs)
+        .map(|(pos, fov)| (*pos, fov.visible_tiles.clone()))
+        .next()
+        .unwrap();
+    // 怪物能否看到玩家角色
+    let sees_player = |fov: &FieldOfView| fov.visible_tiles.contains(&player_pos);
+
+    // 生命值低于设定的百分比时开始逃跑，恢复之后不再逃跑
+    <(Entity, &Point, &Health, &FleesWhenHurt, Option<&Fleeing>)>::query()
+        .iter(ecs)
+        .for_each(|(entity, pos, health, flees, fleeing)| {
+            let hurt = health.current * 100 < health.max * flees.percent;
+            if hurt && fleeing.is_none() {
+                commands.add_component(*entity, Fleeing);
+                if player_fov.contains(pos) {
+                    log.add(format!("{} turns to flee!", name_of(ecs, *entity)), YELLOW);
+                }
+            } else if !hurt && fleeing.is_some() {
+                commands.remove_component::<Fleeing>(*entity);
+            }
+        });
+
+    // 生命值低于设定的百分比时狂暴，之后一直保持狂暴
+    <(Entity, &Point, &Health, &Enrages)>::query()
+        .filter(!component::<Enraged>())
+        .iter(ecs)
+        .filter(|(_, _, health, enrages)| health.current * 100 < health.max * enrages.percent)
+        .for_each(|(entity, pos, _, _)| {
+            commands.add_component(*entity, Enraged);
+            if player_fov.contains(pos) {
+                log.add(format!("{} flies into a rage!", name_of(ecs, *entity)), ORANGE);
+            }
+        });
+
+    // 被呼叫的怪物看到玩家角色时记住玩家角色的位置，到达之后仍然看不到玩家角色就放弃
+    <(Entity, &Point, &Alerted, &FieldOfView)>::query()
+        .iter(ecs)
+        .for_each(|(entity, pos, alerted, fov)| {
+            if sees_player(fov) {
+                commands.add_component(*entity, Alerted { target: player_pos });
+            } else if *pos == alerted.target {
+                commands.remove_component::<Alerted>(*entity);
+            }
+        });
+
+    // 看到玩家角色的怪物呼叫附近还没有看到玩家角色的同伴，守卫金库的怪物不离开岗位
+    let mut allies = <(Entity, &Point, &FieldOfView, Option<&Alerted>)>::query()
+        .filter(component::<Enemy>() & !component::<Guarding>());
+    <(Entity, &Point, &AlertsAllies, &FieldOfView)>::query()
+        .filter(!component::<Fleeing>())
+        .iter(ecs)
+        .filter(|(_, _, _, fov)| sees_player(fov))
+        .for_each(|(entity, pos, alerts, _)| {
+            let mut called = false;
+            allies.iter(ecs)
+                .filter(|(ally, ally_pos, fov, _)| *ally != entity
+                    && !sees_player(fov)
+                    && DistanceAlg::Pythagoras.distance2d(*pos, **ally_pos) <= alerts.radius as f32)
+                .for_each(|(ally, _, _, alerted)| {
+                    called |= alerted.is_none();
+                    commands.add_component(*ally, Alerted { target: player_pos });
+                });
+            if called && player_fov.contains(pos) {
+                log.add(format!("{} shouts for help!", name_of(ecs, *entity)), YELLOW);
+            }
+        });
+
+    // 巡逻的怪物到达路点之后随机选择另一个路点
+    <(Entity, &Point, &Patrolling)>::query()
+        .iter(ecs)
+        .filter(|(_, pos, patrol)| patrol.waypoint.is_none() || patrol.waypoint == Some(**pos))
+        .for_each(|(entity, pos, patrol)| {
+            if let Some(waypoint) = patrol.next_waypoint(rng, *pos) {
+                commands.add_component(*entity, Patrolling { waypoints: patrol.waypoints.clone(), waypoint: Some(waypoint) });
+            }
+        });
+}
@@ -1,11 +1,15 @@
 use crate::prelude::*;
 
+// 走进浅水额外消耗半次行动的能量
+const WADING_COST: i32 = Energy::ACTION_COST / 2;
+
 /*
  * system(for_each)：表示为每一个匹配到的实体运行一次这个系统函数。
  */
 #[system(for_each)]
 #[read_component(Player)]
 #[read_component(FieldOfView)]
+#[write_component(Energy)]
 pub fn movement(
     entity: &Entity,
     want_move: &WantsToMove,
@@ -14,9 +18,24 @@ pub fn movement(
     ecs: &mut SubWorld,
     commands: &mut CommandBuffer,
 ) {
-    if map.can_enter_tile(want_move.destination) {
+    let door = map.try_idx(want_move.destination).filter(|idx| map.tiles[*idx] == TileType::Door);
+    if let Some(idx) = door {
+        // 走向关着的门时把门打开，不移动；门后的图块可能进入任何实体的视野
+        map.tiles[idx] = TileType::OpenDoor;
+        <(Entity, &FieldOfView)>::query()
+            .iter(ecs)
+            .for_each(|(entity, fov)| commands.add_component(*entity, fov.clone_dirty()));
+    } else if map.can_enter_tile(want_move.destination) {
         // 一次性快速批量执行这些更新。
         commands.add_component(want_move.entity, want_move.destination);
+        // 涉水的实体下一次行动要多攒一些能量
+        if map.tiles[map.point2d_to_index(want_move.destination)] == TileType::Water {
+            if let Ok(mut entry) = ecs.entry_mut(want_move.entity) {
+                if let Ok(energy) = entry.get_component_mut::<Energy>() {
+                    energy.0 -= WADING_COST;
+                }
+            }
+        }
         // 用来表示这个实体在当前这个子世界中是否有效，只有在系统声明中read_component或write_component之后，这个实体才有效。
         if let Ok(entry) = ecs.entry_ref(want_move.entity) {
             if let Ok(fov) = entry.get_component::<FieldOfView>() {
@@ -24,11 +43,19 @@ pub fn movement(
 
                 if entry.get_component::<Player>().is_ok() {
                     // 得到这个实体之后，更新与玩家角色相关摄像机的信息。
-                    camera.on_player_move(want_move.destination);
+                    camera.on_player_move(want_move.destination, map);
+
+                    // 走上楼梯时在回合结束后换层，站在楼梯上原地等待不会换层
+                    match map.tiles[map.point2d_to_index(want_move.destination)] {
+                        TileType::Exit => { commands.push(((), WantsToTakeStairs { down: true })); }
+                        TileType::UpStairs => { commands.push(((), WantsToTakeStairs { down: false })); }
+                        _ => {}
+                    }
 
                     // 对于处在玩家可见区域的每一个图块，将revealed_tiles都设置为true
                     fov.visible_tiles.iter().for_each(|pos| {
-                        map.revealed_tiles[map_idx(pos.x, pos.y)] = true;
+                        let idx = map.idx(pos.x, pos.y);
+                        map.revealed_tiles[idx] = true;
                     });
                 }
             }
@@ -36,4 +63,4 @@ pub fn movement(
     }
     // 删除处理过的信息，否则这些信息在下一次运行时还会被处理一次。
     commands.remove(*entity);
-}
\ No newline at end of file
+}
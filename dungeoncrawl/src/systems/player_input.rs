@@ -1,27 +1,60 @@
 use crate::prelude::*;
+use super::random_move::random_step;
 
 /* system：将函数名变为player_input_system，并使用一些Legion构建系统时所需要的额外代码将这个函数包装起来。
  * write_component：为Point组件类型申请可写入权限。
  * read_component：为Player组件类型申请可读权限。
  */
 #[system]
+#[allow(clippy::too_many_arguments)]
 #[read_component(Point)]
 #[read_component(Player)]
 #[read_component(Enemy)]
 #[write_component(Health)]
 #[read_component(Item)]
 #[read_component(Carried)]
-#[read_component(Weapon)]
+#[read_component(Equippable)]
+#[read_component(Equipped)]
+#[read_component(Ranged)]
+#[read_component(FieldOfView)]
+#[read_component(Confused)]
+#[read_component(Name)]
 pub fn player_input(
     ecs: &mut SubWorld,
     commands: &mut CommandBuffer,
     #[resource] key: &Option<VirtualKeyCode>,
+    #[resource] map: &Map,
+    #[resource] targeting: &mut Targeting,
+    #[resource] rng: &mut RandomNumberGenerator,
+    #[resource] log: &mut MessageLog,
     #[resource] turn_state: &mut TurnState,
 ) {
     let mut players = <(Entity, &Point)>::query().filter(component::<Player>());
     let mut enemies = <(Entity, &Point)>::query().filter(component::<Enemy>());
 
     if let Some(key) = *key {
+        // 打开物品栏，不消耗回合
+        if key == VirtualKeyCode::I {
+            *turn_state = TurnState::Inventory;
+            return;
+        }
+        // 打开完整的消息记录，不消耗回合
+        if key == VirtualKeyCode::L {
+            *turn_state = TurnState::MessageLog;
+            return;
+        }
+        // 使用物品，远程物品先进入瞄准模式
+        if let Some(n) = ITEM_KEYS.iter().position(|k| *k == key) {
+            *turn_state = use_item(n, ecs, commands, map, targeting);
+            return;
+        }
+        // 用装备的远程武器射击，没有远程武器时什么也不做
+        if key == VirtualKeyCode::F {
+            if let Some(weapon) = ranged_weapon(ecs) {
+                *turn_state = targeting.begin(ecs, map, weapon);
+            }
+            return;
+        }
         let delta = match key {
             VirtualKeyCode::Left => Point::new(-1, 0),
             VirtualKeyCode::Right => Point::new(1, 0),
@@ -29,45 +62,42 @@ pub fn player_input(
             VirtualKeyCode::Down => Point::new(0, 1),
             VirtualKeyCode::G => {
                 // 获取玩家角色和玩家所在的位置
-                let (player, player_pos) = players.iter(ecs).find_map(|(entity, pos)| Some((*entity, *pos))).unwrap();
+                let (player, player_pos) = players.iter(ecs).map(|(entity, pos)| (*entity, *pos)).next().unwrap();
                 let mut items = <(Entity, &Item, &Point)>::query();
                 // 捡起物品，将物品从Point组件中移除，添加到Carried组件中
                 items.iter(ecs).filter(|(_entity, _item, &item_pos)| item_pos == player_pos)
                     .for_each(|(entity, _item, _item_pos)| {
+                        log.add(format!("You pick up {}.", name_of(ecs, *entity)), WHITE);
                         commands.remove_component::<Point>(*entity);
                         commands.add_component(*entity, Carried(player));
-
-                        if let Ok(e) = ecs.entry_ref(*entity) {
-                            // 检查物品是不是武器
-                            if e.get_component::<Weapon>().is_ok() {
-                                <(Entity, &Carried, &Weapon)>::query()
-                                    .iter(ecs)
-                                    .filter(|(_, c, _)| c.0 == player)
-                                    .for_each(|(e, _c, _w)| {
-                                        // 如果是武器，则从游戏世界中移除
-                                        commands.remove(*e);
-                                    })
-                            }
-                        }
                     });
                 Point::new(0, 0)
             },
-            // 使用物品
-            VirtualKeyCode::Key1 => use_item(0, ecs, commands),
-            VirtualKeyCode::Key2 => use_item(1, ecs, commands),
-            VirtualKeyCode::Key3 => use_item(2, ecs, commands),
-            VirtualKeyCode::Key4 => use_item(3, ecs, commands),
-            VirtualKeyCode::Key5 => use_item(4, ecs, commands),
-            VirtualKeyCode::Key6 => use_item(5, ecs, commands),
-            VirtualKeyCode::Key7 => use_item(6, ecs, commands),
-            VirtualKeyCode::Key8 => use_item(7, ecs, commands),
-            VirtualKeyCode::Key9 => use_item(8, ecs, commands),
+            // 搜索附近隐藏的陷阱和暗门，消耗一个回合
+            VirtualKeyCode::S => {
+                let player = players.iter(ecs).map(|(entity, _)| *entity).next().unwrap();
+                commands.push(((), WantsToSearch { entity: player }));
+                Point::new(0, 0)
+            },
+            // 卸下装备
+            VirtualKeyCode::F1 => unequip(EquipmentSlot::MainHand, ecs, commands),
+            VirtualKeyCode::F2 => unequip(EquipmentSlot::OffHand, ecs, commands),
+            VirtualKeyCode::F3 => unequip(EquipmentSlot::Body, ecs, commands),
+            VirtualKeyCode::F4 => unequip(EquipmentSlot::Head, ecs, commands),
+            VirtualKeyCode::F5 => unequip(EquipmentSlot::Ring, ecs, commands),
             _ => Point::new(0, 0),
         };
+        // 陷入混乱时随机选择移动的方向
+        let confused = <&Confused>::query().filter(component::<Player>()).iter(ecs).next().is_some();
+        let delta = if confused && (delta.x != 0 || delta.y != 0) {
+            random_step(rng)
+        } else {
+            delta
+        };
         // 获取目标点和玩家角色实体
         let (player_entity, destination) = players
             .iter(ecs)
-            .find_map(|(entity, pos)| Some((*entity, *pos + delta)))
+            .map(|(entity, pos)| (*entity, *pos + delta)).next()
             .unwrap();
 
         // 如果位置有移动
@@ -86,6 +116,7 @@ pub fn player_input(
                     commands.push(((), WantsToAttack {
                         attacker: player_entity,
                         victim: *entity,
+                        weapon: None,
                     }));
                 });
             // 如果没有碰到任何东西
@@ -101,10 +132,17 @@ pub fn player_input(
     }
 }
 
-fn use_item(n: usize, ecs: &mut SubWorld, commands: &mut CommandBuffer) -> Point {
+// 数字键1~9，对应物品列表中的前9个物品，无界面模式的自动玩家也使用这张表
+pub(crate) const ITEM_KEYS: [VirtualKeyCode; 9] = [
+    VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4, VirtualKeyCode::Key5, VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+];
+
+fn use_item(n: usize, ecs: &SubWorld, commands: &mut CommandBuffer, map: &Map, targeting: &mut Targeting) -> TurnState {
     // 获得玩家角色实体
     let player_entity = <(Entity, &Player)>::query().iter(ecs)
-        .find_map(|(entity, _player)| Some(*entity))
+        .map(|(entity, _player)| *entity).next()
         .unwrap();
 
     // 过滤掉枚举计数值不等于n的物品，并获取第1个物品实体
@@ -112,16 +150,58 @@ fn use_item(n: usize, ecs: &mut SubWorld, commands: &mut CommandBuffer) -> Point
         .filter(|(_, _, carried)| carried.0 == player_entity)
         .enumerate()
         .filter(|(item_count, (_, _, _))| *item_count == n)
-        .find_map(|(_, (item_entity, _, _))| Some(*item_entity));
+        .map(|(_, (item_entity, _, _))| *item_entity).next();
 
+    match item_entity {
+        Some(item_entity) => activate_item(player_entity, item_entity, ecs, commands, map, targeting),
+        None => TurnState::PlayerTurn,
+    }
+}
+
+// 玩家角色装备的远程武器
+fn ranged_weapon(ecs: &SubWorld) -> Option<Entity> {
+    let player_entity = <(Entity, &Player)>::query().iter(ecs)
+        .map(|(entity, _player)| *entity).next()
+        .unwrap();
+    <(Entity, &Equipped, &Ranged)>::query().iter(ecs)
+        .find(|(_, equipped, _)| equipped.owner == player_entity)
+        .map(|(entity, _, _)| *entity)
+}
 
-    if let Some(item_entity) = item_entity {
-        // 如果物品存在
+/*
+ * 使用物品，返回接下来的游戏模态：如果是装备，则装备到对应的栏位上；
+ * 如果是投掷物品或卷轴，则进入瞄准模式选择目标；否则直接使用物品。
+ */
+pub fn activate_item(player_entity: Entity,
+                     item_entity: Entity,
+                     ecs: &SubWorld,
+                     commands: &mut CommandBuffer,
+                     map: &Map,
+                     targeting: &mut Targeting) -> TurnState {
+    let (equippable, ranged) = ecs.entry_ref(item_entity)
+        .map(|e| (e.get_component::<Equippable>().is_ok(), e.get_component::<Ranged>().is_ok()))
+        .unwrap_or((false, false));
+    if equippable {
+        commands.push(((), WantsToEquip {
+            entity: player_entity,
+            item: item_entity
+        }));
+    } else if ranged {
+        return targeting.begin(ecs, map, item_entity);
+    } else {
         commands.push(((), ActivateItem{
-                used_by: player_entity,
-                item: item_entity
-            }));
+            used_by: player_entity,
+            item: item_entity,
+            target: None,
+        }));
     }
+    TurnState::PlayerTurn
+}
 
+fn unequip(slot: EquipmentSlot, ecs: &mut SubWorld, commands: &mut CommandBuffer) -> Point {
+    let player_entity = <(Entity, &Player)>::query().iter(ecs)
+        .map(|(entity, _player)| *entity).next()
+        .unwrap();
+    commands.push(((), WantsToUnequip { entity: player_entity, slot }));
     Point::zero()
 }
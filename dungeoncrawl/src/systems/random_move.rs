@@ -3,40 +3,60 @@ use crate::prelude::*;
 #[system]
 #[read_component(Point)]
 #[read_component(MovingRandomly)]
+#[read_component(Confused)]
 #[read_component(Health)]
 #[read_component(Player)]
-pub fn random_move(ecs: &SubWorld, commands: &mut CommandBuffer) {
-    let mut movers = <(Entity, &Point, &MovingRandomly)>::query();
+#[read_component(Enemy)]
+#[read_component(Alerted)]
+#[read_component(Fleeing)]
+#[read_component(Energy)]
+pub fn random_move(
+    ecs: &SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] rng: &mut RandomNumberGenerator,
+) {
+    // 随机移动的怪物（被同伴呼叫或者正在逃跑时除外），以及陷入混乱的怪物
+    let mut movers = <(Entity, &Point, Option<&Energy>)>::query()
+        .filter(component::<Enemy>() & (
+            (component::<MovingRandomly>() & !component::<Alerted>() & !component::<Fleeing>())
+                | component::<Confused>()));
     let mut positions = <(Entity, &Point, &Health)>::query();
-    movers.iter(ecs).for_each(|(entity, pos, _)| {
-        let mut rng = RandomNumberGenerator::new();
-        let destination = match rng.range(0, 4) {
-            0 => Point::new(-1, 0),
-            1 => Point::new(1, 0),
-            2 => Point::new(0, -1),
-            _ => Point::new(0, 1),
-        } + *pos;
+    movers.iter(ecs)
+        .filter(|(_, _, energy)| can_act(*energy))
+        .for_each(|(entity, pos, _)| {
+            let destination = random_step(rng) + *pos;
 
-        let mut attacked = false;
-        // 先查询实体，使用过滤器筛选出位于目标图块之上的实体
-        positions
-            .iter(ecs)
-            .filter(|(_, target_pos, _)| **target_pos == destination)
-            .for_each(|(victim, _, _)| {
-                // 如果目标位置上有实体，检查这个是替是否有Player组件
-                if ecs.entry_ref(*victim)
-                    .unwrap().get_component::<Player>().is_ok()
-                {
-                    // 发出攻击命令
-                    commands.push(((), WantsToAttack {
-                        attacker: *entity,
-                        victim: *victim,
-                    }));
-                }
-                attacked = true;
-            });
-        if !attacked {
-            commands.push(((), WantsToMove { entity: *entity, destination }));
-        }
-    });
-}
\ No newline at end of file
+            let mut attacked = false;
+            // 先查询实体，使用过滤器筛选出位于目标图块之上的实体
+            positions
+                .iter(ecs)
+                .filter(|(_, target_pos, _)| **target_pos == destination)
+                .for_each(|(victim, _, _)| {
+                    // 如果目标位置上有实体，检查这个是替是否有Player组件
+                    if ecs.entry_ref(*victim)
+                        .unwrap().get_component::<Player>().is_ok()
+                    {
+                        // 发出攻击命令
+                        commands.push(((), WantsToAttack {
+                            attacker: *entity,
+                            victim: *victim,
+                            weapon: None,
+                        }));
+                    }
+                    attacked = true;
+                });
+            if !attacked {
+                commands.push(((), WantsToMove { entity: *entity, destination }));
+            }
+        });
+}
+
+// 随机选择上下左右中的一个方向
+pub fn random_step(rng: &mut RandomNumberGenerator) -> Point {
+    match rng.range(0, 4) {
+        0 => Point::new(-1, 0),
+        1 => Point::new(1, 0),
+        2 => Point::new(0, -1),
+        _ => Point::new(0, 1),
+    }
+}
@@ -0,0 +1,139 @@
+use crate::prelude::*;
+
+/*
+ * 瞄准模式：Tab键或右方向键切换到下一个目标，左方向键切换到上一个目标，也可以用鼠标指向一个图块，
+ * 回车键或F键确认后发起远程攻击，Esc键取消瞄准，不消耗回合。
+ */
+#[system]
+#[read_component(Player)]
+#[read_component(Point)]
+#[read_component(Enemy)]
+#[read_component(FieldOfView)]
+#[read_component(ProvidesAreaDamage)]
+pub fn targeting(
+    ecs: &SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] key: &Option<VirtualKeyCode>,
+    #[resource] map: &Map,
+    #[resource] targeting: &mut Targeting,
+    #[resource] turn_state: &mut TurnState,
+) {
+    let Some(key) = *key else {
+        return;
+    };
+    match key {
+        VirtualKeyCode::Escape => *turn_state = TurnState::AwaitingInput,
+        VirtualKeyCode::Tab | VirtualKeyCode::Right => targeting.cycle(ecs, map, 1),
+        VirtualKeyCode::Left => targeting.cycle(ecs, map, -1),
+        VirtualKeyCode::Return | VirtualKeyCode::F => {
+            let (player, player_pos, fov) = <(Entity, &Point, &FieldOfView)>::query()
+                .filter(component::<Player>())
+                .iter(ecs)
+                .map(|(entity, pos, fov)| (*entity, *pos, fov))
+                .next()
+                .unwrap();
+            let in_sight = fov.visible_tiles.contains(&targeting.cursor)
+                && targeting.can_hit(map, player_pos, targeting.cursor);
+            // 范围伤害物品可以瞄准任何看得见的图块，不需要图块上有怪物
+            let area = targeting.item
+                .and_then(|item| ecs.entry_ref(item).ok())
+                .is_some_and(|e| e.get_component::<ProvidesAreaDamage>().is_ok());
+            if area {
+                if let Some(item) = targeting.item.filter(|_| in_sight) {
+                    commands.push(((), ActivateItem {
+                        used_by: player,
+                        item,
+                        target: Some(targeting.cursor),
+                    }));
+                    *turn_state = TurnState::PlayerTurn;
+                }
+                return;
+            }
+            // 只能攻击看得见、在射程之内并且没有被遮挡的怪物
+            let victim = <(Entity, &Point)>::query()
+                .filter(component::<Enemy>())
+                .iter(ecs)
+                .find(|(_, pos)| **pos == targeting.cursor)
+                .map(|(entity, _)| *entity)
+                .filter(|_| in_sight);
+            if let (Some(victim), Some(item)) = (victim, targeting.item) {
+                commands.push(((), WantsToAttack {
+                    attacker: player,
+                    victim,
+                    weapon: Some(item),
+                }));
+                *turn_state = TurnState::PlayerTurn;
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // 在玩家角色旁边的空地上放一只怪物，返回怪物实体
+    fn spawn_goblin(game: &mut Game, pos: Point) -> Entity {
+        game.ecs.push((Enemy, pos, Health { current: 10, max: 10 }, Name("Goblin".to_string())))
+    }
+
+    fn player(game: &Game) -> (Entity, Point) {
+        <(Entity, &Point)>::query().filter(component::<Player>()).iter(&game.ecs)
+            .map(|(entity, pos)| (*entity, *pos)).next().unwrap()
+    }
+
+    // 在玩家角色的视野中，从玩家角色出发沿着空地走出distance格的位置
+    fn open_tile(game: &Game, distance: i32) -> Option<Point> {
+        let (_, pos) = player(game);
+        let map = game.resources.get::<Map>().unwrap();
+        [Point::new(1, 0), Point::new(-1, 0), Point::new(0, 1), Point::new(0, -1)].iter()
+            .find(|delta| (1..=distance).all(|n| map.tiles[map.point2d_to_index(pos + **delta * n)] == TileType::Floor))
+            .map(|delta| pos + *delta * distance)
+    }
+
+    #[test]
+    fn a_thrown_knife_hits_the_target_and_is_used_up() {
+        let mut game = Game::new(GameSeed(1));
+        let (player, _) = player(&game);
+        let target = open_tile(&game, 3).expect("no open tiles next to the player");
+        let goblin = spawn_goblin(&mut game, target);
+        let knife = game.ecs.push((Item, Name("Throwing Knife".to_string()), Damage(2), Ranged { range: 5 }, Carried(player)));
+        // 更新视野，让怪物出现在玩家角色的视野中
+        game.step(None);
+
+        game.step(Some(VirtualKeyCode::Key1));
+        assert_eq!(game.turn_state(), TurnState::Targeting);
+        assert_eq!(game.resources.get::<Targeting>().unwrap().cursor, target);
+        game.step(Some(VirtualKeyCode::Return));
+        assert_eq!(game.turn_state(), TurnState::PlayerTurn);
+        game.step(None);
+
+        assert_eq!(game.ecs.entry_ref(goblin).unwrap().get_component::<Health>().unwrap().current, 8);
+        assert!(game.ecs.entry_ref(knife).is_err());
+    }
+
+    #[test]
+    fn walls_block_the_line_of_sight() {
+        let mut map = Map::new(10, 10);
+        let wall = map.idx(5, 5);
+        map.tiles[wall] = TileType::Wall;
+        assert!(!trajectory(&map, Point::new(3, 5), Point::new(7, 5)).1);
+        let (line, clear) = trajectory(&map, Point::new(3, 4), Point::new(7, 4));
+        assert!(clear);
+        assert_eq!(line.first(), Some(&Point::new(3, 4)));
+        assert_eq!(line.last(), Some(&Point::new(7, 4)));
+    }
+
+    #[test]
+    fn cancelling_the_aim_does_not_use_a_turn() {
+        let mut game = Game::new(GameSeed(1));
+        let (player, _) = player(&game);
+        let knife = game.ecs.push((Item, Name("Throwing Knife".to_string()), Damage(2), Ranged { range: 5 }, Carried(player)));
+        game.step(Some(VirtualKeyCode::Key1));
+        game.step(Some(VirtualKeyCode::Escape));
+        assert_eq!(game.turn_state(), TurnState::AwaitingInput);
+        assert_eq!(game.resources.get::<GameStats>().unwrap().turns, 0);
+        assert!(game.ecs.entry_ref(knife).is_ok());
+    }
+}
@@ -0,0 +1,68 @@
+use crate::prelude::*;
+
+#[system]
+#[read_component(Player)]
+#[read_component(Point)]
+#[read_component(Name)]
+#[read_component(ProvidesAreaDamage)]
+pub fn targeting_render(
+    ecs: &SubWorld,
+    #[resource] map: &Map,
+    #[resource] camera: &Camera,
+    #[resource] targeting: &Targeting,
+    #[resource] turn_state: &TurnState,
+) {
+    if *turn_state != TurnState::Targeting {
+        return;
+    }
+    let player_pos = *<&Point>::query().filter(component::<Player>()).iter(ecs).next().unwrap();
+    let offset = Point::new(camera.left_x, camera.top_y);
+    let (line, _) = trajectory(map, player_pos, targeting.cursor);
+    // 可以命中时弹道显示为绿色，超出射程或被遮挡时显示为红色
+    let color = if targeting.can_hit(map, player_pos, targeting.cursor) {
+        RGB::named(DARK_GREEN)
+    } else {
+        RGB::named(DARK_RED)
+    };
+
+    // 在地图图层上用背景色画出弹道和瞄准的图块
+    let mut draw_batch = DrawBatch::new();
+    draw_batch.target(0);
+    line.iter().skip(1).for_each(|pt| {
+        draw_batch.set_bg(*pt - offset, color);
+    });
+    // 范围伤害物品还要画出爆炸的范围
+    let radius = targeting.item
+        .and_then(|item| ecs.entry_ref(item).ok())
+        .and_then(|e| e.get_component::<ProvidesAreaDamage>().ok().map(|area| area.radius));
+    if let Some(radius) = radius {
+        for y in -radius..=radius {
+            for x in -radius..=radius {
+                let pt = targeting.cursor + Point::new(x, y);
+                if DistanceAlg::Pythagoras.distance2d(targeting.cursor, pt) <= radius as f32 {
+                    draw_batch.set_bg(pt - offset, RGB::named(DARK_ORANGE));
+                }
+            }
+        }
+    }
+    draw_batch.set_bg(targeting.cursor - offset, RGB::named(GOLD));
+    draw_batch.submit(1000).expect("Batch error");
+
+    let name = targeting.item
+        .and_then(|item| ecs.entry_ref(item).ok())
+        .and_then(|e| e.get_component::<Name>().ok().map(|name| name.0.clone()))
+        .unwrap_or_default();
+    let mut draw_batch = DrawBatch::new();
+    draw_batch.target(2);
+    draw_batch.print_color_centered(
+        SCREEN_HEIGHT * 2 - 2,
+        format!(" Aiming {} (range {}) ", name, targeting.range),
+        ColorPair::new(YELLOW, BLACK),
+    );
+    draw_batch.print_color_centered(
+        SCREEN_HEIGHT * 2 - 1,
+        " Tab/Left/Right or mouse: choose a target  Enter/F/click: fire  Esc: cancel ",
+        ColorPair::new(WHITE, BLACK),
+    );
+    draw_batch.submit(10300).expect("Batch error");
+}
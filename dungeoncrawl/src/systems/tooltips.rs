@@ -18,10 +18,10 @@ pub fn tooltips(
     let mut draw_batch = DrawBatch::new();
     draw_batch.target(2);
     // 获取玩家视野
-    let player_fov = fov.iter(ecs).nth(0).unwrap();
+    let player_fov = fov.iter(ecs).next().unwrap();
     positions.iter(ecs)
         .filter(|(_, pos, _)|
-            **pos == map_pos && player_fov.visible_tiles.contains(&pos)
+            **pos == map_pos && player_fov.visible_tiles.contains(pos)
         )
         .for_each(|(entity, _, name)| {
             // 由于悬浮提示所在的图层是实体图层的4倍大，需要乘以4
@@ -0,0 +1,116 @@
+use crate::prelude::*;
+use super::use_items::random_empty_floor;
+
+// 搜索的范围
+const SEARCH_RADIUS: f32 = 3.0;
+// 搜索时发现范围之内每一个隐藏图块的概率（百分比）
+const SEARCH_CHANCE: i32 = 60;
+
+/*
+ * 陷阱和暗门：搜索附近隐藏的陷阱和暗门，玩家角色走进陷阱时触发陷阱。
+ * 怪物熟悉地下城里的机关，不会触发陷阱。
+ */
+#[system]
+#[read_component(WantsToSearch)]
+#[read_component(WantsToMove)]
+#[read_component(Point)]
+#[read_component(Player)]
+#[write_component(Health)]
+pub fn traps(ecs: &mut SubWorld,
+             commands: &mut CommandBuffer,
+             #[resource] map: &mut Map,
+             #[resource] rng: &mut RandomNumberGenerator,
+             #[resource] log: &mut MessageLog) {
+    // 搜索：范围之内的每一个隐藏图块都有一定的概率被发现
+    let searches: Vec<(Entity, Entity)> = <(Entity, &WantsToSearch)>::query()
+        .iter(ecs)
+        .map(|(message, search)| (*message, search.entity))
+        .collect();
+    searches.iter().for_each(|(message, searcher)| {
+        if let Some(pos) = ecs.entry_ref(*searcher).ok().and_then(|e| e.get_component::<Point>().ok().copied()) {
+            let mut found = false;
+            for idx in 0..map.tiles.len() {
+                let tile = map.tiles[idx];
+                if !tile.is_hidden()
+                    || DistanceAlg::Pythagoras.distance2d(pos, map.index_to_point2d(idx)) > SEARCH_RADIUS
+                    || rng.range(0, 100) >= SEARCH_CHANCE {
+                    continue;
+                }
+                map.tiles[idx] = tile.revealed();
+                map.revealed_tiles[idx] = true;
+                found = true;
+                if tile.is_trap() {
+                    log.add("You find a hidden trap!", CYAN);
+                } else {
+                    log.add("You find a secret door!", CYAN);
+                }
+            }
+            if !found {
+                log.add("You search but find nothing.", GRAY);
+            }
+        }
+        commands.remove(*message);
+    });
+
+    // 玩家角色走进陷阱：隐藏的陷阱显露出来，然后造成伤害或者把玩家角色传送走
+    let Some(player) = <Entity>::query().filter(component::<Player>()).iter(ecs).copied().next() else {
+        return;
+    };
+    let moves: Vec<(Entity, Point)> = <(Entity, &WantsToMove)>::query()
+        .iter(ecs)
+        .filter(|(_, want_move)| want_move.entity == player)
+        .map(|(message, want_move)| (*message, want_move.destination))
+        .collect();
+    moves.iter().for_each(|(message, destination)| {
+        let Some(idx) = map.try_idx(*destination).filter(|idx| map.tiles[*idx].is_trap()) else {
+            return;
+        };
+        let trap = map.tiles[idx].revealed();
+        map.tiles[idx] = trap;
+        map.revealed_tiles[idx] = true;
+        match trap {
+            TileType::SpikeTrap => {
+                let damage = rng.roll_dice(1, 4);
+                if let Ok(health) = ecs.entry_mut(player).unwrap().get_component_mut::<Health>() {
+                    health.current -= damage;
+                }
+                log.add(format!("You step on a spike trap and take {} damage.", damage), RED);
+            }
+            TileType::TeleportTrap => {
+                // 用传送的目的地代替原来的移动
+                if let Some(teleport) = random_empty_floor(ecs, map, rng) {
+                    commands.remove(*message);
+                    commands.push(((), WantsToMove { entity: player, destination: teleport }));
+                }
+                log.add("You step on a teleport trap!", MAGENTA);
+            }
+            _ => {}
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn stepping_on_a_hidden_spike_trap_reveals_it_and_hurts() {
+        let mut ecs = World::default();
+        let mut resources = Resources::default();
+        let mut map = Map::new(20, 20);
+        let trap = Point::new(6, 5);
+        let idx = map.point2d_to_index(trap);
+        map.tiles[idx] = TileType::HiddenSpikeTrap;
+        resources.insert(map);
+        resources.insert(RandomNumberGenerator::seeded(1));
+        resources.insert(MessageLog::default());
+        let player = ecs.push((Player { map_level: 0 }, Point::new(5, 5), Health { current: 10, max: 10 }));
+        ecs.push(((), WantsToMove { entity: player, destination: trap }));
+        let mut schedule = Schedule::builder().add_system(super::traps_system()).build();
+        schedule.execute(&mut ecs, &mut resources);
+
+        assert_eq!(resources.get::<Map>().unwrap().tiles[idx], TileType::SpikeTrap);
+        let health = ecs.entry_ref(player).unwrap().get_component::<Health>().unwrap().current;
+        assert!((6..10).contains(&health));
+    }
+}
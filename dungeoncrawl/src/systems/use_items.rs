@@ -1,12 +1,32 @@
 use crate::prelude::*;
+use super::combat::{roll_damage, slay_monster};
+
 #[system]
 #[read_component(ActivateItem)]
 #[read_component(ProvidesHealing)]
 #[write_component(Health)]
 #[read_component(ProvidesDungeonMap)]
+#[read_component(Player)]
+#[read_component(ProvidesStatus)]
+#[read_component(ProvidesFood)]
+#[write_component(Hunger)]
+#[read_component(ProvidesAreaDamage)]
+#[read_component(ProvidesChainDamage)]
+#[read_component(ProvidesTeleport)]
+#[read_component(Damage)]
+#[read_component(DamageDice)]
+#[read_component(Point)]
+#[read_component(Enemy)]
+#[read_component(FieldOfView)]
+#[read_component(ExperienceValue)]
+#[read_component(Name)]
+#[read_component(DeathMessage)]
 pub fn use_items(ecs: &mut SubWorld,
                  commands: &mut CommandBuffer,
-                 #[resource] map: &mut Map) {
+                 #[resource] map: &mut Map,
+                 #[resource] rng: &mut RandomNumberGenerator,
+                 #[resource] stats: &mut GameStats,
+                 #[resource] log: &mut MessageLog) {
     /*
      * Rust借用的硬性规定：
      * 1. 可以对一个变量进行任意多次的不可变借用。
@@ -14,20 +34,86 @@ pub fn use_items(ecs: &mut SubWorld,
      * 3. 不能同时以可变和不可变的形式借用一个变量。
      */
     let mut healing_to_apply = Vec::<(Entity, i32)>::new();
+    let mut food_to_apply = Vec::<(Entity, i32)>::new();
+    // 受到范围伤害和连锁伤害的实体、伤害值，以及使用者是玩家角色时的玩家角色实体
+    let mut damage_to_apply = Vec::<(Entity, i32, Option<Entity>)>::new();
 
     <(Entity, &ActivateItem)>::query().iter(ecs)
         .for_each(|(entity, activate)| {
+            // 统计玩家角色使用的物品数量
+            let used_by_player = ecs.entry_ref(activate.used_by)
+                .map(|e| e.get_component::<Player>().is_ok())
+                .unwrap_or(false);
+            if used_by_player {
+                stats.items_used += 1;
+            }
+            let player = Some(activate.used_by).filter(|_| used_by_player);
             // 获取物品实体
             let item = ecs.entry_ref(activate.item);
             if let Ok(item) = item {
+                let user = name_of(ecs, activate.used_by);
+                let action = if item.get_component::<ProvidesFood>().is_ok() {
+                    verb(&user, "eat", "eats")
+                } else {
+                    verb(&user, "use", "uses")
+                };
+                log.add(format!("{} {} {}.", user, action, name_of(ecs, activate.item)), CYAN);
+
                 if let Ok(healing) = item.get_component::<ProvidesHealing>() {
                     // 如果是治疗药水，则加入到向量中
                     healing_to_apply.push((activate.used_by, healing.amount));
                 }
 
+                if let Ok(food) = item.get_component::<ProvidesFood>() {
+                    food_to_apply.push((activate.used_by, food.amount));
+                }
+
                 if let Ok(_mapper) = item.get_component::<ProvidesDungeonMap>() {
                     // 如果是地图，则把所有地块都展示出来
                     map.revealed_tiles.iter_mut().for_each(|t| *t = true);
+                    log.add("The layout of the level is revealed.", CYAN);
+                }
+
+                // 施加药水提供的状态效果，例如再生和加速
+                if let Ok(status) = item.get_component::<ProvidesStatus>() {
+                    status.0.iter().for_each(|effect| effect.apply(activate.used_by, commands));
+                }
+
+                let damage = roll_damage(&item, rng);
+                // 范围伤害：目标图块周围的所有生物都会受到伤害，包括使用者自己
+                if let (Ok(area), Some(target)) = (item.get_component::<ProvidesAreaDamage>(), activate.target) {
+                    <(Entity, &Point, &Health)>::query().iter(ecs)
+                        .filter(|(_, pos, _)| DistanceAlg::Pythagoras.distance2d(target, **pos) <= area.radius as f32)
+                        .for_each(|(victim, _, _)| damage_to_apply.push((*victim, damage, player)));
+                }
+
+                // 连锁伤害：击中使用者视野中最近的几个怪物
+                if let Ok(chain) = item.get_component::<ProvidesChainDamage>() {
+                    let user = ecs.entry_ref(activate.used_by).ok().and_then(|user| {
+                        let pos = *user.get_component::<Point>().ok()?;
+                        let fov = user.get_component::<FieldOfView>().ok()?.visible_tiles.clone();
+                        Some((pos, fov))
+                    });
+                    if let Some((user_pos, fov)) = user {
+                        let mut victims: Vec<(Entity, f32)> = <(Entity, &Point)>::query()
+                            .filter(component::<Enemy>())
+                            .iter(ecs)
+                            .filter(|(_, pos)| fov.contains(pos))
+                            .map(|(victim, pos)| (*victim, DistanceAlg::Pythagoras.distance2d(user_pos, *pos)))
+                            .collect();
+                        victims.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+                        victims.iter()
+                            .take(chain.targets as usize)
+                            .for_each(|(victim, _)| damage_to_apply.push((*victim, damage, player)));
+                    }
+                }
+
+                // 传送：随机选择一块没有生物的空地，由移动系统移动过去并更新视野和摄像机
+                if item.get_component::<ProvidesTeleport>().is_ok() {
+                    if let Some(destination) = random_empty_floor(ecs, map, rng) {
+                        commands.push(((), WantsToMove { entity: activate.used_by, destination }));
+                        log.add(format!("{} {} teleported.", user, verb(&user, "are", "is")), MAGENTA);
+                    }
                 }
             }
 
@@ -35,17 +121,119 @@ pub fn use_items(ecs: &mut SubWorld,
             commands.remove(*entity);
         });
 
+    // 造成伤害，消灭生命值耗尽的怪物，玩家角色的死亡在回合结束时处理
+    for (victim, damage, player) in damage_to_apply.iter() {
+        let victim_name = name_of(ecs, *victim);
+        let mut killed = false;
+        let mut is_player = false;
+        if let Ok(mut target) = ecs.entry_mut(*victim) {
+            is_player = target.get_component::<Player>().is_ok();
+            if let Ok(health) = target.get_component_mut::<Health>() {
+                let was_alive = health.current > 0;
+                health.current -= damage;
+                killed = was_alive && health.current < 1;
+                log.add(
+                    format!("{} {} {} damage.", victim_name, verb(&victim_name, "take", "takes"), damage),
+                    if is_player { RED } else { WHITE },
+                );
+            }
+        }
+        if killed && !is_player {
+            slay_monster(ecs, commands, log, stats, *victim, *player,
+                         format!("{} is destroyed!", victim_name), GOLD);
+        }
+    }
+
+    // 吃东西，恢复食物值
+    for (eater, amount) in food_to_apply.iter() {
+        if let Ok(mut target) = ecs.entry_mut(*eater) {
+            if let Ok(hunger) = target.get_component_mut::<Hunger>() {
+                hunger.food = i32::min(Hunger::MAX_FOOD, i32::max(0, hunger.food) + amount);
+            }
+        }
+    }
+
     // 执行疗伤
     for heal in healing_to_apply.iter() {
+        let healed = name_of(ecs, heal.0);
         if let Ok(mut target) = ecs.entry_mut(heal.0) {
             // 获取生命值
             if let Ok(health) = target.get_component_mut::<Health>() {
                 // 恢复生命
+                let before = health.current;
                 health.current = i32::min(
                     health.max,
                     health.current + heal.1,
                 );
+                log.add(
+                    format!("{} {} {} health.", healed, verb(&healed, "recover", "recovers"), health.current - before),
+                    GREEN,
+                );
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// 随机选择一块没有生物的空地，用于传送
+pub fn random_empty_floor(ecs: &SubWorld, map: &Map, rng: &mut RandomNumberGenerator) -> Option<Point> {
+    let occupied: Vec<Point> = <&Point>::query()
+        .filter(component::<Health>())
+        .iter(ecs)
+        .copied()
+        .collect();
+    let tiles: Vec<Point> = map.tiles.iter()
+        .enumerate()
+        .filter(|(_, t)| **t == TileType::Floor)
+        .map(|(idx, _)| map.index_to_point2d(idx))
+        .filter(|pt| !occupied.contains(pt))
+        .collect();
+    rng.random_slice_entry(&tiles).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    // 直接发出使用物品的消息，并运行玩家角色的回合
+    fn activate(game: &mut Game, item: Entity, target: Option<Point>) {
+        let player = *<Entity>::query().filter(component::<Player>()).iter(&game.ecs).next().unwrap();
+        game.ecs.push(((), ActivateItem { used_by: player, item, target }));
+        game.set_turn_state(TurnState::PlayerTurn);
+        game.step(None);
+    }
+
+    fn health(game: &Game, entity: Entity) -> i32 {
+        game.ecs.entry_ref(entity).unwrap().get_component::<Health>().unwrap().current
+    }
+
+    #[test]
+    fn a_fireball_hurts_everything_in_its_radius() {
+        let mut game = Game::new(GameSeed(4));
+        let center = Point::new(2, 2);
+        let near = game.ecs.push((Enemy, center + Point::new(1, 1), Health { current: 5, max: 5 }));
+        let far = game.ecs.push((Enemy, center + Point::new(3, 0), Health { current: 5, max: 5 }));
+        let scroll = game.ecs.push((Item, Damage(3), ProvidesAreaDamage { radius: 2 }));
+        activate(&mut game, scroll, Some(center));
+        assert_eq!(health(&game, near), 2);
+        assert_eq!(health(&game, far), 5);
+        assert!(game.ecs.entry_ref(scroll).is_err());
+    }
+
+    #[test]
+    fn teleporting_lands_on_an_empty_floor_tile() {
+        let mut game = Game::new(GameSeed(4));
+        let player = *<Entity>::query().filter(component::<Player>()).iter(&game.ecs).next().unwrap();
+        let start = *game.ecs.entry_ref(player).unwrap().get_component::<Point>().unwrap();
+        let scroll = game.ecs.push((Item, ProvidesTeleport));
+        activate(&mut game, scroll, None);
+        // 移动在下一个阶段执行，运行到回合结束
+        while !game.turn_state().awaits_input() {
+            game.step(None);
+        }
+        let pos = *game.ecs.entry_ref(player).unwrap().get_component::<Point>().unwrap();
+        assert_ne!(pos, start);
+        let map = game.resources.get::<Map>().unwrap();
+        assert_eq!(map.tiles[map.point2d_to_index(pos)], TileType::Floor);
+        assert!(game.ecs.entry_ref(player).unwrap().get_component::<FieldOfView>().unwrap().visible_tiles.contains(&pos));
+    }
+}
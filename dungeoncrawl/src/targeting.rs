@@ -0,0 +1,102 @@
+use crate::prelude::*;
+
+/*
+ * 瞄准模式的状态：使用远程武器或投掷物品时，玩家角色先选择一个目标，确认后才发起攻击。
+ * 只在瞄准时使用，不写入存档。
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Targeting {
+    // 发射的远程武器，或者投掷、使用的物品
+    pub item: Option<Entity>,
+    // 射程
+    pub range: i32,
+    // 瞄准的图块
+    pub cursor: Point,
+    // 按Tab键切换目标时，当前目标在目标列表中的序号
+    target: usize,
+}
+
+impl Default for Targeting {
+    fn default() -> Self {
+        Self {
+            item: None,
+            range: 0,
+            cursor: Point::zero(),
+            target: 0,
+        }
+    }
+}
+
+impl Targeting {
+    // 使用物品开始瞄准，默认瞄准射程内最近的怪物，没有怪物时瞄准玩家角色自己所在的位置
+    pub fn begin(&mut self, ecs: &impl EntityStore, map: &Map, item: Entity) -> TurnState {
+        let range = ecs.entry_ref(item)
+            .ok()
+            .and_then(|e| e.get_component::<Ranged>().ok().map(|r| r.range))
+            .unwrap_or(0);
+        let player_pos = *<&Point>::query().filter(component::<Player>()).iter(ecs).next().unwrap();
+        *self = Self {
+            item: Some(item),
+            range,
+            cursor: player_pos,
+            target: 0,
+        };
+        if let Some(target) = self.targets(ecs, map).first() {
+            self.cursor = *target;
+        }
+        TurnState::Targeting
+    }
+
+    // 玩家角色可以看到、在射程之内并且没有被遮挡的怪物位置，按距离从近到远排列
+    pub fn targets(&self, ecs: &impl EntityStore, map: &Map) -> Vec<Point> {
+        let (player_pos, fov) = <(&Point, &FieldOfView)>::query()
+            .filter(component::<Player>())
+            .iter(ecs)
+            .map(|(pos, fov)| (*pos, fov.visible_tiles.clone()))
+            .next()
+            .unwrap();
+        let mut targets: Vec<Point> = <&Point>::query()
+            .filter(component::<Enemy>())
+            .iter(ecs)
+            .filter(|pos| fov.contains(pos) && self.can_hit(map, player_pos, **pos))
+            .copied()
+            .collect();
+        targets.sort_by(|a, b| DistanceAlg::Pythagoras.distance2d(player_pos, *a)
+            .partial_cmp(&DistanceAlg::Pythagoras.distance2d(player_pos, *b))
+            .unwrap());
+        targets
+    }
+
+    // 按顺序切换到下一个（step为1）或上一个（step为-1）目标
+    pub fn cycle(&mut self, ecs: &impl EntityStore, map: &Map, step: i32) {
+        let targets = self.targets(ecs, map);
+        if targets.is_empty() {
+            return;
+        }
+        let n = targets.len() as i32;
+        self.target = (self.target as i32 + step).rem_euclid(n) as usize;
+        self.cursor = targets[self.target];
+    }
+
+    // 目标在射程之内，并且弹道上没有遮挡
+    pub fn can_hit(&self, map: &Map, from: Point, to: Point) -> bool {
+        in_line_of_fire(map, from, to, self.range)
+    }
+}
+
+// 目标在射程之内，并且弹道上没有遮挡；保持距离的怪物也用它判断能否射击玩家角色
+pub fn in_line_of_fire(map: &Map, from: Point, to: Point, range: i32) -> bool {
+    from != to
+        && DistanceAlg::Pythagoras.distance2d(from, to) <= range as f32
+        && trajectory(map, from, to).1
+}
+
+// 从起点到终点的弹道，以及弹道是否畅通：起点和终点之间的图块都不能遮挡视线
+pub fn trajectory(map: &Map, from: Point, to: Point) -> (Vec<Point>, bool) {
+    let line = line2d_bresenham(from, to);
+    let clear = line.iter()
+        .skip(1)
+        .take(line.len().saturating_sub(2))
+        .all(|pt| map.try_idx(*pt).is_some_and(|idx| !map.is_opaque(idx)));
+    (line, clear)
+}
@@ -1,7 +1,19 @@
-#[derive(Copy, Clone, Debug, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum TurnState {
+    // 主菜单，存在存档时可以选择继续游戏
+    MainMenu,
     // 等待输入
     AwaitingInput,
+    // 物品栏界面，关闭物品栏不消耗回合
+    Inventory,
+    // 瞄准模式，为远程武器或投掷物品选择目标，取消瞄准不消耗回合
+    Targeting,
+    // 升级界面，选择一项天赋，不消耗回合
+    LevelUp,
+    // 完整的消息记录，可以上下滚动，不消耗回合
+    MessageLog,
     // 玩家移动
     PlayerTurn,
     // 怪物移动
@@ -11,5 +23,17 @@ pub enum TurnState {
     // 游戏胜利
     Victory,
     // 到达下一层
-    NextLevel
-}
\ No newline at end of file
+    NextLevel,
+    // 回到上一层
+    PreviousLevel,
+    // 地图生成调试画面，回放生成地图的每一步
+    MapGenDebug
+}
+
+impl TurnState {
+    // 是否在等待玩家的按键，物品栏界面、瞄准模式、升级界面和消息记录中的按键同样由玩家输入
+    pub fn awaits_input(&self) -> bool {
+        matches!(self,
+            TurnState::AwaitingInput | TurnState::Inventory | TurnState::Targeting | TurnState::LevelUp | TurnState::MessageLog)
+    }
+}